@@ -1,10 +1,12 @@
-fn main() -> std::io::Result<()> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     use perf_event::events::Hardware;
-    use perf_event::{Builder, Group};
+    use perf_event::metrics::{Metric, MetricGroup};
 
-    let mut group = Group::new()?;
-    let cycles = group.add(&Builder::new(Hardware::CPU_CYCLES))?;
-    let insns = group.add(&Builder::new(Hardware::INSTRUCTIONS))?;
+    let mut cpi = Metric::new("CPI", "cycles / instructions", "cycles per insn")?;
+    cpi.event("cycles", Hardware::CPU_CYCLES)
+        .event("instructions", Hardware::INSTRUCTIONS);
+
+    let mut group = MetricGroup::new(vec![cpi, Metric::ipc()])?;
 
     let vec = (0..=51).collect::<Vec<_>>();
 
@@ -12,13 +14,9 @@ fn main() -> std::io::Result<()> {
     println!("{:?}", vec);
     group.disable()?;
 
-    let counts = group.read()?;
-    println!(
-        "cycles / instructions: {} / {} ({:.2} cpi)",
-        counts[&cycles],
-        counts[&insns],
-        (counts[&cycles] as f64 / counts[&insns] as f64)
-    );
+    for value in group.read()? {
+        println!("{}: {:.2}", value.name(), value);
+    }
 
     Ok(())
 }
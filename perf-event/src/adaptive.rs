@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+use crate::Sampler;
+
+used_in_docs!(Sampler);
+
+/// State for adjusting the sampling period of a [`Sampler`] so that it
+/// produces records at a roughly constant rate.
+///
+/// A fixed sampling period will produce records at a rate proportional to how
+/// often the underlying event occurs. For long running services this may
+/// result in either far too few samples when the service is idle or far too
+/// many when it is busy. The kernel's own frequency mode (see
+/// [`Builder::sample_frequency`]) solves this too, but only adjusts the
+/// period on a per-tick basis and targets a rate of samples per second of
+/// _CPU time_ instead of wall time.
+///
+/// `AdaptivePeriod` instead measures how quickly the counter is increasing in
+/// wall-clock time each time [`Sampler::adapt_period`] is called, and then
+/// picks the period that would have produced the target record rate over
+/// that interval. The period is always kept within the configured
+/// minimum and maximum bounds.
+///
+/// # Example
+/// ```
+/// use perf_event::events::Software;
+/// use perf_event::{AdaptivePeriod, Builder};
+///
+/// let mut sampler = Builder::new(Software::CPU_CLOCK)
+///     .sample_period(1_000_000)
+///     .build()?
+///     .sampled(8192)?;
+/// let mut period = AdaptivePeriod::new(100.0);
+/// period.min_period(10_000);
+///
+/// sampler.enable()?;
+/// for _ in 0..3 {
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+///     while let Some(_record) = sampler.next_record() {
+///         // ...
+///     }
+///
+///     sampler.adapt_period(&mut period)?;
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Builder::sample_frequency`]: crate::Builder::sample_frequency
+#[derive(Clone, Debug)]
+pub struct AdaptivePeriod {
+    target: f64,
+    min: u64,
+    max: u64,
+    last: Option<(u64, Instant)>,
+}
+
+impl AdaptivePeriod {
+    /// Create a new `AdaptivePeriod` that aims to produce `target_rate`
+    /// records per second.
+    ///
+    /// # Panics
+    /// Panics if `target_rate` is not a positive finite number.
+    pub fn new(target_rate: f64) -> Self {
+        assert!(
+            target_rate.is_finite() && target_rate > 0.0,
+            "target rate must be a positive number, got {}",
+            target_rate
+        );
+
+        Self {
+            target: target_rate,
+            min: 1,
+            max: u64::MAX,
+            last: None,
+        }
+    }
+
+    /// Set the smallest period that will be picked.
+    ///
+    /// Very small periods can result in a large amount of overhead when the
+    /// event rate suddenly increases so it is usually a good idea to set this.
+    /// A value of 0 is treated as 1.
+    pub fn min_period(&mut self, min: u64) -> &mut Self {
+        self.min = min.max(1);
+        self
+    }
+
+    /// Set the largest period that will be picked.
+    pub fn max_period(&mut self, max: u64) -> &mut Self {
+        self.max = max;
+        self
+    }
+
+    /// The target record rate, in records per second.
+    pub fn target_rate(&self) -> f64 {
+        self.target
+    }
+
+    /// Record a new reading of the counter value and compute the period that
+    /// should be used from now on.
+    ///
+    /// Returns `None` if there is not enough information to pick a new period
+    /// yet. This happens on the first call, if no time has elapsed, or if the
+    /// counter has gone backwards (e.g. because it was reset).
+    pub(crate) fn update(&mut self, count: u64, now: Instant) -> Option<u64> {
+        let (last_count, last_time) = self.last.replace((count, now))?;
+        let delta = count.checked_sub(last_count)?;
+        let elapsed = now.checked_duration_since(last_time)?;
+
+        self.period_for(delta, elapsed)
+    }
+
+    /// Compute the period that would have generated records at the target
+    /// rate given that the counter increased by `delta` over `elapsed`.
+    fn period_for(&self, delta: u64, elapsed: Duration) -> Option<u64> {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return None;
+        }
+
+        let events_per_sec = delta as f64 / secs;
+        let period = events_per_sec / self.target;
+
+        // Float to int casts saturate so this handles periods above u64::MAX.
+        let period = period.round() as u64;
+        Some(period.clamp(self.min, self.max.max(self.min)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_hits_target_rate() {
+        let period = AdaptivePeriod::new(100.0);
+
+        // 1M events/s at 100 records/s means a period of 10k.
+        assert_eq!(
+            period.period_for(500_000, Duration::from_millis(500)),
+            Some(10_000)
+        );
+    }
+
+    #[test]
+    fn period_is_clamped() {
+        let mut period = AdaptivePeriod::new(1000.0);
+        period.min_period(50).max_period(1_000);
+
+        assert_eq!(period.period_for(0, Duration::from_secs(1)), Some(50));
+        assert_eq!(
+            period.period_for(u64::MAX, Duration::from_secs(1)),
+            Some(1_000)
+        );
+        assert_eq!(period.period_for(100, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn first_update_and_reset() {
+        let mut period = AdaptivePeriod::new(10.0);
+        let start = Instant::now();
+
+        assert_eq!(period.update(1000, start), None);
+        assert_eq!(
+            period.update(11_000, start + Duration::from_secs(1)),
+            Some(1_000)
+        );
+        assert_eq!(period.update(0, start + Duration::from_secs(2)), None);
+    }
+}
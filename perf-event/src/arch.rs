@@ -0,0 +1,225 @@
+//! Architecture-specific instructions for reading performance counters and
+//! timestamps directly from userspace.
+//!
+//! Each supported architecture provides `read_pmc`, which reads the hardware
+//! counter at the (0-based) index handed out by the kernel in the
+//! `perf_event_mmap_page`, and `read_timestamp`, which reads the counter that
+//! the kernel uses as the base for the `time_*` fields in that same page.
+//! Architectures that are not supported return an error from `read_pmc` and
+//! `None` from `read_timestamp`.
+
+use crate::UserReadError;
+
+/// Read the performance counter at `index`.
+///
+/// # Errors
+/// - [`UserReadError::UnsupportedArch`] if reading counters from userspace is
+///   not supported by perf-event2 on the current architecture.
+/// - [`UserReadError::UnsupportedCounter`] if `index` does not refer to a
+///   counter that can be read on this architecture.
+///
+/// # Safety
+/// - `index` must be an index that was handed out by the kernel.
+/// - The kernel must have indicated that the counter can be read from userspace
+///   via `cap_user_rdpmc`. Attempting to read a counter otherwise will likely
+///   result in a `SIGILL` or `SIGSEGV`.
+#[allow(unused_variables)]
+pub(crate) unsafe fn read_pmc(index: u32) -> Result<u64, UserReadError> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return Ok(x86::rdpmc(index));
+
+    #[cfg(target_arch = "aarch64")]
+    return aarch64::read_pmc(index).ok_or(UserReadError::UnsupportedCounter);
+
+    #[cfg(target_arch = "riscv64")]
+    return riscv64::read_pmc(index).ok_or(UserReadError::UnsupportedCounter);
+
+    #[cfg(not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )))]
+    return Err(UserReadError::UnsupportedArch);
+}
+
+/// Read the timestamp counter used as the base for the time fields in the
+/// `perf_event_mmap_page`.
+///
+/// Returns `None` if this is not supported by perf-event2 on the current
+/// architecture.
+pub(crate) fn read_timestamp() -> Option<u64> {
+    // SAFETY: it is always safe to run rdtsc on x86
+    #[cfg(target_arch = "x86")]
+    return Some(unsafe { std::arch::x86::_rdtsc() });
+
+    // SAFETY: it is always safe to run rdtsc on x86
+    #[cfg(target_arch = "x86_64")]
+    return Some(unsafe { std::arch::x86_64::_rdtsc() });
+
+    #[cfg(target_arch = "aarch64")]
+    return Some(aarch64::read_timestamp());
+
+    #[cfg(target_arch = "riscv64")]
+    return Some(riscv64::read_timestamp());
+
+    #[cfg(not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )))]
+    return None;
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+mod x86 {
+    /// Read a performance monitoring counter via the `rdpmc` instruction.
+    ///
+    /// # Safety
+    /// - `index` must be a valid PMC index
+    /// - The current CPU must be allowed to execute the `rdpmc` instruction at
+    ///   the current priviledge level.
+    ///
+    /// Note that the safety constraints come from the x86 ISA so any violation
+    /// of them will likely lead to a SIGINT or other such signal.
+    pub(super) unsafe fn rdpmc(index: u32) -> u64 {
+        // This saves a few instructions for 64-bit since LLVM doesn't realize
+        // that the top 32 bits of RAX:RDX are cleared otherwise.
+        #[cfg(target_arch = "x86_64")]
+        {
+            let lo: u64;
+            let hi: u64;
+
+            std::arch::asm!(
+                "rdpmc",
+                in("ecx") index,
+                out("rax") lo,
+                out("rdx") hi
+            );
+
+            lo | (hi << u32::BITS)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            let lo: u32;
+            let hi: u32;
+
+            std::arch::asm!(
+                "rdpmc",
+                in("ecx") index,
+                out("eax") lo,
+                out("edx") hi
+            );
+
+            (lo as u64) | ((hi as u64) << u32::BITS)
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    /// The index that the kernel uses for the dedicated cycle counter.
+    const CYCLE_COUNTER_INDEX: u32 = 31;
+
+    macro_rules! read_sysreg {
+        ($reg:expr) => {{
+            let value: u64;
+            std::arch::asm!(
+                concat!("mrs {}, ", $reg),
+                out(reg) value,
+                options(nomem, nostack, preserves_flags)
+            );
+            value
+        }};
+    }
+
+    macro_rules! read_pmevcntr {
+        ($index:expr; $( $n:literal ),* $(,)?) => {
+            match $index {
+                $( $n => Some(read_sysreg!(concat!("pmevcntr", stringify!($n), "_el0"))), )*
+                _ => None,
+            }
+        };
+    }
+
+    /// Read a performance counter via the `PMEVCNTR<n>_EL0` or `PMCCNTR_EL0`
+    /// system registers.
+    ///
+    /// # Safety
+    /// The kernel must have enabled userspace access to the counter registers.
+    pub(super) unsafe fn read_pmc(index: u32) -> Option<u64> {
+        if index == CYCLE_COUNTER_INDEX {
+            return Some(read_sysreg!("pmccntr_el0"));
+        }
+
+        read_pmevcntr!(index;
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+            16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
+        )
+    }
+
+    /// Read the virtual count register (`CNTVCT_EL0`).
+    pub(super) fn read_timestamp() -> u64 {
+        // SAFETY: CNTVCT_EL0 is always readable from EL0 on Linux. The isb
+        //         prevents the read from being speculated ahead of earlier
+        //         instructions.
+        unsafe {
+            std::arch::asm!("isb", options(nomem, nostack, preserves_flags));
+            read_sysreg!("cntvct_el0")
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv64")]
+mod riscv64 {
+    macro_rules! read_csr {
+        ($csr:literal) => {{
+            let value: u64;
+            std::arch::asm!(
+                concat!("csrr {}, ", $csr),
+                out(reg) value,
+                options(nomem, nostack, preserves_flags)
+            );
+            value
+        }};
+    }
+
+    macro_rules! read_counter {
+        ($index:expr; $( $n:literal => $csr:literal ),* $(,)?) => {
+            match $index {
+                $( $n => Some(read_csr!($csr)), )*
+                _ => None,
+            }
+        };
+    }
+
+    /// Read a performance counter via the unprivileged counter CSRs (`cycle`,
+    /// `time`, `instret`, and `hpmcounter3` through `hpmcounter31`).
+    ///
+    /// # Safety
+    /// The kernel must have enabled userspace access to the counter CSR via
+    /// `scounteren`.
+    pub(super) unsafe fn read_pmc(index: u32) -> Option<u64> {
+        read_counter!(index;
+            0 => "cycle", 1 => "time", 2 => "instret",
+            3 => "hpmcounter3", 4 => "hpmcounter4", 5 => "hpmcounter5",
+            6 => "hpmcounter6", 7 => "hpmcounter7", 8 => "hpmcounter8",
+            9 => "hpmcounter9", 10 => "hpmcounter10", 11 => "hpmcounter11",
+            12 => "hpmcounter12", 13 => "hpmcounter13", 14 => "hpmcounter14",
+            15 => "hpmcounter15", 16 => "hpmcounter16", 17 => "hpmcounter17",
+            18 => "hpmcounter18", 19 => "hpmcounter19", 20 => "hpmcounter20",
+            21 => "hpmcounter21", 22 => "hpmcounter22", 23 => "hpmcounter23",
+            24 => "hpmcounter24", 25 => "hpmcounter25", 26 => "hpmcounter26",
+            27 => "hpmcounter27", 28 => "hpmcounter28", 29 => "hpmcounter29",
+            30 => "hpmcounter30", 31 => "hpmcounter31",
+        )
+    }
+
+    /// Read the `time` CSR.
+    pub(super) fn read_timestamp() -> u64 {
+        // SAFETY: the time CSR is always readable from userspace on Linux.
+        unsafe { read_csr!("time") }
+    }
+}
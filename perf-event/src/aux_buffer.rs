@@ -0,0 +1,467 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::{io, ptr};
+
+use crate::data::Aux;
+use crate::sampler::{atomic_load, atomic_store, ByteBuffer};
+use crate::sys::bindings::perf_event_mmap_page;
+use crate::{check_errno_syscall, Sampler};
+
+/// The AUX area of a [`Sampler`]'s ring buffer.
+///
+/// Some PMUs (e.g. Intel PT, ARM CoreSight, and ARM SPE) produce far more data
+/// than can reasonably be emitted as individual records. Instead, they write
+/// their trace data into a separate AUX area that is mapped alongside the
+/// regular ring buffer. The kernel then emits `AUX` records (see [`Aux`]) into
+/// the regular ring buffer to describe which parts of the AUX area were
+/// written to.
+///
+/// An `AuxBuffer` is created by calling [`Sampler::map_aux`] or
+/// [`Sampler::map_aux_overwrite`]. There are two modes that the AUX area can be
+/// used in:
+/// - In the normal mode the kernel will not overwrite data that has not yet
+///   been consumed. Reading data via [`read`](Self::read) or
+///   [`for_record`](Self::for_record) will consume it once the returned
+///   [`AuxData`] is dropped. If the buffer fills up then the kernel will stop
+///   writing and will mark the next `AUX` record as truncated.
+/// - In overwrite mode the kernel will continuously overwrite the oldest data
+///   in the buffer. This is meant to be used to take snapshots of the most
+///   recent trace data via [`snapshot`](Self::snapshot), usually after pausing
+///   output or disabling the counter.
+///
+/// The `AuxBuffer` keeps the metadata page of the sampler mapped so it can
+/// outlive the [`Sampler`] it was created from.
+///
+/// # Example
+/// ```no_run
+/// use perf_event::data::Record;
+/// use perf_event::events::DynamicBuilder;
+/// use perf_event::Builder;
+///
+/// let pt = DynamicBuilder::new("intel_pt")?.build()?;
+/// let mut sampler = Builder::new(pt).build()?.sampled(8192)?;
+/// let aux = sampler.map_aux(4 * 1024 * 1024)?;
+///
+/// sampler.enable()?;
+/// // ...
+/// sampler.disable()?;
+///
+/// while let Some(record) = sampler.next_record() {
+///     if let Ok(Record::Aux(record)) = record.parse_record() {
+///         if let Some(data) = aux.for_record(&record) {
+///             println!("got {} bytes of trace data", data.len());
+///         }
+///     }
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+pub struct AuxBuffer {
+    // Note that the AUX area must be unmapped before the sampler's main
+    // mapping so the field order here matters.
+    aux: memmap2::MmapRaw,
+    mmap: Arc<memmap2::MmapRaw>,
+    overwrite: bool,
+
+    /// The position up to which data has been consumed. In normal mode this
+    /// is mirrored into `aux_tail` within the metadata page.
+    tail: Cell<u64>,
+}
+
+/// A view of some data within an [`AuxBuffer`].
+///
+/// Since the AUX area is a ring buffer the data may wrap around the end of the
+/// buffer. That gets exposed here as either one or two byte slices.
+///
+/// When dropped, this type will consume all data in the AUX area up to the end
+/// of the data it references. To avoid this, you can use [`std::mem::forget`].
+pub struct AuxData<'a> {
+    buffer: &'a AuxBuffer,
+    offset: u64,
+    data: ByteBuffer<'a>,
+}
+
+impl AuxBuffer {
+    pub(crate) fn new(sampler: &Sampler, len: usize, overwrite: bool) -> io::Result<Self> {
+        let pagesize =
+            check_errno_syscall(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) })? as usize;
+        let len = len
+            .checked_next_power_of_two()
+            .unwrap_or((usize::MAX >> 1) + 1)
+            .max(pagesize);
+
+        let mmap = sampler.mmap().clone();
+        let page = mmap.as_ptr() as *mut perf_event_mmap_page;
+
+        // SAFETY:
+        // - page points to a valid instance of perf_event_mmap_page.
+        // - data_offset and data_size are never written to by the kernel after the map
+        //   is created.
+        // - aux_offset and aux_size are only read by the kernel while creating the AUX
+        //   mapping, which happens after these writes.
+        let offset = unsafe {
+            let data_offset = ptr::read(ptr::addr_of!((*page).data_offset));
+            let data_size = ptr::read(ptr::addr_of!((*page).data_size));
+            let offset = data_offset + data_size;
+
+            ptr::write_volatile(ptr::addr_of_mut!((*page).aux_offset), offset);
+            ptr::write_volatile(ptr::addr_of_mut!((*page).aux_size), len as u64);
+            offset
+        };
+
+        // The kernel picks the mode for the AUX area based on whether it was
+        // mapped writable or not.
+        let mut options = memmap2::MmapOptions::new();
+        options.offset(offset).len(len);
+        let aux = if overwrite {
+            options.map_raw_read_only(&sampler.as_counter().file)?
+        } else {
+            options.map_raw(&sampler.as_counter().file)?
+        };
+
+        // SAFETY: page points to a valid instance of perf_event_mmap_page
+        let tail = unsafe { ptr::read(ptr::addr_of!((*page).aux_tail)) };
+
+        Ok(Self {
+            aux,
+            mmap,
+            overwrite,
+            tail: Cell::new(tail),
+        })
+    }
+
+    /// The size of the AUX area, in bytes.
+    pub fn size(&self) -> usize {
+        self.aux.len()
+    }
+
+    /// Whether this AUX area was mapped in overwrite mode.
+    pub fn is_overwrite(&self) -> bool {
+        self.overwrite
+    }
+
+    /// The position up to which the kernel has written data into the AUX area.
+    ///
+    /// This, along with [`tail`](Self::tail), is a monotonically increasing
+    /// byte offset and not an index into the AUX area. The exception is
+    /// overwrite mode, where some PMUs (e.g. Intel PT) wrap the head back to
+    /// the start of the AUX area instead.
+    pub fn head(&self) -> u64 {
+        // ATOMICS:
+        // - The acquire load here syncronizes with the release store in the kernel and
+        //   ensures that all the data written to the AUX area before aux_head is
+        //   visible to this thread.
+        // SAFETY:
+        // - page points to a valid instance of perf_event_mmap_page.
+        unsafe { atomic_load(ptr::addr_of!((*self.page()).aux_head), Ordering::Acquire) }
+    }
+
+    /// The position up to which data has been consumed.
+    pub fn tail(&self) -> u64 {
+        self.tail.get()
+    }
+
+    /// Read all data that has been written to the AUX area but not yet
+    /// consumed.
+    ///
+    /// In overwrite mode this will only return the most recent data still
+    /// present in the AUX area. Returns `None` if there is no new data.
+    pub fn read(&self) -> Option<AuxData<'_>> {
+        let head = self.position();
+        let start = self.tail().max(head.saturating_sub(self.size() as u64));
+
+        self.get(start, head - start)
+    }
+
+    /// Get the data at `offset` within the AUX area.
+    ///
+    /// `offset` is a position, like [`head`](Self::head), and not an index into
+    /// the AUX area. This will return `None` if any part of the requested data
+    /// has not been written yet or has already been consumed or overwritten.
+    pub fn get(&self, offset: u64, len: u64) -> Option<AuxData<'_>> {
+        if len == 0 {
+            return None;
+        }
+
+        let head = self.position();
+        let start = aux_window_start(head, self.tail(), self.size() as u64, self.overwrite);
+        let end = offset.checked_add(len)?;
+        if offset < start || end > head {
+            return None;
+        }
+
+        let size = self.size();
+        let data = self.aux.as_ptr();
+        let index = (offset % size as u64) as usize;
+        let len = len as usize;
+
+        // SAFETY:
+        // - the AUX mapping is valid for `size` bytes.
+        // - the range checks above ensure that len <= size.
+        let data = unsafe {
+            if index + len <= size {
+                ByteBuffer::Single(std::slice::from_raw_parts(data.add(index), len))
+            } else {
+                ByteBuffer::Split([
+                    std::slice::from_raw_parts(data.add(index), size - index),
+                    std::slice::from_raw_parts(data, len - (size - index)),
+                ])
+            }
+        };
+
+        Some(AuxData {
+            buffer: self,
+            offset,
+            data,
+        })
+    }
+
+    /// Get the data described by an `AUX` record.
+    ///
+    /// This will return `None` if the data is no longer available or if the
+    /// record describes an empty update.
+    pub fn for_record(&self, record: &Aux) -> Option<AuxData<'_>> {
+        self.get(record.aux_offset, record.aux_size)
+    }
+
+    /// Copy the most recent data in the AUX area.
+    ///
+    /// In normal mode this copies all data that has not yet been consumed. In
+    /// overwrite mode it copies the entire AUX area, ordered from oldest to
+    /// newest. This does not consume any data. In overwrite mode the kernel may
+    /// be writing to the AUX area while it is being copied so you will want
+    /// to pause output (see [`Sampler::pause_output`]) or disable the
+    /// counter before taking a snapshot.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let head = self.position();
+        let start = aux_window_start(head, self.tail(), self.size() as u64, self.overwrite);
+
+        match self.get(start, head - start) {
+            Some(data) => {
+                let vec = data.to_vec();
+                std::mem::forget(data);
+                vec
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The [`head`](Self::head) as a monotonically increasing position.
+    fn position(&self) -> u64 {
+        let head = self.head();
+        if !self.overwrite {
+            return head;
+        }
+
+        // SAFETY: the AUX mapping is valid for `size` bytes.
+        let data = unsafe { std::slice::from_raw_parts(self.aux.as_ptr(), self.size()) };
+        overwrite_position(head, data)
+    }
+
+    fn page(&self) -> *const perf_event_mmap_page {
+        self.mmap.as_ptr() as *const _
+    }
+
+    fn consume(&self, end: u64) {
+        if end <= self.tail.get() {
+            return;
+        }
+
+        self.tail.set(end);
+
+        // The kernel ignores aux_tail in overwrite mode.
+        if !self.overwrite {
+            // ATOMICS:
+            // - The release store here prevents the compiler from re-ordering any reads
+            //   past the store to aux_tail.
+            // SAFETY:
+            // - page points to a valid instance of perf_event_mmap_page
+            unsafe {
+                atomic_store(
+                    ptr::addr_of!((*self.page()).aux_tail),
+                    end,
+                    Ordering::Release,
+                )
+            }
+        }
+    }
+}
+
+// AuxBuffer contains pointers which prevent it from implementing Send by
+// default. The mappings are owned by the AuxBuffer so it is safe to send it
+// across threads.
+unsafe impl Send for AuxBuffer {}
+
+impl<'a> AuxData<'a> {
+    /// The position of this data within the AUX area.
+    ///
+    /// This is a monotonically increasing byte offset, like the `aux_offset`
+    /// field of an `AUX` record.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The length of this data, in bytes.
+    #[allow(clippy::len_without_is_empty)] // AuxData is never empty
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Access the bytes of this data.
+    ///
+    /// If the data wraps around the end of the AUX area then two slices will
+    /// be returned, otherwise only one will be.
+    pub fn data(&self) -> &[&'a [u8]] {
+        self.data.as_slices()
+    }
+
+    /// Copy the bytes of this data to an owned [`Vec`].
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.to_contiguous().into_owned()
+    }
+
+    /// Get the bytes of this data as a single contiguous slice.
+    ///
+    /// If the data wraps around the end of the AUX area then it will be copied
+    /// to a vector.
+    pub fn to_contiguous(&self) -> Cow<'a, [u8]> {
+        self.data.to_contiguous()
+    }
+}
+
+impl Drop for AuxData<'_> {
+    fn drop(&mut self) {
+        self.buffer.consume(self.offset + self.len() as u64);
+    }
+}
+
+/// Compute the oldest position within the AUX area that still contains valid
+/// data.
+fn aux_window_start(head: u64, tail: u64, size: u64, overwrite: bool) -> u64 {
+    let oldest = head.saturating_sub(size);
+
+    // In normal mode the kernel will not overwrite data that has not been
+    // consumed yet, but anything before the tail may have been overwritten.
+    if overwrite {
+        oldest
+    } else {
+        oldest.max(tail)
+    }
+}
+
+/// Convert the head of an AUX area in overwrite mode into a monotonically
+/// increasing position.
+///
+/// PMUs such as Intel PT wrap `aux_head` to the size of the AUX area in
+/// overwrite mode, so a head within the AUX area does not tell us whether the
+/// buffer has wrapped around. The AUX area starts out zeroed, so, like perf
+/// does, we treat it as having wrapped if anything has been written past the
+/// head.
+fn overwrite_position(head: u64, data: &[u8]) -> u64 {
+    let size = data.len() as u64;
+    if head >= size {
+        return head;
+    }
+
+    if data[head as usize..].iter().any(|&byte| byte != 0) {
+        head + size
+    } else {
+        head
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_normal_mode() {
+        assert_eq!(aux_window_start(100, 40, 4096, false), 40);
+        assert_eq!(aux_window_start(10_000, 9_000, 4096, false), 9_000);
+        assert_eq!(aux_window_start(10_000, 0, 4096, false), 10_000 - 4096);
+    }
+
+    #[test]
+    fn window_overwrite_mode() {
+        assert_eq!(aux_window_start(100, 40, 4096, true), 0);
+        assert_eq!(aux_window_start(10_000, 9_000, 4096, true), 10_000 - 4096);
+    }
+
+    #[test]
+    fn overwrite_wrapped_head() {
+        let mut data = [0u8; 16];
+        data[..4].fill(1);
+        assert_eq!(overwrite_position(4, &data), 4);
+        assert_eq!(overwrite_position(40, &data), 40);
+
+        data[12] = 1;
+        assert_eq!(overwrite_position(4, &data), 20);
+        assert_eq!(overwrite_position(0, &data), 16);
+    }
+
+    /// Write `bytes` into the AUX area of `sampler` at `index` and set
+    /// `aux_head` to `head`, as the kernel would.
+    #[cfg(feature = "hooks")]
+    fn write_aux(sampler: &Sampler, aux: &AuxBuffer, index: usize, bytes: &[u8], head: u64) {
+        use std::os::unix::fs::FileExt;
+
+        let page = aux.page();
+        // SAFETY: page points to a valid instance of perf_event_mmap_page.
+        let aux_offset = unsafe { ptr::read(ptr::addr_of!((*page).aux_offset)) };
+        sampler
+            .as_counter()
+            .file
+            .write_all_at(bytes, aux_offset + index as u64)
+            .unwrap();
+
+        // SAFETY: page points to a valid instance of perf_event_mmap_page.
+        unsafe { atomic_store(ptr::addr_of!((*page).aux_head), head, Ordering::Release) }
+    }
+
+    #[test]
+    #[cfg(feature = "hooks")]
+    fn snapshot_overwrite_mode() {
+        use crate::events::Software;
+        use crate::hooks::{clear_thread_hooks, set_thread_hooks, MockKernel};
+        use crate::Builder;
+
+        let kernel = MockKernel::new();
+        unsafe { set_thread_hooks(Box::new(kernel.clone())) };
+        let mut sampler = Builder::new(Software::DUMMY)
+            .build()
+            .unwrap()
+            .sampled(8192)
+            .unwrap();
+        unsafe { clear_thread_hooks() };
+
+        // The mock only sets up the data area once a record is written to it.
+        let pagesize = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+        let page = sampler.mmap().as_mut_ptr() as *mut perf_event_mmap_page;
+        // SAFETY: page points to a valid instance of perf_event_mmap_page.
+        unsafe {
+            (*page).data_offset = pagesize;
+            (*page).data_size = sampler.mmap().len() as u64 - pagesize;
+        }
+
+        let aux = sampler.map_aux_overwrite(4096).unwrap();
+        let size = aux.size();
+        assert!(aux.is_overwrite());
+        assert_eq!(aux.snapshot(), Vec::<u8>::new());
+
+        // Before wrapping, only the data that has been written is returned.
+        write_aux(&sampler, &aux, 0, &vec![1; size - 100], (size - 100) as u64);
+        assert_eq!(aux.snapshot(), vec![1; size - 100]);
+
+        // Once the head wraps, the whole AUX area is returned starting with
+        // the oldest data just past the head.
+        write_aux(&sampler, &aux, size - 100, &[2; 100], 0);
+        write_aux(&sampler, &aux, 0, &[3; 50], 50);
+
+        let mut expected = vec![1; size - 150];
+        expected.extend_from_slice(&[2; 100]);
+        expected.extend_from_slice(&[3; 50]);
+        assert_eq!(aux.snapshot(), expected);
+        assert_eq!(aux.read().unwrap().to_vec(), expected);
+    }
+}
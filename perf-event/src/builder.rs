@@ -7,7 +7,6 @@ use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::sync::Arc;
 
 use libc::pid_t;
-use perf_event_data::parse::ParseConfig;
 use perf_event_open_sys::bindings;
 
 use crate::events::{Event, EventData};
@@ -199,7 +198,7 @@ impl<'a> Builder<'a> {
     /// [`enable_on_exec`]: Builder::enable_on_exec
     /// [0]: https://www.mankier.com/2/perf_event_open
     pub fn build(&self) -> std::io::Result<Counter> {
-        Counter::new_internal(self.build_impl(None)?, ParseConfig::from(self.attrs))
+        Counter::new_internal(self.build_impl(None)?, self.attrs)
     }
 
     /// Construct a [`Counter`] as part of a group.
@@ -252,7 +251,7 @@ impl<'a> Builder<'a> {
             .checked_add(1)
             .expect("cannot add more than u32::MAX elements to a group");
 
-        Counter::new_internal(file, ParseConfig::from(self.attrs))
+        Counter::new_internal(file, self.attrs)
     }
 
     /// Build a [`Group`] according to the specifications made on this
@@ -457,6 +456,23 @@ impl<'a> Builder<'a> {
         self.attrs.read_format = read_format.bits();
         self
     }
+
+    /// Include [`TOTAL_TIME_ENABLED`] and [`TOTAL_TIME_RUNNING`] in
+    /// [`read_format`], in addition to any fields that are already set.
+    ///
+    /// These are needed to scale counter values when the kernel has to
+    /// multiplex counters onto the hardware, see
+    /// [`CounterData::scaled_count`].
+    ///
+    /// [`TOTAL_TIME_ENABLED`]: ReadFormat::TOTAL_TIME_ENABLED
+    /// [`TOTAL_TIME_RUNNING`]: ReadFormat::TOTAL_TIME_RUNNING
+    /// [`read_format`]: Builder::read_format
+    /// [`CounterData::scaled_count`]: crate::CounterData::scaled_count
+    pub fn enable_scaling(&mut self) -> &mut Self {
+        self.attrs.read_format |=
+            (ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::TOTAL_TIME_RUNNING).bits();
+        self
+    }
 }
 
 // Section for methods which directly modify attrs. These should correspond
@@ -810,6 +826,20 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Have the kernel write records into the ring buffer backwards, from the
+    /// end of the buffer towards the start.
+    ///
+    /// This is required for using a counter as a [`FlightRecorder`], where
+    /// the kernel continuously overwrites the oldest records in the ring
+    /// buffer. A [`Sampler`] cannot read records that were written backwards.
+    ///
+    /// [`FlightRecorder`]: crate::FlightRecorder
+    /// [`Sampler`]: crate::Sampler
+    pub fn write_backward(&mut self, write_backward: bool) -> &mut Self {
+        self.attrs.set_write_backward(write_backward.into());
+        self
+    }
+
     /// Generate `NAMESPACES` records when a task enters a new namespace.
     pub fn namespaces(&mut self, namespaces: bool) -> &mut Self {
         self.attrs.set_namespaces(namespaces.into());
@@ -0,0 +1,231 @@
+use std::io;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{check_errno_syscall, Clock};
+
+/// Converts perf timestamps into [`Instant`]s and [`SystemTime`]s.
+///
+/// The `TIME` field of records emitted by the kernel is measured using either
+/// the clock selected via [`Builder::clockid`] or, if no clock was selected,
+/// an internal kernel clock that does not correspond to any clock accessible
+/// from userspace. A `PerfClock` records a reference point in both perf time
+/// and the standard library clocks so that timestamps can be correlated with
+/// other sources of time (e.g. application logs).
+///
+/// There are two ways to create a `PerfClock`:
+/// - [`Sampler::perf_clock`] uses the time conversion fields in the metadata
+///   page of the sampler. This works even when no clock was selected, provided
+///   the kernel and architecture support it.
+/// - [`PerfClock::new`] works for counters built with a specific
+///   [`Builder::clockid`].
+///
+/// The conversion is only as accurate as the reference point. Converting
+/// timestamps that are far away from when the `PerfClock` was created will
+/// accumulate any drift between the clocks.
+///
+/// # Example
+/// ```
+/// use perf_event::events::Software;
+/// use perf_event::{Builder, Clock, PerfClock, SampleFlag};
+///
+/// let mut sampler = Builder::new(Software::CPU_CLOCK)
+///     .sample_period(100_000)
+///     .sample(SampleFlag::TIME)
+///     .clockid(Clock::MONOTONIC)
+///     .enabled(true)
+///     .build()?
+///     .sampled(8192)?;
+/// let clock = PerfClock::new(Clock::MONOTONIC)?;
+///
+/// // ... do some work ...
+///
+/// while let Some(record) = sampler.next_record() {
+///     if let Ok(perf_event::data::Record::Sample(sample)) = record.parse_record() {
+///         println!(
+///             "{:?}",
+///             sample.time().and_then(|time| clock.to_system_time(time))
+///         );
+///     }
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Builder::clockid`]: crate::Builder::clockid
+/// [`Sampler::perf_clock`]: crate::Sampler::perf_clock
+#[derive(Copy, Clone, Debug)]
+pub struct PerfClock {
+    clock: Option<Clock>,
+    conversion: Option<TimeConversion>,
+
+    perf_time: u64,
+    instant: Instant,
+    system_time: SystemTime,
+}
+
+impl PerfClock {
+    /// Create a `PerfClock` for timestamps measured using `clock`.
+    ///
+    /// This is the clock that was passed to [`Builder::clockid`].
+    ///
+    /// [`Builder::clockid`]: crate::Builder::clockid
+    pub fn new(clock: Clock) -> io::Result<Self> {
+        let perf_time = clock_gettime(clock)?;
+
+        Ok(Self {
+            clock: Some(clock),
+            conversion: None,
+            perf_time,
+            instant: Instant::now(),
+            system_time: SystemTime::now(),
+        })
+    }
+
+    /// Create a `PerfClock` for the kernel's internal perf clock using the
+    /// time conversion fields from a metadata page.
+    pub(crate) fn from_conversion(conversion: TimeConversion, timestamp: u64) -> Self {
+        Self {
+            clock: None,
+            conversion: Some(conversion),
+            perf_time: conversion.to_perf_time(timestamp),
+            instant: Instant::now(),
+            system_time: SystemTime::now(),
+        }
+    }
+
+    /// The clock that timestamps are measured with, or `None` if they are
+    /// measured with the kernel's internal perf clock.
+    pub fn clock(&self) -> Option<Clock> {
+        self.clock
+    }
+
+    /// Convert a raw timestamp counter value into a perf timestamp.
+    ///
+    /// The timestamp counter is the one read by [`Sampler::read_user`] (e.g.
+    /// `rdtsc` on x86). This is only possible if this `PerfClock` was created
+    /// by [`Sampler::perf_clock`] for a counter that uses the kernel's
+    /// internal perf clock. Otherwise, this returns `None`.
+    ///
+    /// [`Sampler::read_user`]: crate::Sampler::read_user
+    /// [`Sampler::perf_clock`]: crate::Sampler::perf_clock
+    pub fn tsc_to_perf_time(&self, tsc: u64) -> Option<u64> {
+        self.conversion
+            .map(|conversion| conversion.to_perf_time(tsc))
+    }
+
+    /// Convert a perf timestamp into an [`Instant`].
+    ///
+    /// Returns `None` if the resulting time cannot be represented as an
+    /// `Instant`.
+    pub fn to_instant(&self, time: u64) -> Option<Instant> {
+        if time >= self.perf_time {
+            self.instant
+                .checked_add(Duration::from_nanos(time - self.perf_time))
+        } else {
+            self.instant
+                .checked_sub(Duration::from_nanos(self.perf_time - time))
+        }
+    }
+
+    /// Convert a perf timestamp into a [`SystemTime`].
+    ///
+    /// Returns `None` if the resulting time cannot be represented as a
+    /// `SystemTime`.
+    pub fn to_system_time(&self, time: u64) -> Option<SystemTime> {
+        if time >= self.perf_time {
+            self.system_time
+                .checked_add(Duration::from_nanos(time - self.perf_time))
+        } else {
+            self.system_time
+                .checked_sub(Duration::from_nanos(self.perf_time - time))
+        }
+    }
+}
+
+/// The fields of `perf_event_mmap_page` used to convert timestamp counter
+/// values into perf time.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TimeConversion {
+    pub time_zero: u64,
+    pub time_mult: u32,
+    pub time_shift: u16,
+
+    /// `time_cycles` and `time_mask`, if `cap_user_time_short` is set.
+    pub time_short: Option<(u64, u64)>,
+}
+
+impl TimeConversion {
+    /// Convert a timestamp counter value into perf time.
+    ///
+    /// This follows the algorithm documented for the `time_zero` field of
+    /// `perf_event_mmap_page`.
+    pub fn to_perf_time(self, mut cyc: u64) -> u64 {
+        if let Some((time_cycles, time_mask)) = self.time_short {
+            cyc = time_cycles.wrapping_add(cyc.wrapping_sub(time_cycles) & time_mask);
+        }
+
+        let time_mult = self.time_mult as u64;
+        let quot = cyc >> self.time_shift;
+        let rem = cyc & ((1u64 << self.time_shift) - 1);
+
+        self.time_zero
+            .wrapping_add(quot.wrapping_mul(time_mult))
+            .wrapping_add(rem.wrapping_mul(time_mult) >> self.time_shift)
+    }
+}
+
+/// Read the current value of `clock` in nanoseconds.
+fn clock_gettime(clock: Clock) -> io::Result<u64> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    check_errno_syscall(|| unsafe { libc::clock_gettime(clock.into_raw(), &mut ts) })?;
+
+    Ok(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perf_time_conversion() {
+        let conversion = TimeConversion {
+            time_zero: 1000,
+            time_mult: 3,
+            time_shift: 1,
+            time_short: None,
+        };
+
+        assert_eq!(conversion.to_perf_time(0), 1000);
+        assert_eq!(conversion.to_perf_time(10), 1015);
+        assert_eq!(conversion.to_perf_time(11), 1016);
+    }
+
+    #[test]
+    fn perf_time_conversion_short() {
+        let conversion = TimeConversion {
+            time_zero: 0,
+            time_mult: 1,
+            time_shift: 0,
+            time_short: Some((0x1_0000_0000, 0xFFFF_FFFF)),
+        };
+
+        assert_eq!(conversion.to_perf_time(0x10), 0x1_0000_0010);
+    }
+
+    #[test]
+    fn monotonic_round_trip() {
+        let clock = PerfClock::new(Clock::MONOTONIC).unwrap();
+        let now = clock_gettime(Clock::MONOTONIC).unwrap();
+        let instant = clock.to_instant(now).unwrap();
+
+        let error = if instant > Instant::now() {
+            instant - Instant::now()
+        } else {
+            Instant::now() - instant
+        };
+        assert!(error < Duration::from_secs(1), "error was {:?}", error);
+    }
+}
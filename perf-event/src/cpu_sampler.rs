@@ -0,0 +1,391 @@
+use std::convert::TryInto;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+use std::{fmt, io};
+
+use crate::per_cpu::online_cpus;
+use crate::{check_errno_syscall, Builder, Record, SampleFlag, Sampler};
+
+/// A set of [`Sampler`]s, one per CPU, whose records are merged into a single
+/// stream ordered by timestamp.
+///
+/// Sampling the whole system requires a separate ring buffer for every CPU.
+/// `CpuSampler` opens a [`Sampler`] on each CPU and then merges the records
+/// from all of them so that they are returned in [`SampleFlag::TIME`] order,
+/// the same way `perf record` would order them.
+///
+/// Records within a single ring buffer are already ordered, but records from
+/// different CPUs may show up in their ring buffers at different times. To
+/// deal with this, `CpuSampler` uses a bounded reordering window. A record is
+/// only returned once either
+/// - every ring buffer has a record available, in which case the oldest one is
+///   guaranteed to be next, or,
+/// - a record has been seen that is newer by more than the reordering window.
+///
+/// [`next_blocking`] will additionally return the oldest available record if
+/// no new records show up for the duration of the reordering window. Use
+/// [`flush_next`] to bypass the window entirely, e.g. when draining the ring
+/// buffers after disabling the samplers.
+///
+/// In order to be able to order records, `CpuSampler` will enable both
+/// [`SampleFlag::TIME`] and [`sample_id_all`] on the builder it is created
+/// with. This means that every record, including `MMAP` records, carries a
+/// timestamp either in the sample itself or in its trailing sample id. Records
+/// whose timestamp cannot be parsed are treated as being older than every
+/// other record.
+///
+/// # Example
+/// ```no_run
+/// use perf_event::events::Software;
+/// use perf_event::{Builder, CpuSampler};
+///
+/// let mut builder = Builder::new(Software::CPU_CLOCK);
+/// builder.any_pid().sample_frequency(1000);
+///
+/// let mut sampler = CpuSampler::new(&builder, 8192)?;
+/// sampler.enable()?;
+///
+/// while let Some(record) = sampler.next_blocking(None) {
+///     println!("{:?}", record.parse_record());
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`next_blocking`]: Self::next_blocking
+/// [`flush_next`]: Self::flush_next
+/// [`sample_id_all`]: Builder::sample_id_all
+pub struct CpuSampler {
+    samplers: Vec<Entry>,
+    window: u64,
+    max_time: u64,
+}
+
+struct Entry {
+    cpu: usize,
+    sampler: Sampler,
+
+    /// The timestamp of the record at the tail of the ring buffer, if we have
+    /// already looked at it.
+    pending: Option<u64>,
+
+    /// Whether the process being tracked by this sampler has exited. No new
+    /// records will be written once this happens.
+    hung_up: bool,
+}
+
+impl CpuSampler {
+    /// The default size of the reordering window.
+    pub const DEFAULT_WINDOW: Duration = Duration::from_millis(100);
+
+    /// Open a sampler on every online CPU.
+    ///
+    /// `map_len` is the size of the ring buffer for each CPU. See
+    /// [`Counter::sampled`](crate::Counter::sampled) for details on how it is
+    /// rounded.
+    pub fn new(builder: &Builder, map_len: usize) -> io::Result<Self> {
+        Self::with_cpus(builder, online_cpus()?, map_len)
+    }
+
+    /// Open a sampler on each of the CPUs in `cpus`.
+    pub fn with_cpus<I>(builder: &Builder, cpus: I, map_len: usize) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut builder = builder.clone();
+        builder.sample(SampleFlag::TIME).sample_id_all(true);
+
+        let samplers = cpus
+            .into_iter()
+            .map(|cpu| {
+                Ok(Entry {
+                    cpu,
+                    sampler: builder.one_cpu(cpu).build()?.sampled(map_len)?,
+                    pending: None,
+                    hung_up: false,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            samplers,
+            window: Self::DEFAULT_WINDOW.as_nanos() as u64,
+            max_time: 0,
+        })
+    }
+
+    /// Set the size of the reordering window.
+    ///
+    /// The window is measured in the same units as the record timestamps,
+    /// which are nanoseconds. Larger windows are more tolerant of records
+    /// that are delayed but also increase the latency before records are
+    /// returned by [`next_record`](Self::next_record).
+    pub fn reorder_window(&mut self, window: Duration) -> &mut Self {
+        self.window = window.as_nanos().try_into().unwrap_or(u64::MAX);
+        self
+    }
+
+    /// The number of samplers in this set.
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    /// Whether this set contains no samplers at all.
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    /// Get the sampler for `cpu`, if there is one.
+    pub fn get(&self, cpu: usize) -> Option<&Sampler> {
+        self.samplers
+            .iter()
+            .find(|entry| entry.cpu == cpu)
+            .map(|entry| &entry.sampler)
+    }
+
+    /// Iterate over the samplers in this set along with the CPU that each one
+    /// is observing.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Sampler)> {
+        self.samplers
+            .iter()
+            .map(|entry| (entry.cpu, &entry.sampler))
+    }
+
+    /// Convert this set into the individual samplers that make it up.
+    pub fn into_samplers(self) -> Vec<(usize, Sampler)> {
+        self.samplers
+            .into_iter()
+            .map(|entry| (entry.cpu, entry.sampler))
+            .collect()
+    }
+
+    /// Enable every sampler in this set.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.samplers
+            .iter_mut()
+            .try_for_each(|entry| entry.sampler.enable())
+    }
+
+    /// Disable every sampler in this set.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.samplers
+            .iter_mut()
+            .try_for_each(|entry| entry.sampler.disable())
+    }
+
+    /// Reset the counters of every sampler in this set.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.samplers
+            .iter_mut()
+            .try_for_each(|entry| entry.sampler.reset())
+    }
+
+    /// Read the next record in timestamp order.
+    ///
+    /// This method does not block. It will return `None` if no record can be
+    /// returned without potentially violating the timestamp ordering. See the
+    /// type-level docs for more details.
+    pub fn next_record(&mut self) -> Option<Record<'_>> {
+        let index = self.ready_index()?;
+        self.take(index)
+    }
+
+    /// Read the next record in timestamp order. This method will block (with
+    /// an optional timeout) until a record is available.
+    ///
+    /// If no new records are written to any ring buffer for the duration of
+    /// the reordering window then the oldest available record will be
+    /// returned, even if it could not otherwise be proven to be next.
+    ///
+    /// This will return `None` if the timeout expires or if every sampler is
+    /// tracking a process that has exited and there are no records left.
+    ///
+    /// # Panics
+    /// This method will panic if an unexpected error is returned from
+    /// `libc::poll`. See [`Sampler::next_blocking`] for details.
+    pub fn next_blocking(&mut self, timeout: Option<Duration>) -> Option<Record<'_>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let window = Duration::from_nanos(self.window);
+
+        loop {
+            if let Some(index) = self.ready_index() {
+                return self.take(index);
+            }
+
+            let remaining = match deadline {
+                Some(deadline) => Some(deadline.checked_duration_since(Instant::now())?),
+                None => None,
+            };
+
+            // If there is a record waiting then we only wait for as long as
+            // the reordering window before giving up and returning it anyways.
+            let has_pending = self.samplers.iter().any(|entry| entry.pending.is_some());
+            let wait = match (remaining, has_pending) {
+                (Some(remaining), true) => Some(remaining.min(window)),
+                (Some(remaining), false) => Some(remaining),
+                (None, true) => Some(window),
+                (None, false) => None,
+            };
+
+            match self.poll(wait) {
+                PollResult::Ready => continue,
+                PollResult::Timeout | PollResult::HangUp if has_pending => {
+                    return self.flush_next()
+                }
+                PollResult::Timeout if remaining.is_some() => return None,
+                PollResult::Timeout => continue,
+                // All samplers were tracking processes that have exited.
+                // There may still be records in the ring buffers though.
+                PollResult::HangUp => return self.flush_next(),
+            }
+        }
+    }
+
+    /// Return the oldest record currently available in any of the ring
+    /// buffers, without waiting for the reordering window.
+    ///
+    /// This is mainly useful for draining the remaining records once the
+    /// samplers have been disabled.
+    pub fn flush_next(&mut self) -> Option<Record<'_>> {
+        self.peek_all();
+
+        let (index, _) = self.oldest()?;
+        self.take(index)
+    }
+
+    /// Look at the record at the tail of each ring buffer and record its
+    /// timestamp.
+    fn peek_all(&mut self) {
+        for entry in &mut self.samplers {
+            if entry.pending.is_some() {
+                continue;
+            }
+
+            let record = match entry.sampler.next_record() {
+                Some(record) => record,
+                None => continue,
+            };
+
+            let time = record
+                .parse_sample_id()
+                .ok()
+                .and_then(|id| id.time())
+                .unwrap_or(0);
+
+            // We only want to look at the record here, not consume it.
+            std::mem::forget(record);
+
+            entry.pending = Some(time);
+            self.max_time = self.max_time.max(time);
+        }
+    }
+
+    fn oldest(&self) -> Option<(usize, u64)> {
+        self.samplers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| Some((index, entry.pending?)))
+            .min_by_key(|&(_, time)| time)
+    }
+
+    /// Find the index of the sampler whose record should be returned next,
+    /// provided that returning it will not violate the ordering guarantees.
+    fn ready_index(&mut self) -> Option<usize> {
+        self.peek_all();
+
+        let (index, time) = self.oldest()?;
+        let all_pending = self
+            .samplers
+            .iter()
+            .all(|entry| entry.pending.is_some() || entry.hung_up);
+
+        if all_pending || time.saturating_add(self.window) <= self.max_time {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self, index: usize) -> Option<Record<'_>> {
+        let entry = &mut self.samplers[index];
+        entry.pending = None;
+        entry.sampler.next_record()
+    }
+
+    fn poll(&mut self, timeout: Option<Duration>) -> PollResult {
+        let timeout = match timeout {
+            Some(timeout) => timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+
+        let mut entries: Vec<_> = self
+            .samplers
+            .iter_mut()
+            .filter(|entry| !entry.hung_up)
+            .collect();
+        let mut pollfds: Vec<_> = entries
+            .iter()
+            .map(|entry| libc::pollfd {
+                fd: entry.sampler.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        if pollfds.is_empty() {
+            return PollResult::HangUp;
+        }
+
+        loop {
+            let result = check_errno_syscall(|| unsafe {
+                libc::poll(pollfds.as_mut_ptr(), pollfds.len() as _, timeout)
+            });
+
+            match result {
+                Ok(0) => return PollResult::Timeout,
+                Ok(_) => break,
+                Err(e) => match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    _ => panic!(
+                        "polling a perf-event fd returned an unexpected error: {}",
+                        e
+                    ),
+                },
+            }
+        }
+
+        for (entry, pollfd) in entries.iter_mut().zip(&pollfds) {
+            if pollfd.revents & libc::POLLHUP != 0 {
+                entry.hung_up = true;
+            }
+        }
+
+        if entries.iter().all(|entry| entry.hung_up) {
+            PollResult::HangUp
+        } else {
+            PollResult::Ready
+        }
+    }
+}
+
+impl fmt::Debug for CpuSampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CpuSampler")
+            .field(
+                "cpus",
+                &self
+                    .samplers
+                    .iter()
+                    .map(|entry| entry.cpu)
+                    .collect::<Vec<_>>(),
+            )
+            .field("window", &Duration::from_nanos(self.window))
+            .field("max_time", &self.max_time)
+            .finish_non_exhaustive()
+    }
+}
+
+enum PollResult {
+    Ready,
+    Timeout,
+    HangUp,
+}
@@ -6,6 +6,7 @@ use perf_event_open_sys::bindings::perf_event_attr;
 
 use crate::events::x86::Msr;
 use crate::events::Event;
+use crate::pmu::{Format, FormatTarget, Pmu, PmuEvent};
 
 used_in_docs!(Msr);
 
@@ -39,49 +40,9 @@ impl Event for Dynamic {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-enum FieldDest {
-    Config,
-    Config1,
-    Config2,
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-enum FieldBits {
-    /// Field is a single bit
-    Bit(u32),
-
-    /// Field covers a range of bits.
-    ///
-    /// Note that `hi` is inclusive.
-    Range { lo: u32, hi: u32 },
-}
-
-impl FieldBits {
-    pub fn mask(self) -> u64 {
-        match self {
-            Self::Bit(bit) => 1u64 << bit,
-            Self::Range { lo, hi } => (u64::MAX >> (63 - hi)) & (u64::MAX << lo),
-        }
-    }
-
-    pub fn shift(self) -> u32 {
-        match self {
-            Self::Bit(bit) => bit,
-            Self::Range { lo, .. } => lo,
-        }
-    }
-
-    pub fn validate(&self, value: u64) -> bool {
-        let mask = self.mask() >> self.shift();
-        value & !mask == 0
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 struct Field {
-    dest: FieldDest,
-    bits: FieldBits,
+    format: Format,
     value: Option<u64>,
 }
 
@@ -124,10 +85,10 @@ struct Field {
 /// # }
 /// ```
 ///
-/// You can use `perf list` to get a list of which kernel PMU events are
-/// supported on the current machine. These will generally be listed in the
-/// format `<pmu>/<event>/`. Here is a sample of some of the events on my
-/// machine:
+/// You can use `perf list` or [`Pmu::events`] to get a list of which kernel
+/// PMU events are supported on the current machine. These will generally be
+/// listed in the format `<pmu>/<event>/`. Here is a sample of some of the
+/// events on my machine:
 ///
 /// ```text
 /// msr/aperf/                                         [Kernel PMU event]
@@ -186,7 +147,7 @@ struct Field {
 pub struct DynamicBuilder {
     ty: u32,
     pmu: PathBuf,
-    event: Option<PathBuf>,
+    event: Option<PmuEvent>,
     fields: HashMap<String, Field>,
 }
 
@@ -210,77 +171,43 @@ impl DynamicBuilder {
     }
 
     fn _new(pmu: &Path) -> io::Result<Self> {
-        let mut path = Path::new("/sys/bus/event_source/devices").to_path_buf();
-        path.push(pmu);
-
-        path.push("type");
-        let ty: u32 = match std::fs::read_to_string(&path)?.trim().parse() {
-            Ok(ty) => ty,
-            Err(e) => return Err(Error::parse(path, e))?,
-        };
-
-        path.pop();
-        path.push("format");
-
-        let mut fields = HashMap::new();
-
-        for entry in std::fs::read_dir(&path)? {
-            let entry = entry?;
-            let contents = std::fs::read_to_string(entry.path())?;
-
-            let (name, rest) = contents
-                .split_once(':')
-                .ok_or_else(|| Error::missing_colon(entry.path()))?;
-
-            let dest = match name {
-                "config" => FieldDest::Config,
-                "config1" => FieldDest::Config1,
-                "config2" => FieldDest::Config2,
-                _ => return Err(Error::unknown_target(entry.path(), name.to_owned()))?,
-            };
-
-            let bits = if let Some((first, rest)) = rest.split_once('-') {
-                let lo: u32 = first.parse().map_err(|e| Error::parse(entry.path(), e))?;
-                let hi: u32 = rest
-                    .trim_end()
-                    .parse()
-                    .map_err(|e| Error::parse(entry.path(), e))?;
-
-                FieldBits::Range { lo, hi }
-            } else {
-                let index = rest
-                    .trim_end()
-                    .parse()
-                    .map_err(|e| Error::parse(entry.path(), e))?;
-
-                FieldBits::Bit(index)
-            };
-
-            let name = entry.file_name();
-            let name = name
-                .to_str()
-                .ok_or_else(|| Error::invalid_field_name(entry.path()))?;
-
-            fields.insert(
-                name.to_owned(),
-                Field {
-                    dest,
-                    bits,
-                    value: None,
-                },
-            );
-        }
-
-        path.pop();
+        let pmu = Pmu::new(pmu)?;
+        let fields = pmu
+            .formats()?
+            .into_iter()
+            .map(|format| {
+                let name = format.name().to_owned();
+                (
+                    name,
+                    Field {
+                        format,
+                        value: None,
+                    },
+                )
+            })
+            .collect();
 
         Ok(Self {
-            ty,
-            pmu: path,
+            ty: pmu.ty()?,
+            pmu: pmu.path().to_owned(),
             event: None,
             fields,
         })
     }
 
+    /// Construct a new dynamic builder for `pmu` with every field set to 0.
+    ///
+    /// This is useful when building events whose definitions only mention the
+    /// fields that are non-zero.
+    pub(crate) fn with_zeroed_fields(pmu: impl AsRef<Path>) -> io::Result<Self> {
+        let mut builder = Self::new(pmu)?;
+        for field in builder.fields.values_mut() {
+            field.value = Some(0);
+        }
+
+        Ok(builder)
+    }
+
     /// Initialize the builder for the specified event.
     ///
     /// `event` can be either
@@ -298,35 +225,23 @@ impl DynamicBuilder {
     }
 
     fn _event(&mut self, event: &Path) -> io::Result<&mut Self> {
-        // The event file format is described here:
-        // https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-bus-event_source-devices-events
-
-        let mut path = self.pmu.join("events");
-        path.push(event);
-
-        let text = std::fs::read_to_string(&path)?;
-
-        for term in text.split(',') {
-            let term = term.trim_end();
-
-            let (term, value) = match term.split_once('=') {
-                Some((term, "?")) => (term, None),
-                Some((term, value)) => match parse_hex(value) {
-                    Ok(value) => (term, Some(value)),
-                    Err(e) => return Err(Error::parse(path, e))?,
-                },
-                None => (term, Some(1u64)),
-            };
+        let event = PmuEvent::read(self.pmu.join("events").join(event))?;
 
+        for (term, value) in event.terms() {
             let field = match self.fields.get_mut(term) {
                 Some(field) => field,
-                None => return Err(Error::unknown_field(path, term.to_owned()))?,
+                None => {
+                    return Err(Error::unknown_field(
+                        event.path().to_owned(),
+                        term.to_owned(),
+                    ))?
+                }
             };
 
             field.value = value;
         }
 
-        self.event = Some(path);
+        self.event = Some(event);
         Ok(self)
     }
 
@@ -344,7 +259,7 @@ impl DynamicBuilder {
             .get_mut(field)
             .ok_or_else(|| Error::new(ErrorData::UnknownField(field.to_owned())))?;
 
-        if !field.bits.validate(value) {
+        if !field.format.validate(value) {
             return Err(Error::new(ErrorData::ValueTooLarge));
         }
 
@@ -365,19 +280,20 @@ impl DynamicBuilder {
         };
 
         for (name, field) in self.fields.iter() {
-            let target = match field.dest {
-                FieldDest::Config => &mut dynamic.config,
-                FieldDest::Config1 => &mut dynamic.config1,
-                FieldDest::Config2 => &mut dynamic.config2,
+            let target = match field.format.target() {
+                FormatTarget::Config => &mut dynamic.config,
+                FormatTarget::Config1 => &mut dynamic.config1,
+                FormatTarget::Config2 => &mut dynamic.config2,
             };
 
-            let mask = field.bits.mask();
             let value = match field.value {
-                Some(value) => (value << field.bits.shift()) & mask,
+                Some(value) => field.format.encode(value),
                 None => return Err(MissingParameterError::new(name.to_owned())),
             };
 
-            *target &= !mask;
+            // Some PMUs have fields that overlap (e.g. `ldlat` and
+            // `offcore_rsp` on Intel). Combining them means that the result
+            // does not depend on the order that the fields are visited in.
             *target |= value;
         }
 
@@ -399,20 +315,10 @@ impl DynamicBuilder {
         self.fields.iter().map(|(key, field)| (&**key, field.value))
     }
 
-    fn property(&self, suffix: &str) -> io::Result<(PathBuf, Option<String>)> {
-        let event = match &self.event {
-            Some(event) => event,
-            None => return Err(Error::new(ErrorData::MissingEvent))?,
-        };
-
-        let path = event.with_extension(suffix);
-        let content = match std::fs::read_to_string(&path) {
-            Ok(content) => Some(content),
-            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
-            Err(e) => return Err(e),
-        };
-
-        Ok((path, content))
+    fn current_event(&self) -> Result<&PmuEvent, DynamicBuilderError> {
+        self.event
+            .as_ref()
+            .ok_or_else(|| Error::new(ErrorData::MissingEvent))
     }
 
     /// Read the scale factor of the event.
@@ -424,17 +330,7 @@ impl DynamicBuilder {
     /// If [`event`](Self::event) has not been specified then this method will
     /// always return an error.
     pub fn scale(&self) -> io::Result<Option<f64>> {
-        let (path, content) = match self.property("scale")? {
-            (_, None) => return Ok(None),
-            (path, Some(content)) => (path, content),
-        };
-
-        let scale: f64 = match content.trim().parse() {
-            Ok(scale) => scale,
-            Err(e) => return Err(Error::parse_float(path, e))?,
-        };
-
-        Ok(Some(scale))
+        Ok(self.current_event()?.scale())
     }
 
     /// Read the unit of the event.
@@ -445,22 +341,10 @@ impl DynamicBuilder {
     /// If [`event`](Self::event) has not been specified then this method will
     /// always return an error.
     pub fn unit(&self) -> io::Result<Option<String>> {
-        Ok(match self.property("unit")? {
-            (_, Some(mut content)) => {
-                let trimmed = content.trim_end();
-                content.truncate(trimmed.len());
-                Some(content)
-            }
-            (_, None) => None,
-        })
+        Ok(self.current_event()?.unit().map(|unit| unit.to_owned()))
     }
 }
 
-fn parse_hex(text: &str) -> Result<u64, std::num::ParseIntError> {
-    let text = text.strip_prefix("0x").unwrap_or(text);
-    u64::from_str_radix(text, 16)
-}
-
 impl fmt::Debug for DynamicBuilder {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut dbg = f.debug_struct("DynamicBuilder");
@@ -468,7 +352,7 @@ impl fmt::Debug for DynamicBuilder {
         dbg.field("pmu", &self.pmu);
 
         if let Some(event) = &self.event {
-            dbg.field("event", &event);
+            dbg.field("event", &event.path());
         }
 
         dbg.field("fields", &DebugFields(self));
@@ -512,6 +396,9 @@ enum ErrorData {
     /// The value was larger than the maximum value supported by the field.
     ValueTooLarge,
 
+    /// A format field contained an invalid range of bits.
+    InvalidBits(String),
+
     /// We were unable to parse one of the integers within the config file.
     InvalidInteger(std::num::ParseIntError),
 
@@ -548,39 +435,49 @@ impl DynamicBuilderError {
         Self { data, path: None }
     }
 
-    fn missing_colon(path: PathBuf) -> Self {
+    /// Attach the path of the file that the error occurred in.
+    pub(crate) fn with_path(self, path: PathBuf) -> Self {
         Self {
-            data: ErrorData::MissingColon,
             path: Some(path),
+            ..self
         }
     }
 
-    fn parse(path: PathBuf, e: std::num::ParseIntError) -> Self {
+    pub(crate) fn missing_colon() -> Self {
+        Self::new(ErrorData::MissingColon)
+    }
+
+    pub(crate) fn invalid_integer(e: std::num::ParseIntError) -> Self {
+        Self::new(ErrorData::InvalidInteger(e))
+    }
+
+    pub(crate) fn invalid_bits(range: String) -> Self {
+        Self::new(ErrorData::InvalidBits(range))
+    }
+
+    pub(crate) fn parse(path: PathBuf, e: std::num::ParseIntError) -> Self {
         Self {
             data: ErrorData::InvalidInteger(e),
             path: Some(path),
         }
     }
 
-    fn parse_float(path: PathBuf, e: std::num::ParseFloatError) -> Self {
+    pub(crate) fn parse_float(path: PathBuf, e: std::num::ParseFloatError) -> Self {
         Self {
             data: ErrorData::InvalidFloat(e),
             path: Some(path),
         }
     }
 
-    fn invalid_field_name(path: PathBuf) -> Self {
+    pub(crate) fn invalid_field_name(path: PathBuf) -> Self {
         Self {
             data: ErrorData::NonUtf8FieldName,
             path: Some(path),
         }
     }
 
-    fn unknown_target(path: PathBuf, target: String) -> Self {
-        Self {
-            data: ErrorData::UnknownTarget(target),
-            path: Some(path),
-        }
+    pub(crate) fn unknown_target(target: String) -> Self {
+        Self::new(ErrorData::UnknownTarget(target))
     }
 
     fn unknown_field(path: PathBuf, field: String) -> Self {
@@ -597,6 +494,7 @@ impl fmt::Display for ErrorData {
             Self::UnknownTarget(field) => write!(f, "unknown target field `{field}`"),
             Self::UnknownField(field) => write!(f, "unknown field `{field}`"),
             Self::ValueTooLarge => write!(f, "value was too large for the field"),
+            Self::InvalidBits(range) => write!(f, "invalid bit range `{range}`"),
             Self::InvalidInteger(e) => e.fmt(f),
             Self::InvalidFloat(e) => e.fmt(f),
             Self::NonUtf8FieldName => write!(f, "field name contained invalid UTF-8"),
@@ -696,11 +594,6 @@ mod tests {
         Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data"))
     }
 
-    #[test]
-    fn parse_hex_sanity() {
-        assert_eq!(parse_hex("0xFFFFFFFF"), Ok(0xFFFFFFFF));
-    }
-
     #[test]
     #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
     fn dynamic_msr_event() {
@@ -0,0 +1,585 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+use crate::events::{KProbe, Tracepoint, UProbe};
+use crate::Counter;
+
+used_in_docs!(Counter);
+used_in_docs!(KProbe);
+used_in_docs!(Tracepoint);
+used_in_docs!(UProbe);
+
+/// A filter that can be applied to a counter using [`Counter::set_filter`].
+///
+/// This trait is sealed and is only implemented for [`TracepointFilter`] and
+/// [`AddressFilter`].
+pub trait Filter: fmt::Display + sealed::Sealed {}
+
+impl Filter for TracepointFilter {}
+impl Filter for AddressFilter {}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::TracepointFilter {}
+    impl Sealed for super::AddressFilter {}
+}
+
+/// A filter expression for a [`Tracepoint`] event.
+///
+/// Tracepoint (and kprobe/uprobe) events can be filtered in the kernel using
+/// an ftrace filter expression over the fields of the event. This type
+/// validates such an expression against the fields listed within the
+/// tracepoint's `format` file so that typos in field names are caught before
+/// the filter is handed to the kernel.
+///
+/// Filters are applied to a counter using [`Counter::set_filter`]. See the
+/// [kernel docs][0] for a description of the filter syntax.
+///
+/// # Example
+/// ```
+/// # fn run() -> std::io::Result<()> {
+/// use perf_event::events::{Tracepoint, TracepointFilter};
+/// use perf_event::Builder;
+///
+/// let filter = TracepointFilter::new("sched/sched_switch", "prev_pid != 0 && next_pid != 0")?;
+/// let mut counter = Builder::new(Tracepoint::with_name("sched/sched_switch")?)
+///     .any_pid()
+///     .one_cpu(0)
+///     .build()?;
+/// counter.set_filter(&filter)?;
+/// # Ok(())
+/// # }
+/// # let _ = run();
+/// ```
+///
+/// [0]: https://docs.kernel.org/trace/events.html#event-filtering
+#[derive(Clone, Debug)]
+pub struct TracepointFilter {
+    expr: String,
+}
+
+impl TracepointFilter {
+    /// Create a new filter for the tracepoint `tracepoint`, validating that
+    /// `expr` only refers to fields that are defined by that tracepoint.
+    ///
+    /// `tracepoint` can be either
+    /// - an absolute path to the tracepoint directory, or,
+    /// - the name of a tracepoint under `/sys/kernel/debug/tracing/events`.
+    ///
+    /// # Errors
+    /// Any IO errors from reading the `format` file will be returned directly.
+    /// Any errors in the filter expression will have kind
+    /// [`io::ErrorKind::InvalidInput`] with the inner error being a
+    /// [`TracepointFilterError`].
+    pub fn new(tracepoint: impl AsRef<Path>, expr: &str) -> io::Result<Self> {
+        let mut path = PathBuf::from("/sys/kernel/debug/tracing/events");
+        path.push(tracepoint.as_ref());
+        path.push("format");
+
+        let format = std::fs::read_to_string(&path)?;
+        let fields = parse_format_fields(&format);
+
+        Self::with_fields(fields.iter().map(|field| field.as_str()), expr)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Create a new filter, validating that `expr` only refers to the fields
+    /// within `fields`.
+    ///
+    /// The generic fields that the kernel makes available for all tracepoints
+    /// (`CPU`, `cpu`, `COMM`, and `comm`) are always allowed.
+    pub fn with_fields<'a, I>(fields: I, expr: &str) -> Result<Self, TracepointFilterError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut fields: HashSet<&str> = fields.into_iter().collect();
+        fields.extend(["CPU", "cpu", "COMM", "comm"]);
+
+        validate_filter(expr, &fields)?;
+
+        Ok(Self {
+            expr: expr.to_owned(),
+        })
+    }
+
+    /// Create a new filter without doing any validation.
+    pub fn new_unchecked(expr: impl Into<String>) -> Self {
+        Self { expr: expr.into() }
+    }
+
+    /// The filter expression.
+    pub fn as_str(&self) -> &str {
+        &self.expr
+    }
+}
+
+impl fmt::Display for TracepointFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+/// An error in a [`TracepointFilter`] expression.
+#[derive(Clone, Debug)]
+pub struct TracepointFilterError {
+    kind: FilterErrorKind,
+    offset: usize,
+}
+
+#[derive(Clone, Debug)]
+enum FilterErrorKind {
+    UnknownField(String),
+    UnexpectedChar(char),
+    UnterminatedString,
+    UnbalancedParens,
+}
+
+impl TracepointFilterError {
+    fn new(kind: FilterErrorKind, offset: usize) -> Self {
+        Self { kind, offset }
+    }
+
+    /// The name of the unknown field referenced by the filter, if that is
+    /// what caused this error.
+    pub fn unknown_field(&self) -> Option<&str> {
+        match &self.kind {
+            FilterErrorKind::UnknownField(field) => Some(field),
+            _ => None,
+        }
+    }
+
+    /// The byte offset within the filter expression at which the error
+    /// occurred.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for TracepointFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FilterErrorKind::UnknownField(field) => write!(f, "unknown field `{field}`"),
+            FilterErrorKind::UnexpectedChar(c) => write!(f, "unexpected character `{c}`"),
+            FilterErrorKind::UnterminatedString => f.write_str("unterminated string"),
+            FilterErrorKind::UnbalancedParens => f.write_str("unbalanced parentheses"),
+        }?;
+
+        write!(f, " at offset {} in tracepoint filter", self.offset)
+    }
+}
+
+impl std::error::Error for TracepointFilterError {}
+
+/// Parse the names of the fields out of a tracepoint `format` file.
+fn parse_format_fields(format: &str) -> Vec<String> {
+    // Field lines look like this:
+    //     field:unsigned short common_type;	offset:0;	size:2;	signed:0;
+    //     field:char prev_comm[16];	offset:8;	size:16;	signed:0;
+    format
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("field:"))
+        .filter_map(|decl| decl.split(';').next())
+        .filter_map(|decl| {
+            let decl = match decl.find('[') {
+                Some(idx) => &decl[..idx],
+                None => decl,
+            };
+
+            decl.split_whitespace().last()
+        })
+        .map(|name| name.trim_start_matches('*').to_owned())
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Value,
+    Compare,
+    Logical,
+    OpenParen,
+    CloseParen,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(usize, Token<'_>)>, TracepointFilterError> {
+    const OPERATORS: &[(&str, Token<'static>)] = &[
+        ("&&", Token::Logical),
+        ("||", Token::Logical),
+        ("==", Token::Compare),
+        ("!=", Token::Compare),
+        ("<=", Token::Compare),
+        (">=", Token::Compare),
+        ("<", Token::Compare),
+        (">", Token::Compare),
+        ("&", Token::Compare),
+        ("~", Token::Compare),
+        ("!", Token::Logical),
+        ("(", Token::OpenParen),
+        (")", Token::CloseParen),
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = expr;
+
+    loop {
+        rest = rest.trim_start();
+        let offset = expr.len() - rest.len();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+
+        if c == '"' || c == '\'' {
+            let mut escaped = false;
+            let end = rest[1..]
+                .char_indices()
+                .find(|&(_, ch)| {
+                    let end = !escaped && ch == c;
+                    escaped = !escaped && ch == '\\';
+                    end
+                })
+                .map(|(idx, _)| idx + 2)
+                .ok_or_else(|| {
+                    TracepointFilterError::new(FilterErrorKind::UnterminatedString, offset)
+                })?;
+
+            tokens.push((offset, Token::Value));
+            rest = &rest[end..];
+            continue;
+        }
+
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || "_.*?[]-".contains(ch)))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+
+            if c.is_ascii_digit() {
+                tokens.push((offset, Token::Value));
+            } else {
+                tokens.push((offset, Token::Ident(word)));
+            }
+
+            rest = &rest[end..];
+            continue;
+        }
+
+        // Glob patterns don't need to be quoted when compared against strings.
+        if "*?[]-".contains(c) {
+            let end = rest
+                .find(|ch: char| ch.is_whitespace() || "()&|".contains(ch))
+                .unwrap_or(rest.len());
+            tokens.push((offset, Token::Value));
+            rest = &rest[end..];
+            continue;
+        }
+
+        match OPERATORS.iter().find(|(op, _)| rest.starts_with(op)) {
+            Some(&(op, token)) => {
+                tokens.push((offset, token));
+                rest = &rest[op.len()..];
+            }
+            None => {
+                return Err(TracepointFilterError::new(
+                    FilterErrorKind::UnexpectedChar(c),
+                    offset,
+                ))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn validate_filter(expr: &str, fields: &HashSet<&str>) -> Result<(), TracepointFilterError> {
+    let tokens = tokenize(expr)?;
+    let mut depth = 0usize;
+
+    for (index, &(offset, token)) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParen => depth += 1,
+            Token::CloseParen => {
+                depth = depth.checked_sub(1).ok_or_else(|| {
+                    TracepointFilterError::new(FilterErrorKind::UnbalancedParens, offset)
+                })?;
+            }
+            // Only identifiers on the left hand side of a comparison are field
+            // names. Unquoted identifiers on the right hand side are string
+            // values.
+            Token::Ident(name) => {
+                let is_lhs = matches!(tokens.get(index + 1), Some((_, Token::Compare)));
+                if is_lhs && !fields.contains(name) {
+                    return Err(TracepointFilterError::new(
+                        FilterErrorKind::UnknownField(name.to_owned()),
+                        offset,
+                    ));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if depth != 0 {
+        return Err(TracepointFilterError::new(
+            FilterErrorKind::UnbalancedParens,
+            expr.len(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// An address range filter for PMUs that support them.
+///
+/// Some PMUs that write to the AUX area (e.g. Intel PT or ARM CoreSight) can
+/// restrict tracing to specific address ranges within either the kernel or a
+/// specific object file. The number of filters supported is reported by the
+/// PMU in its `nr_addr_filters` file in sysfs.
+///
+/// There are three kinds of filters:
+/// - [`filter`] will only trace within the given address range,
+/// - [`start`] will begin tracing when the address range is executed, and,
+/// - [`stop`] will stop tracing when the address range is executed.
+///
+/// Filters are applied to a counter using [`Counter::set_filter`].
+///
+/// # Example
+/// ```
+/// use perf_event::events::{AddressFilter, AddressRange};
+///
+/// let mut filter = AddressFilter::new();
+/// filter
+///     .filter(AddressRange::new(0x1000, 0x200).object("/usr/bin/ls"))
+///     .stop(AddressRange::at(0x4000).object("/usr/bin/ls"));
+///
+/// assert_eq!(
+///     filter.to_string(),
+///     "filter 0x1000/0x200@/usr/bin/ls,stop 0x4000@/usr/bin/ls"
+/// );
+/// ```
+///
+/// [`filter`]: Self::filter
+/// [`start`]: Self::start
+/// [`stop`]: Self::stop
+#[derive(Clone, Debug, Default)]
+pub struct AddressFilter {
+    entries: Vec<(AddressFilterAction, AddressRange)>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum AddressFilterAction {
+    Filter,
+    Start,
+    Stop,
+}
+
+impl AddressFilter {
+    /// Create a new, empty, address filter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only trace within `range`.
+    ///
+    /// Note that the kernel requires a non-zero size for ranges used as a
+    /// filter.
+    pub fn filter(&mut self, range: impl Into<AddressRange>) -> &mut Self {
+        self.push(AddressFilterAction::Filter, range.into())
+    }
+
+    /// Start tracing when execution reaches `range`.
+    pub fn start(&mut self, range: impl Into<AddressRange>) -> &mut Self {
+        self.push(AddressFilterAction::Start, range.into())
+    }
+
+    /// Stop tracing when execution reaches `range`.
+    pub fn stop(&mut self, range: impl Into<AddressRange>) -> &mut Self {
+        self.push(AddressFilterAction::Stop, range.into())
+    }
+
+    /// The number of filters that have been added.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no filters have been added.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn push(&mut self, action: AddressFilterAction, range: AddressRange) -> &mut Self {
+        self.entries.push((action, range));
+        self
+    }
+}
+
+impl fmt::Display for AddressFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (action, range)) in self.entries.iter().enumerate() {
+            if index != 0 {
+                f.write_str(",")?;
+            }
+
+            let action = match action {
+                AddressFilterAction::Filter => "filter",
+                AddressFilterAction::Start => "start",
+                AddressFilterAction::Stop => "stop",
+            };
+
+            write!(f, "{} {}", action, range)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An address range used within an [`AddressFilter`].
+///
+/// Without an object file the addresses refer to kernel addresses. With an
+/// object file they are offsets within that file.
+///
+/// Note that the kernel splits filters on whitespace and commas so object
+/// paths containing those characters cannot be used.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AddressRange {
+    start: u64,
+    size: u64,
+    object: Option<PathBuf>,
+}
+
+impl AddressRange {
+    /// Create a range covering `size` bytes starting at `start`.
+    pub fn new(start: u64, size: u64) -> Self {
+        Self {
+            start,
+            size,
+            object: None,
+        }
+    }
+
+    /// Create a range that covers a single address.
+    pub fn at(addr: u64) -> Self {
+        Self::new(addr, 0)
+    }
+
+    /// Make the range relative to the object file at `path`.
+    pub fn object(mut self, path: impl Into<PathBuf>) -> Self {
+        self.object = Some(path.into());
+        self
+    }
+}
+
+impl From<Range<u64>> for AddressRange {
+    fn from(range: Range<u64>) -> Self {
+        Self::new(range.start, range.end.saturating_sub(range.start))
+    }
+}
+
+impl fmt::Display for AddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.start)?;
+
+        if self.size != 0 {
+            write!(f, "/{:#x}", self.size)?;
+        }
+
+        if let Some(object) = &self.object {
+            write!(f, "@{}", object.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHED_SWITCH: &str = "\
+name: sched_switch
+ID: 316
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:char prev_comm[16];\toffset:8;\tsize:16;\tsigned:0;
+\tfield:pid_t prev_pid;\toffset:24;\tsize:4;\tsigned:1;
+\tfield:int prev_prio;\toffset:28;\tsize:4;\tsigned:1;
+\tfield:long prev_state;\toffset:32;\tsize:8;\tsigned:1;
+\tfield:char next_comm[16];\toffset:40;\tsize:16;\tsigned:0;
+\tfield:pid_t next_pid;\toffset:56;\tsize:4;\tsigned:1;
+\tfield:int next_prio;\toffset:60;\tsize:4;\tsigned:1;
+
+print fmt: \"prev_comm=%s prev_pid=%d\", REC->prev_comm, REC->prev_pid
+";
+
+    fn filter(expr: &str) -> Result<TracepointFilter, TracepointFilterError> {
+        let fields = parse_format_fields(SCHED_SWITCH);
+        TracepointFilter::with_fields(fields.iter().map(|f| f.as_str()), expr)
+    }
+
+    #[test]
+    fn format_fields() {
+        let fields = parse_format_fields(SCHED_SWITCH);
+        assert_eq!(
+            fields,
+            [
+                "common_type",
+                "common_flags",
+                "common_preempt_count",
+                "common_pid",
+                "prev_comm",
+                "prev_pid",
+                "prev_prio",
+                "prev_state",
+                "next_comm",
+                "next_pid",
+                "next_prio"
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_filters() {
+        filter("prev_pid != 0").unwrap();
+        filter("(prev_pid == 1 || next_pid == 1) && common_pid > 10").unwrap();
+        filter("prev_comm == \"bash\"").unwrap();
+        filter("prev_comm ~ \"ba*\" && !(next_prio < 100)").unwrap();
+        filter("next_comm == bash").unwrap();
+        filter("prev_state & 0x2").unwrap();
+        filter("CPU == 0").unwrap();
+    }
+
+    #[test]
+    fn unknown_field() {
+        let err = filter("prev_pid != 0 && nxt_pid == 1").unwrap_err();
+        assert_eq!(err.unknown_field(), Some("nxt_pid"));
+        assert_eq!(err.offset(), 17);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(filter("(prev_pid == 0").is_err());
+        assert!(filter("prev_pid == 0)").is_err());
+        assert!(filter("prev_comm == \"bash").is_err());
+        assert!(filter("prev_pid == 0 ; next_pid == 1").is_err());
+    }
+
+    #[test]
+    fn address_filter_format() {
+        let mut filter = AddressFilter::new();
+        filter
+            .filter(0xffffffff81000000..0xffffffff81001000)
+            .start(AddressRange::at(0x1234).object("/bin/true"));
+
+        assert_eq!(
+            filter.to_string(),
+            "filter 0xffffffff81000000/0x1000,start 0x1234@/bin/true"
+        );
+    }
+}
@@ -15,6 +15,10 @@ c_enum! {
     /// Each variant of this enum corresponds to a particular `PERF_COUNT_HW_`...
     /// value supported by the [`perf_event_open`][man] system call.
     ///
+    /// On hybrid processors these only count on one type of core. Use
+    /// [`OnPmu`](crate::events::OnPmu) or [`HybridCounters`](crate::HybridCounters)
+    /// to count on the others.
+    ///
     /// [man]: https://www.mankier.com/2/perf_event_open
     #[repr(transparent)]
     #[derive(Clone, Copy, Eq, PartialEq, Hash)]
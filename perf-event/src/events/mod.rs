@@ -18,6 +18,10 @@
 //!   read/write accesses to an address as well as execution of an instruction
 //!   address.
 //!
+//! Events can also be created from the text syntax used by the `perf` tool
+//! (e.g. `cycles:u` or `cpu/event=0x3c,umask=0x0/`) using [`parse`] and
+//! [`parse_list`].
+//!
 //! Linux supports many more kinds of events than this module covers, including
 //! events specific to particular make and model of processor, and events that
 //! are dynamically registered by drivers and kernel modules. If something you
@@ -39,7 +43,10 @@ pub mod x86;
 mod breakpoint;
 mod cache;
 mod dynamic;
+mod filter;
 mod hardware;
+mod on_pmu;
+mod parse;
 mod probe;
 mod raw;
 mod software;
@@ -49,6 +56,8 @@ mod util;
 /// Non-io errors emitted when constructing events.
 pub mod error {
     pub use crate::events::dynamic::{DynamicBuilderError, MissingParameterError};
+    pub use crate::events::filter::TracepointFilterError;
+    pub use crate::events::parse::ParseEventError;
 }
 
 pub use self::breakpoint::{Breakpoint, BreakpointAccess};
@@ -56,7 +65,10 @@ pub use self::breakpoint::{Breakpoint, BreakpointAccess};
 pub use self::cache::WhichCache;
 pub use self::cache::{Cache, CacheId, CacheOp, CacheResult};
 pub use self::dynamic::{Dynamic, DynamicBuilder};
+pub use self::filter::{AddressFilter, AddressRange, Filter, TracepointFilter};
 pub use self::hardware::Hardware;
+pub use self::on_pmu::OnPmu;
+pub use self::parse::{parse, parse_list, ParsedEvent};
 pub use self::probe::{KProbe, UProbe};
 pub use self::raw::Raw;
 pub use self::software::Software;
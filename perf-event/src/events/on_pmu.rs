@@ -0,0 +1,126 @@
+use std::io;
+use std::sync::Arc;
+
+use perf_event_open_sys::bindings::{self, perf_event_attr};
+
+use crate::events::{Cache, Event, EventData, Hardware, Raw};
+use crate::pmu::Pmu;
+
+used_in_docs!(Cache);
+used_in_docs!(Hardware);
+used_in_docs!(Raw);
+
+/// A [`Hardware`], [`Cache`], or [`Raw`] event that counts on a specific core
+/// PMU.
+///
+/// Hybrid processors (e.g. Intel Alder Lake and later) have a separate core
+/// PMU for each type of core, such as `cpu_core` and `cpu_atom`. A generic
+/// hardware or cache event only ever counts on one of them, so a process that
+/// runs on both kinds of cores will be missing some of its counts. Wrapping
+/// the event in `OnPmu` encodes the type of the PMU in the upper bits of
+/// `config` (the "extended type"), which tells the kernel which PMU to use.
+/// [`Raw`] events are instead opened with the type of the PMU, the same way
+/// that `perf` opens `cpu_atom/r1234/`.
+///
+/// To count on every type of core at once, use [`HybridCounters`].
+///
+/// This requires Linux 6.0 or later. For any other kind of event the PMU type
+/// is ignored.
+///
+/// # Example
+/// ```no_run
+/// use perf_event::events::{Hardware, OnPmu};
+/// use perf_event::pmu::Pmu;
+/// use perf_event::Builder;
+///
+/// let atom = Pmu::new("cpu_atom")?;
+/// let event = OnPmu::new(Hardware::CPU_CYCLES, &atom)?;
+/// let mut counter = Builder::new(event).build()?;
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`HybridCounters`]: crate::HybridCounters
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct OnPmu<E> {
+    event: E,
+    ty: u32,
+}
+
+impl<E: Event> OnPmu<E> {
+    /// Count `event` on `pmu`.
+    ///
+    /// # Errors
+    /// Returns any errors from reading the type of `pmu`.
+    pub fn new(event: E, pmu: &Pmu) -> io::Result<Self> {
+        Ok(Self::with_type(event, pmu.ty()?))
+    }
+
+    /// Count `event` on the PMU with type `ty`.
+    pub fn with_type(event: E, ty: u32) -> Self {
+        Self { event, ty }
+    }
+
+    /// The event being counted.
+    pub fn event(&self) -> &E {
+        &self.event
+    }
+
+    /// The type of the PMU that the event will count on.
+    pub fn pmu_type(&self) -> u32 {
+        self.ty
+    }
+}
+
+impl<E: Event> Event for OnPmu<E> {
+    fn update_attrs(self, attr: &mut perf_event_attr) {
+        self.update_attrs_with_data(attr);
+    }
+
+    fn update_attrs_with_data(self, attr: &mut perf_event_attr) -> Option<Arc<dyn EventData>> {
+        let data = self.event.update_attrs_with_data(attr);
+
+        match attr.type_ {
+            bindings::PERF_TYPE_HARDWARE | bindings::PERF_TYPE_HW_CACHE => {
+                attr.config &= bindings::PERF_HW_EVENT_MASK as u64;
+                attr.config |= (self.ty as u64) << bindings::PERF_PMU_TYPE_SHIFT;
+            }
+            bindings::PERF_TYPE_RAW => attr.type_ = self.ty,
+            _ => (),
+        }
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Software;
+
+    #[test]
+    fn extended_type() {
+        let mut attr = perf_event_attr::default();
+        OnPmu::with_type(Hardware::INSTRUCTIONS, 8).update_attrs(&mut attr);
+
+        assert_eq!(attr.type_, bindings::PERF_TYPE_HARDWARE);
+        assert_eq!(attr.config, 8 << 32 | Hardware::INSTRUCTIONS.0);
+    }
+
+    #[test]
+    fn raw_uses_pmu_type() {
+        let mut attr = perf_event_attr::default();
+        OnPmu::with_type(Raw::new(0x1234), 8).update_attrs(&mut attr);
+
+        assert_eq!(attr.type_, 8);
+        assert_eq!(attr.config, 0x1234);
+    }
+
+    #[test]
+    fn ignored_for_other_events() {
+        let mut attr = perf_event_attr::default();
+        OnPmu::with_type(Software::TASK_CLOCK, 8).update_attrs(&mut attr);
+
+        assert_eq!(attr.type_, bindings::PERF_TYPE_SOFTWARE);
+        assert_eq!(attr.config, Software::TASK_CLOCK.0);
+    }
+}
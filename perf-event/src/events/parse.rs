@@ -0,0 +1,741 @@
+use std::str::FromStr;
+use std::{fmt, io};
+
+use crate::events::{
+    Cache, CacheId, CacheOp, CacheResult, DynamicBuilder, Hardware, Raw, Software, Tracepoint,
+};
+use crate::pmu::Pmu;
+use crate::{Builder, Group, ReadFormat, SampleFlag, SampleSkid};
+
+used_in_docs!(Group);
+
+/// Parse a single event in the syntax used by the `perf` tool.
+///
+/// The following kinds of events are supported:
+/// - symbolic [`Hardware`] and [`Software`] events (e.g. `cycles`,
+///   `instructions`, `task-clock`, `page-faults`),
+/// - [`Cache`] events (e.g. `L1-dcache-load-misses`, `dTLB-loads`),
+/// - [`Raw`] events (e.g. `r01c2`),
+/// - tracepoints (e.g. `sched:sched_switch`),
+/// - PMU events with explicit terms (e.g. `cpu/event=0x3c,umask=0x0/`, or
+///   `msr/tsc/`), and,
+/// - PMU events by name (e.g. `slots`), which are looked up in every PMU.
+///
+/// Events may be followed by modifiers, separated by a `:` (or directly after
+/// the trailing `/` for PMU events):
+///
+/// | Modifier        | Meaning                                             |
+/// |-----------------|-----------------------------------------------------|
+/// | `u`, `k`, `h`   | Only count in user, kernel, or hypervisor mode.     |
+/// | `p`,`pp`,`ppp`  | Request increasingly precise sample IPs.            |
+/// | `G`, `H`        | Only count in the guest or on the host.             |
+/// | `D`             | Pin the event to the PMU.                           |
+/// | `S`             | Include the counter values in samples.              |
+///
+/// The returned builder is otherwise configured the same as one from
+/// [`Builder::new`]. Use [`parse_list`] to parse a comma-separated list of
+/// events or groups.
+///
+/// # Example
+/// ```
+/// use perf_event::events;
+///
+/// let builder = events::parse("task-clock:u")?;
+/// let counter = builder.build()?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+///
+/// `Builder` also implements [`FromStr`] using this function.
+pub fn parse(text: &str) -> Result<Builder<'static>, ParseEventError> {
+    let mut parser = Parser::new(text);
+    let builder = parser.event()?;
+
+    if !parser.is_empty() {
+        return Err(parser.error(ErrorData::TrailingInput));
+    }
+
+    Ok(builder)
+}
+
+/// Parse a comma-separated list of events in the syntax used by the `perf`
+/// tool.
+///
+/// Each item in the list is either a single event, using the syntax described
+/// in [`parse`], or a group of events written as `{a,b,...}`. Modifiers after
+/// a group (e.g. `{cycles,instructions}:u`) apply to every event within it.
+///
+/// The first builder within a group is the group leader. Its read format is
+/// set up so that it can be used with [`Builder::build_group`], the remaining
+/// builders should then be built with [`Builder::build_with_group`].
+///
+/// # Example
+/// ```
+/// use perf_event::events::{self, ParsedEvent};
+///
+/// for item in events::parse_list("cpu-clock,{task-clock,page-faults}:u")? {
+///     match item {
+///         ParsedEvent::Single(builder) => {
+///             let counter = builder.build()?;
+///         }
+///         ParsedEvent::Group(builders) => {
+///             let mut group = builders[0].build_group()?;
+///             for builder in &builders[1..] {
+///                 builder.build_with_group(&mut group)?;
+///             }
+///         }
+///     }
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub fn parse_list(text: &str) -> Result<Vec<ParsedEvent>, ParseEventError> {
+    let mut parser = Parser::new(text);
+    let mut items = Vec::new();
+
+    loop {
+        items.push(parser.item()?);
+
+        if parser.is_empty() {
+            break;
+        }
+        parser.expect(',')?;
+    }
+
+    Ok(items)
+}
+
+/// An item within an event list parsed by [`parse_list`].
+#[derive(Clone, Debug)]
+pub enum ParsedEvent {
+    /// A single event.
+    Single(Builder<'static>),
+
+    /// A group of events, written as `{a,b,...}`. The first builder is the
+    /// group leader.
+    Group(Vec<Builder<'static>>),
+}
+
+impl FromStr for Builder<'_> {
+    type Err = ParseEventError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+/// Characters that can appear within an event, PMU, or tracepoint name.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rest().is_empty()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseEventError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(ErrorData::Expected(c)))
+        }
+    }
+
+    fn take_while(&mut self, mut pred: impl FnMut(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn name(&mut self) -> Result<&'a str, ParseEventError> {
+        match self.take_while(is_name_char) {
+            "" => Err(self.error(ErrorData::ExpectedEvent)),
+            name => Ok(name),
+        }
+    }
+
+    fn error(&self, data: ErrorData) -> ParseEventError {
+        ParseEventError {
+            text: self.text.to_owned(),
+            offset: self.pos,
+            data,
+        }
+    }
+
+    fn item(&mut self) -> Result<ParsedEvent, ParseEventError> {
+        if !self.eat('{') {
+            return self.event().map(ParsedEvent::Single);
+        }
+
+        let mut builders = vec![self.event()?];
+        while self.eat(',') {
+            builders.push(self.event()?);
+        }
+        self.expect('}')?;
+
+        if self.eat(':') {
+            let modifiers = self.modifiers()?;
+            for builder in &mut builders {
+                modifiers.apply(builder);
+            }
+        }
+
+        builders[0].read_format(
+            ReadFormat::GROUP
+                | ReadFormat::TOTAL_TIME_ENABLED
+                | ReadFormat::TOTAL_TIME_RUNNING
+                | ReadFormat::ID,
+        );
+
+        Ok(ParsedEvent::Group(builders))
+    }
+
+    fn event(&mut self) -> Result<Builder<'static>, ParseEventError> {
+        let start = self.pos;
+        let name = self.name()?;
+        let is_pmu = self.eat('/');
+
+        let mut builder = if is_pmu {
+            self.pmu_event(name, start)?
+        } else if let Some(builder) = symbolic_event(name) {
+            builder
+        } else if let Some(builder) = self.sysfs_event(name, start)? {
+            builder
+        } else if self.eat(':') {
+            let event = self.name()?;
+            let tracepoint = Tracepoint::with_name(format!("{}/{}", name, event))
+                .map_err(|e| self.error_at(start, ErrorData::Io(e)))?;
+
+            Builder::new(tracepoint)
+        } else {
+            return Err(self.error_at(start, ErrorData::UnknownEvent(name.to_owned())));
+        };
+
+        // The modifiers for PMU events can come directly after the closing `/`.
+        if self.eat(':') || (is_pmu && self.peek().is_some_and(|c| c.is_ascii_alphabetic())) {
+            self.modifiers()?.apply(&mut builder);
+        }
+
+        Ok(builder)
+    }
+
+    fn error_at(&self, offset: usize, data: ErrorData) -> ParseEventError {
+        ParseEventError {
+            text: self.text.to_owned(),
+            offset,
+            data,
+        }
+    }
+
+    /// Parse the terms of a `pmu/terms/` event. The leading `pmu/` has
+    /// already been consumed.
+    fn pmu_event(&mut self, pmu: &str, start: usize) -> Result<Builder<'static>, ParseEventError> {
+        let terms = self.take_while(|c| c != '/');
+        self.expect('/')?;
+
+        let pmu = Pmu::new(pmu).map_err(|e| self.error_at(start, ErrorData::Io(e)))?;
+        let result = (|| -> io::Result<_> {
+            let mut builder = DynamicBuilder::with_zeroed_fields(pmu.path())?;
+
+            for term in terms.split(',').filter(|term| !term.is_empty()) {
+                match term.split_once('=') {
+                    Some((field, value)) => {
+                        let value = parse_number(value).ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("invalid value for term `{}`", field),
+                            )
+                        })?;
+
+                        builder.field(field, value)?;
+                    }
+                    None if pmu.event(term)?.is_some() => {
+                        builder.event(term)?;
+                    }
+                    None => {
+                        builder.field(term, 1)?;
+                    }
+                }
+            }
+
+            Ok(Builder::new(builder.build()?))
+        })();
+
+        result.map_err(|e| self.error_at(start, ErrorData::Io(e)))
+    }
+
+    /// Look for an event with the provided name in every PMU, the same way
+    /// that `perf` does for names it does not otherwise recognize.
+    fn sysfs_event(
+        &self,
+        name: &str,
+        start: usize,
+    ) -> Result<Option<Builder<'static>>, ParseEventError> {
+        let pmu = match find_pmu_event(Pmu::all(), name) {
+            Some(pmu) => pmu,
+            None => return Ok(None),
+        };
+
+        let result = (|| -> io::Result<Builder<'static>> {
+            let mut builder = DynamicBuilder::with_zeroed_fields(pmu.path())?;
+            builder.event(name)?;
+            Ok(Builder::new(builder.build()?))
+        })();
+
+        result
+            .map(Some)
+            .map_err(|e| self.error_at(start, ErrorData::Io(e)))
+    }
+
+    fn modifiers(&mut self) -> Result<Modifiers, ParseEventError> {
+        let start = self.pos;
+        let text = self.take_while(|c| c.is_ascii_alphabetic());
+        let mut modifiers = Modifiers::default();
+
+        for (index, c) in text.char_indices() {
+            match c {
+                'u' => modifiers.user = true,
+                'k' => modifiers.kernel = true,
+                'h' => modifiers.hv = true,
+                'p' => modifiers.precise += 1,
+                'G' => modifiers.guest = true,
+                'H' => modifiers.host = true,
+                'D' => modifiers.pinned = true,
+                'S' => modifiers.sample_read = true,
+                _ => return Err(self.error_at(start + index, ErrorData::UnknownModifier(c))),
+            }
+        }
+
+        if modifiers.precise > 3 {
+            return Err(self.error_at(start, ErrorData::TooPrecise));
+        }
+
+        Ok(modifiers)
+    }
+}
+
+#[derive(Default)]
+struct Modifiers {
+    user: bool,
+    kernel: bool,
+    hv: bool,
+    precise: u32,
+    guest: bool,
+    host: bool,
+    pinned: bool,
+    sample_read: bool,
+}
+
+impl Modifiers {
+    fn apply(&self, builder: &mut Builder) {
+        if self.user || self.kernel || self.hv {
+            builder.exclude_user(!self.user);
+            builder.exclude_kernel(!self.kernel);
+            builder.exclude_hv(!self.hv);
+        }
+
+        let skid = match self.precise {
+            0 => None,
+            1 => Some(SampleSkid::Constant),
+            2 => Some(SampleSkid::RequestZero),
+            _ => Some(SampleSkid::RequireZero),
+        };
+        if let Some(skid) = skid {
+            builder.precise_ip(skid);
+        }
+
+        if self.guest || self.host {
+            builder.exclude_host(!self.host);
+            builder.exclude_guest(!self.guest);
+        }
+
+        if self.pinned {
+            builder.pinned(true);
+        }
+
+        if self.sample_read {
+            builder.sample(SampleFlag::READ);
+        }
+    }
+}
+
+/// Look up the builtin event names that `perf` supports.
+fn symbolic_event(name: &str) -> Option<Builder<'static>> {
+    if let Some(event) = hardware_event(name) {
+        return Some(Builder::new(event));
+    }
+
+    if let Some(event) = software_event(name) {
+        return Some(Builder::new(event));
+    }
+
+    if let Some(event) = cache_event(name) {
+        return Some(Builder::new(event));
+    }
+
+    let config = name.strip_prefix('r')?;
+    if config.is_empty() {
+        return None;
+    }
+
+    u64::from_str_radix(config, 16)
+        .ok()
+        .map(|config| Builder::new(Raw::new(config)))
+}
+
+fn hardware_event(name: &str) -> Option<Hardware> {
+    Some(match name {
+        "cycles" | "cpu-cycles" => Hardware::CPU_CYCLES,
+        "instructions" => Hardware::INSTRUCTIONS,
+        "cache-references" => Hardware::CACHE_REFERENCES,
+        "cache-misses" => Hardware::CACHE_MISSES,
+        "branches" | "branch-instructions" => Hardware::BRANCH_INSTRUCTIONS,
+        "branch-misses" => Hardware::BRANCH_MISSES,
+        "bus-cycles" => Hardware::BUS_CYCLES,
+        "stalled-cycles-frontend" | "idle-cycles-frontend" => Hardware::STALLED_CYCLES_FRONTEND,
+        "stalled-cycles-backend" | "idle-cycles-backend" => Hardware::STALLED_CYCLES_BACKEND,
+        "ref-cycles" => Hardware::REF_CPU_CYCLES,
+        _ => return None,
+    })
+}
+
+fn software_event(name: &str) -> Option<Software> {
+    Some(match name {
+        "cpu-clock" => Software::CPU_CLOCK,
+        "task-clock" => Software::TASK_CLOCK,
+        "page-faults" | "faults" => Software::PAGE_FAULTS,
+        "context-switches" | "cs" => Software::CONTEXT_SWITCHES,
+        "cpu-migrations" | "migrations" => Software::CPU_MIGRATIONS,
+        "minor-faults" => Software::PAGE_FAULTS_MIN,
+        "major-faults" => Software::PAGE_FAULTS_MAJ,
+        "alignment-faults" => Software::ALIGNMENT_FAULTS,
+        "emulation-faults" => Software::EMULATION_FAULTS,
+        "dummy" => Software::DUMMY,
+        "bpf-output" => Software::BPF_OUTPUT,
+        "cgroup-switches" => Software::CGROUP_SWITCHES,
+        _ => return None,
+    })
+}
+
+/// Parse a cache event of the form `<cache>-<op>[-<result>]`, e.g.
+/// `L1-dcache-load-misses` or `LLC-stores`.
+fn cache_event(name: &str) -> Option<Cache> {
+    const CACHES: &[(&str, CacheId)] = &[
+        ("l1-dcache", CacheId::L1D),
+        ("l1-icache", CacheId::L1I),
+        ("llc", CacheId::LL),
+        ("dtlb", CacheId::DTLB),
+        ("itlb", CacheId::ITLB),
+        ("branch", CacheId::BPU),
+        ("node", CacheId::NODE),
+    ];
+
+    let name = name.to_ascii_lowercase();
+    let (which, rest) = CACHES.iter().find_map(|&(prefix, which)| {
+        let rest = name.strip_prefix(prefix)?.strip_prefix('-')?;
+        Some((which, rest))
+    })?;
+
+    let (operation, result) = match rest {
+        "loads" => (CacheOp::READ, CacheResult::ACCESS),
+        "stores" => (CacheOp::WRITE, CacheResult::ACCESS),
+        "prefetches" => (CacheOp::PREFETCH, CacheResult::ACCESS),
+        "load-misses" => (CacheOp::READ, CacheResult::MISS),
+        "store-misses" => (CacheOp::WRITE, CacheResult::MISS),
+        "prefetch-misses" => (CacheOp::PREFETCH, CacheResult::MISS),
+        _ => return None,
+    };
+
+    Some(Cache {
+        which,
+        operation,
+        result,
+    })
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[derive(Debug)]
+enum ErrorData {
+    /// The name was not a known event.
+    UnknownEvent(String),
+
+    /// A modifier character was not one that we support.
+    UnknownModifier(char),
+
+    /// More than 3 `p` modifiers were specified.
+    TooPrecise,
+
+    /// We expected a specific character but found something else.
+    Expected(char),
+
+    /// We expected an event name but found something else.
+    ExpectedEvent,
+
+    /// There was leftover input after a single event.
+    TrailingInput,
+
+    /// Looking up the event failed.
+    Io(io::Error),
+}
+
+/// Error for when an event string could not be parsed.
+///
+/// This is returned by [`parse`] and [`parse_list`].
+#[derive(Debug)]
+pub struct ParseEventError {
+    text: String,
+    offset: usize,
+    data: ErrorData,
+}
+
+impl ParseEventError {
+    /// The byte offset within the input at which the error occurred.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for ErrorData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownEvent(name) => write!(f, "unknown event `{name}`"),
+            Self::UnknownModifier(c) => write!(f, "unknown modifier `{c}`"),
+            Self::TooPrecise => write!(f, "at most 3 `p` modifiers are supported"),
+            Self::Expected(c) => write!(f, "expected `{c}`"),
+            Self::ExpectedEvent => write!(f, "expected an event name"),
+            Self::TrailingInput => write!(f, "unexpected input after the event"),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for ParseEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid event `{}` at offset {}: {}",
+            self.text, self.offset, self.data
+        )
+    }
+}
+
+impl std::error::Error for ParseEventError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.data {
+            ErrorData::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseEventError> for io::Error {
+    fn from(value: ParseEventError) -> Self {
+        match &value.data {
+            ErrorData::Io(e) => io::Error::new(e.kind(), value),
+            _ => io::Error::new(io::ErrorKind::InvalidInput, value),
+        }
+    }
+}
+
+/// Find the first PMU in `pmus` that has an event called `name`.
+///
+/// Errors from listing the PMUs or reading their events are treated as the
+/// event not being found. sysfs may be missing or only partially readable
+/// (e.g. within a container) and that should not prevent the name from being
+/// parsed as something else.
+fn find_pmu_event(pmus: io::Result<Vec<Pmu>>, name: &str) -> Option<Pmu> {
+    pmus.ok()?
+        .into_iter()
+        .find(|pmu| matches!(pmu.event(name), Ok(Some(_))))
+}
+
+#[cfg(test)]
+mod tests {
+    use perf_event_open_sys::bindings;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn attrs(text: &str) -> bindings::perf_event_attr {
+        *parse(text).unwrap().attrs()
+    }
+
+    #[test]
+    fn symbolic_events() {
+        let cycles = attrs("cycles");
+        assert_eq!(cycles.type_, bindings::PERF_TYPE_HARDWARE);
+        assert_eq!(cycles.config, bindings::PERF_COUNT_HW_CPU_CYCLES as u64);
+
+        let clock = attrs("task-clock");
+        assert_eq!(clock.type_, bindings::PERF_TYPE_SOFTWARE);
+        assert_eq!(clock.config, bindings::PERF_COUNT_SW_TASK_CLOCK as u64);
+
+        let cache = attrs("L1-dcache-load-misses");
+        assert_eq!(cache.type_, bindings::PERF_TYPE_HW_CACHE);
+        // L1D = 0, READ = 0, MISS = 1
+        assert_eq!(cache.config, 1 << 16);
+        assert_eq!(attrs("dTLB-stores").config, 0x3 | 0x1 << 8);
+
+        let raw = attrs("r01c2");
+        assert_eq!(raw.type_, bindings::PERF_TYPE_RAW);
+        assert_eq!(raw.config, 0x1c2);
+
+        // Hardware event names take priority over raw events.
+        assert_eq!(attrs("ref-cycles").type_, bindings::PERF_TYPE_HARDWARE);
+    }
+
+    #[test]
+    fn modifiers() {
+        let default = attrs("cycles");
+        assert_eq!(default.exclude_user(), 0);
+        assert_eq!(default.exclude_kernel(), 1);
+
+        let user = attrs("cycles:u");
+        assert_eq!(user.exclude_user(), 0);
+        assert_eq!(user.exclude_kernel(), 1);
+        assert_eq!(user.exclude_hv(), 1);
+
+        let kernel = attrs("instructions:kpp");
+        assert_eq!(kernel.exclude_user(), 1);
+        assert_eq!(kernel.exclude_kernel(), 0);
+        assert_eq!(kernel.precise_ip(), 2);
+
+        let other = attrs("r1a8:GDS");
+        assert_eq!(other.exclude_host(), 1);
+        assert_eq!(other.exclude_guest(), 0);
+        assert_eq!(other.pinned(), 1);
+        assert_eq!(other.sample_type, SampleFlag::READ.bits());
+
+        let host = attrs("cycles:H");
+        assert_eq!(host.exclude_host(), 0);
+        assert_eq!(host.exclude_guest(), 1);
+    }
+
+    #[test]
+    fn groups() {
+        let items = parse_list("cycles:k,{instructions,branches:k}:u,cs").unwrap();
+        assert_eq!(items.len(), 3);
+
+        assert!(matches!(&items[0], ParsedEvent::Single(_)));
+        assert!(matches!(&items[2], ParsedEvent::Single(_)));
+
+        let group = match &items[1] {
+            ParsedEvent::Group(group) => group,
+            item => panic!("expected a group, got {:?}", item),
+        };
+        assert_eq!(group.len(), 2);
+
+        let leader = group[0].attrs();
+        assert!(leader.read_format & ReadFormat::GROUP.bits() != 0);
+        assert_eq!(leader.exclude_kernel(), 1);
+        assert_eq!(leader.exclude_user(), 0);
+
+        // Group modifiers override the modifiers of the members.
+        let member = group[1].attrs();
+        assert_eq!(
+            member.config,
+            bindings::PERF_COUNT_HW_BRANCH_INSTRUCTIONS as u64
+        );
+        assert_eq!(member.exclude_kernel(), 1);
+        assert_eq!(member.exclude_user(), 0);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    fn pmu_events() {
+        if Pmu::new("msr").is_err() {
+            return;
+        }
+
+        let tsc = attrs("msr/tsc/u");
+        assert_eq!(tsc.type_, Pmu::new("msr").unwrap().ty().unwrap());
+        assert_eq!(tsc.exclude_kernel(), 1);
+        assert_eq!(attrs("msr/tsc/:k").exclude_kernel(), 0);
+
+        // Events can also be found by searching all PMUs.
+        let found = attrs("tsc:k");
+        assert_eq!(found.type_, tsc.type_);
+        assert_eq!(found.config, tsc.config);
+        assert_eq!(found.exclude_kernel(), 0);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| parse(text).unwrap_err();
+
+        assert_eq!(error("cycles:x").offset(), 7);
+        assert_eq!(error("cycles:pppp").offset(), 7);
+        assert_eq!(error("").offset(), 0);
+        assert_eq!(error("cycles,instructions").offset(), 6);
+        assert!(matches!(
+            error("not-an-event").data,
+            ErrorData::UnknownEvent(_)
+        ));
+        assert!(matches!(
+            error("missing-pmu/event=1/").data,
+            ErrorData::Io(_)
+        ));
+
+        assert!(parse_list("{cycles,instructions").is_err());
+        assert!(parse_list("cycles,").is_err());
+        assert!("cycles:z".parse::<Builder>().is_err());
+        assert!("cycles:u".parse::<Builder>().is_ok());
+    }
+
+    #[test]
+    fn sysfs_errors_are_not_found() {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(find_pmu_event(Err(missing), "evt1").is_none());
+
+        // A PMU whose events cannot be read is skipped over.
+        let broken = TempDir::new("broken-pmu");
+        std::fs::write(broken.path().join("events"), "").unwrap();
+
+        let pmus = vec![
+            Pmu::new(broken.path()).unwrap(),
+            Pmu::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/dyn-pmu")).unwrap(),
+        ];
+        assert!(pmus[0].event("evt1").is_err());
+        assert_eq!(find_pmu_event(Ok(pmus), "evt1").unwrap().name(), "dyn-pmu");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x3c"), Some(0x3c));
+        assert_eq!(parse_number("60"), Some(60));
+        assert_eq!(parse_number("0xZZ"), None);
+    }
+}
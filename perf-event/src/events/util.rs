@@ -1,7 +1,7 @@
-use std::num::ParseIntError;
-use std::path::Path;
+use std::io;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::{fmt, io};
+
+use crate::pmu::Pmu;
 
 /// Helper to read and cache the type of a dynamic perf PMU event.
 ///
@@ -50,43 +50,9 @@ impl CachedPmuType {
 
     #[cold]
     fn read(&self) -> io::Result<u32> {
-        let mut path = Path::new("/sys/bus/event_source/devices").to_path_buf();
-        path.push(self.name);
-        path.push("type");
-
-        let ty = std::fs::read_to_string(&path)?
-            .trim_end()
-            .parse()
-            .map_err(|e| {
-                io::Error::other(ParsePmuTypeError {
-                    name: self.name,
-                    error: e,
-                })
-            })?;
+        let ty = Pmu::new(self.name)?.ty()?;
 
         self.value.store(ty, Ordering::Relaxed);
         Ok(ty)
     }
 }
-
-#[derive(Debug, Clone)]
-struct ParsePmuTypeError {
-    name: &'static str,
-    error: ParseIntError,
-}
-
-impl fmt::Display for ParsePmuTypeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "type file for pmu `{}` contained invalid data",
-            self.name
-        )
-    }
-}
-
-impl std::error::Error for ParsePmuTypeError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        Some(&self.error)
-    }
-}
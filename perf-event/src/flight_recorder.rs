@@ -0,0 +1,322 @@
+use std::convert::TryInto;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use std::sync::atomic::Ordering;
+use std::{io, ptr};
+
+use crate::sampler::{atomic_load, set_output_paused};
+use crate::sys::bindings::{perf_event_header, perf_event_mmap_page};
+use crate::{check_errno_syscall, Counter, OwnedRecord};
+
+/// A ring buffer that the kernel continuously overwrites, keeping only the
+/// most recent records.
+///
+/// A [`Sampler`](crate::Sampler) consumes records as they are written and the
+/// kernel drops new records when the ring buffer is full. A `FlightRecorder`
+/// does the opposite: the ring buffer is mapped read-only, the kernel
+/// overwrites the oldest records when it runs out of space, and records are
+/// only read on demand via [`snapshot`](Self::snapshot). This makes it
+/// possible to cheaply keep a record of what happened just before some
+/// interesting event (e.g. a crash).
+///
+/// The counter must have been built with [`Builder::write_backward`] so that
+/// the kernel writes records in a way that allows them to be found by walking
+/// backwards from the most recent one.
+///
+/// # Example
+/// ```
+/// use perf_event::events::Software;
+/// use perf_event::{Builder, SampleFlag};
+///
+/// let mut recorder = Builder::new(Software::CPU_CLOCK)
+///     .sample_period(100_000)
+///     .sample(SampleFlag::TIME | SampleFlag::IP)
+///     .write_backward(true)
+///     .enabled(true)
+///     .build()?
+///     .flight_recorder(8192)?;
+///
+/// // ... do some work ...
+///
+/// for record in recorder.snapshot(16)? {
+///     println!("{:?}", record.parse_record());
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Builder::write_backward`]: crate::Builder::write_backward
+pub struct FlightRecorder {
+    counter: Counter,
+    mmap: memmap2::MmapRaw,
+}
+
+impl FlightRecorder {
+    pub(crate) fn new(counter: Counter, map_len: usize) -> io::Result<Self> {
+        if counter.attrs().write_backward() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a flight recorder requires a counter with write_backward enabled",
+            ));
+        }
+
+        let pagesize =
+            check_errno_syscall(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) })? as usize;
+        let len = pagesize
+            + map_len
+                .checked_next_power_of_two()
+                .unwrap_or((usize::MAX >> 1) + 1)
+                .max(pagesize);
+
+        // Mapping the buffer read-only tells the kernel to overwrite old
+        // records instead of waiting for us to consume them.
+        let mmap = memmap2::MmapOptions::new()
+            .len(len)
+            .map_raw_read_only(&counter.file)?;
+
+        Ok(Self { counter, mmap })
+    }
+
+    /// Convert this flight recorder back into a counter.
+    pub fn into_counter(self) -> Counter {
+        self.counter
+    }
+
+    /// Access the underlying counter for this flight recorder.
+    pub fn as_counter(&self) -> &Counter {
+        &self.counter
+    }
+
+    /// Mutably access the underlying counter for this flight recorder.
+    pub fn as_counter_mut(&mut self) -> &mut Counter {
+        &mut self.counter
+    }
+
+    /// Copy out the most recent records in the ring buffer.
+    ///
+    /// At most `max_records` records will be returned. They are returned in
+    /// the order they were written, so the most recent record is last.
+    ///
+    /// Output to the ring buffer is paused while the records are being copied
+    /// so that the kernel does not overwrite them. Records generated during
+    /// this time are lost.
+    pub fn snapshot(&mut self, max_records: usize) -> io::Result<Vec<OwnedRecord>> {
+        let paused = PausedOutput::new(&self.counter)?;
+        let records = self.read_records(max_records);
+        paused.resume()?;
+
+        Ok(records)
+    }
+
+    /// Walk the ring buffer from newest to oldest record.
+    ///
+    /// This must only be called while output is paused.
+    fn read_records(&self, max_records: usize) -> Vec<OwnedRecord> {
+        use std::mem::size_of;
+
+        let page = self.mmap.as_ptr() as *const perf_event_mmap_page;
+
+        // SAFETY:
+        // - page points to a valid instance of perf_event_mmap_page.
+        // - data_offset and data_size are never written to after the map is created.
+        let (data_offset, data_size) = unsafe {
+            (
+                ptr::read(ptr::addr_of!((*page).data_offset)),
+                ptr::read(ptr::addr_of!((*page).data_size)),
+            )
+        };
+        // ATOMICS:
+        // - The acquire load here syncronizes with the release store in the kernel and
+        //   ensures that all the data written to the ring buffer before data_head is
+        //   visible to this thread.
+        // SAFETY:
+        // - page points to a valid instance of perf_event_mmap_page.
+        let head = unsafe { atomic_load(ptr::addr_of!((*page).data_head), Ordering::Acquire) };
+
+        // SAFETY: perf_event_open guarantees that the data area is within the
+        //         memory mapping.
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                self.mmap.as_ptr().add(data_offset as usize),
+                data_size as usize,
+            )
+        };
+
+        let mut records = Vec::new();
+        for (offset, header) in BackwardRecords::new(data, head).take(max_records) {
+            let len = header.size as usize - size_of::<perf_event_header>();
+            let bytes = copy_wrapping(data, offset + size_of::<perf_event_header>() as u64, len);
+            records.push(OwnedRecord::new(
+                header,
+                bytes,
+                self.counter.config().clone(),
+            ));
+        }
+
+        records.reverse();
+        records
+    }
+}
+
+impl AsRawFd for FlightRecorder {
+    fn as_raw_fd(&self) -> RawFd {
+        self.counter.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for FlightRecorder {
+    fn into_raw_fd(self) -> RawFd {
+        self.counter.into_raw_fd()
+    }
+}
+
+/// Keeps output to a counter's ring buffer paused until it is dropped.
+struct PausedOutput<'a> {
+    counter: &'a Counter,
+}
+
+impl<'a> PausedOutput<'a> {
+    fn new(counter: &'a Counter) -> io::Result<Self> {
+        set_output_paused(counter, true)?;
+        Ok(Self { counter })
+    }
+
+    /// Resume output to the ring buffer, returning any error that occurred.
+    fn resume(self) -> io::Result<()> {
+        let result = set_output_paused(self.counter, false);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for PausedOutput<'_> {
+    fn drop(&mut self) {
+        let _ = set_output_paused(self.counter, false);
+    }
+}
+
+/// Iterator over the records in a ring buffer written backwards, starting
+/// with the newest record.
+///
+/// When writing backwards the kernel decrements `data_head` by the size of
+/// each record before writing it, so the newest record always starts at
+/// `data_head` and older records follow it in memory.
+struct BackwardRecords<'a> {
+    data: &'a [u8],
+    head: u64,
+    offset: u64,
+}
+
+impl<'a> BackwardRecords<'a> {
+    fn new(data: &'a [u8], head: u64) -> Self {
+        Self {
+            data,
+            head,
+            offset: head,
+        }
+    }
+}
+
+impl Iterator for BackwardRecords<'_> {
+    type Item = (u64, perf_event_header);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use std::mem::size_of;
+
+        let size = self.data.len() as u64;
+        let walked = self.offset.wrapping_sub(self.head);
+        if walked + size_of::<perf_event_header>() as u64 > size {
+            return None;
+        }
+
+        let bytes = copy_wrapping(self.data, self.offset, size_of::<perf_event_header>());
+        let bytes: [u8; size_of::<perf_event_header>()] = bytes.try_into().ok()?;
+        // SAFETY: perf_event_header is a packed C struct so it is valid to
+        //         copy arbitrary initialized memory into it.
+        let header: perf_event_header = unsafe { std::mem::transmute(bytes) };
+
+        // A zero-sized header means we have reached the part of the buffer
+        // that has never been written to. A record that extends past the end
+        // of the buffer has been partially overwritten.
+        if (header.size as usize) < size_of::<perf_event_header>()
+            || walked + header.size as u64 > size
+        {
+            return None;
+        }
+
+        let offset = self.offset;
+        self.offset = self.offset.wrapping_add(header.size as u64);
+        Some((offset, header))
+    }
+}
+
+/// Copy `len` bytes starting at position `offset` out of the ring buffer
+/// `data`, wrapping around the end of the buffer if needed.
+fn copy_wrapping(data: &[u8], offset: u64, len: usize) -> Vec<u8> {
+    let start = (offset % data.len() as u64) as usize;
+    let mut bytes = Vec::with_capacity(len);
+
+    let first = len.min(data.len() - start);
+    bytes.extend_from_slice(&data[start..start + first]);
+    bytes.extend_from_slice(&data[..len - first]);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ty: u32, len: u16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&ty.to_ne_bytes());
+        bytes.extend_from_slice(&0u16.to_ne_bytes());
+        bytes.extend_from_slice(&len.to_ne_bytes());
+        bytes.resize(len as usize, 0xAB);
+        bytes
+    }
+
+    /// Simulate the kernel writing records backwards into a ring buffer.
+    fn write_backward(data: &mut [u8], head: &mut u64, record: &[u8]) {
+        *head = head.wrapping_sub(record.len() as u64);
+        let size = data.len() as u64;
+        for (i, byte) in record.iter().enumerate() {
+            data[(head.wrapping_add(i as u64) % size) as usize] = *byte;
+        }
+    }
+
+    #[test]
+    fn walk_partially_filled() {
+        let mut data = vec![0u8; 128];
+        let mut head = 0u64;
+
+        write_backward(&mut data, &mut head, &record(1, 16));
+        write_backward(&mut data, &mut head, &record(2, 24));
+
+        let records: Vec<_> = BackwardRecords::new(&data, head)
+            .map(|(_, header)| header.type_)
+            .collect();
+        assert_eq!(records, [2, 1]);
+    }
+
+    #[test]
+    fn walk_overwritten() {
+        let mut data = vec![0u8; 64];
+        let mut head = 0u64;
+
+        for ty in 0..10 {
+            write_backward(&mut data, &mut head, &record(ty, 24));
+        }
+
+        // Only two records fit entirely, the third has been partially
+        // overwritten.
+        let records: Vec<_> = BackwardRecords::new(&data, head)
+            .map(|(_, header)| header.type_)
+            .collect();
+        assert_eq!(records, [9, 8]);
+    }
+
+    #[test]
+    fn copy_wraps() {
+        let data = [0, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(copy_wrapping(&data, 6, 4), [6, 7, 0, 1]);
+        assert_eq!(copy_wrapping(&data, 10, 3), [2, 3, 4]);
+    }
+}
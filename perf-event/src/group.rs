@@ -1,7 +1,7 @@
 use std::io;
 
 use crate::events::Software;
-use crate::{Builder, Counter, GroupData, ReadFormat};
+use crate::{Builder, Counter, GroupData, ReadFormat, UserGroup};
 
 /// A group of counters that can be managed as a unit.
 ///
@@ -182,6 +182,15 @@ impl Group {
         builder.build_with_group(self)
     }
 
+    /// Map the metadata page of each of `members`, returning a [`UserGroup`]
+    /// whose members can be read directly from userspace.
+    ///
+    /// Each of `members` must have been added to this group, otherwise an
+    /// error with kind [`io::ErrorKind::InvalidInput`] will be returned.
+    pub fn user_group(self, members: Vec<Counter>) -> io::Result<UserGroup> {
+        UserGroup::new(self, members)
+    }
+
     /// Return the values of all the `Counter`s in this `Group` as a
     /// [`GroupData`] value.
     ///
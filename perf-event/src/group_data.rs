@@ -71,24 +71,90 @@ used_in_docs!(ReadFormat);
 /// }
 /// # std::io::Result::Ok(())
 /// ```
+#[derive(Clone)]
 pub struct GroupData {
-    pub(crate) data: crate::data::ReadGroup<'static>,
-    // We need a set of values that we can actually reference for the index implementation.
-    values: Vec<u64>,
+    time_enabled: Option<u64>,
+    time_running: Option<u64>,
+    entries: Vec<GroupEntry>,
     should_skip: bool,
 }
 
 impl GroupData {
-    pub(crate) fn new(data: crate::data::ReadGroup<'static>) -> Self {
-        let values = data.entries().map(|entry| entry.value()).collect();
+    pub(crate) fn new(data: crate::data::ReadGroup<'_>) -> Self {
+        Self::from_parts(
+            data.time_enabled(),
+            data.time_running(),
+            data.entries()
+                .map(|entry| (entry.value(), entry.id(), entry.lost())),
+        )
+    }
+
+    /// Construct a `GroupData` directly from its component values.
+    ///
+    /// `entries` contains the value, kernel-assigned id, and lost count of
+    /// each counter in the group. Any values that are `None` will not be
+    /// present in the resulting `GroupData`.
+    pub(crate) fn from_parts<I>(
+        time_enabled: Option<u64>,
+        time_running: Option<u64>,
+        entries: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (u64, Option<u64>, Option<u64>)>,
+    {
+        let entries = entries
+            .into_iter()
+            .map(|(value, id, lost)| GroupEntry {
+                value,
+                id,
+                lost,
+                time_enabled,
+                time_running,
+            })
+            .collect();
 
         Self {
-            data,
-            values,
+            time_enabled,
+            time_running,
+            entries,
             should_skip: false,
         }
     }
 
+    /// Compute the change in each value since `previous` was read.
+    ///
+    /// Entries are matched up by id. Counters which were not present in
+    /// `previous` are reported with their full value and counters which are
+    /// no longer present are omitted.
+    pub(crate) fn delta(&self, previous: &GroupData) -> Self {
+        use crate::interval::delta;
+
+        let entries = self.entries.iter().map(|entry| {
+            let prev = previous.get_by_id(entry.id());
+            (
+                delta(entry.value, prev.map(|prev| prev.value)),
+                entry.id,
+                entry
+                    .lost
+                    .map(|lost| delta(lost, prev.and_then(|prev| prev.lost))),
+            )
+        });
+
+        let mut data = Self::from_parts(
+            self.time_enabled
+                .map(|time| delta(time, previous.time_enabled)),
+            self.time_running
+                .map(|time| delta(time, previous.time_running)),
+            entries,
+        );
+        data.should_skip = self.should_skip;
+        data
+    }
+
+    fn get_by_id(&self, id: u64) -> Option<&GroupEntry> {
+        self.entries.iter().find(|entry| entry.id == Some(id))
+    }
+
     /// Return the number of counters this `Counts` holds results for.
     pub fn len(&self) -> usize {
         self.iter().len()
@@ -107,7 +173,7 @@ impl GroupData {
     /// [`TOTAL_TIME_ENABLED`]: ReadFormat::TOTAL_TIME_ENABLED
     /// [`read_format`]: Builder::read_format
     pub fn time_enabled(&self) -> Option<Duration> {
-        self.data.time_enabled().map(Duration::from_nanos)
+        self.time_enabled.map(Duration::from_nanos)
     }
 
     /// The duration for which the group was scheduled on the CPU.
@@ -118,7 +184,23 @@ impl GroupData {
     /// [`TOTAL_TIME_RUNNING`]: ReadFormat::TOTAL_TIME_RUNNING
     /// [`read_format`]: Builder::read_format
     pub fn time_running(&self) -> Option<Duration> {
-        self.data.time_running().map(Duration::from_nanos)
+        self.time_running.map(Duration::from_nanos)
+    }
+
+    /// The percentage of the time the group was enabled that it was actually
+    /// running.
+    ///
+    /// This is the value that `perf stat` prints in parentheses after a
+    /// counter. It will be less than 100% if the group had to share the
+    /// underlying hardware with other counters. It is `None` if the group was
+    /// never enabled or if [`read_format`] did not include both
+    /// [`TOTAL_TIME_ENABLED`] and [`TOTAL_TIME_RUNNING`].
+    ///
+    /// [`TOTAL_TIME_ENABLED`]: ReadFormat::TOTAL_TIME_ENABLED
+    /// [`TOTAL_TIME_RUNNING`]: ReadFormat::TOTAL_TIME_RUNNING
+    /// [`read_format`]: Builder::read_format
+    pub fn percent_running(&self) -> Option<f64> {
+        crate::percent_running(self.time_enabled?, self.time_running?)
     }
 
     /// Get the entry for `member` in `self`, or `None` if `member` is not
@@ -143,7 +225,7 @@ impl GroupData {
     /// # std::io::Result::Ok(())
     /// ```
     pub fn get(&self, member: &Counter) -> Option<GroupEntry> {
-        self.data.get_by_id(member.id()).map(GroupEntry)
+        self.get_by_id(member.id()).copied()
     }
 
     /// Return an iterator over all entries in `self`.
@@ -173,7 +255,9 @@ impl GroupData {
     }
 
     fn iter_with_group(&self) -> GroupIter<'_> {
-        GroupIter(self.data.entries())
+        GroupIter {
+            iter: self.entries.iter(),
+        }
     }
 
     /// Mark that the first counter in this group is a `Group` and should not be
@@ -187,13 +271,10 @@ impl std::ops::Index<&Counter> for GroupData {
     type Output = u64;
 
     fn index(&self, ctr: &Counter) -> &u64 {
-        let (index, _) = self
-            .iter_with_group()
-            .enumerate()
-            .find(|(_, entry)| entry.id() == ctr.id())
-            .unwrap_or_else(|| panic!("group contained no counter with id {}", ctr.id()));
-
-        &self.values[index]
+        &self
+            .get_by_id(ctr.id())
+            .unwrap_or_else(|| panic!("group contained no counter with id {}", ctr.id()))
+            .value
     }
 }
 
@@ -233,22 +314,48 @@ impl<'a> IntoIterator for &'a GroupData {
 
 /// Individual entry for a counter returned by [`Group::read`].
 #[derive(Copy, Clone)]
-pub struct GroupEntry(pub(crate) crate::data::GroupEntry);
+pub struct GroupEntry {
+    value: u64,
+    id: Option<u64>,
+    lost: Option<u64>,
+    time_enabled: Option<u64>,
+    time_running: Option<u64>,
+}
 
 impl GroupEntry {
     /// The value of the counter.
     pub fn value(&self) -> u64 {
-        self.0.value()
+        self.value
     }
 
     /// The kernel-assigned unique id of the counter that was read.
     pub fn id(&self) -> u64 {
-        self.0.id().expect("group entry did not have an id")
+        self.id.expect("group entry did not have an id")
     }
 
     /// The number of lost samples for this event.
     pub fn lost(&self) -> Option<u64> {
-        self.0.lost()
+        self.lost
+    }
+
+    /// The value of the counter, scaled to estimate what it would have been
+    /// had the group been running for the entire time it was enabled.
+    ///
+    /// This will be `None` if the group never ran or if the group was not
+    /// read with both [`TOTAL_TIME_ENABLED`] and [`TOTAL_TIME_RUNNING`].
+    ///
+    /// [`TOTAL_TIME_ENABLED`]: ReadFormat::TOTAL_TIME_ENABLED
+    /// [`TOTAL_TIME_RUNNING`]: ReadFormat::TOTAL_TIME_RUNNING
+    pub fn scaled_value(&self) -> Option<u64> {
+        crate::scale(self.value(), self.time_enabled?, self.time_running?)
+    }
+
+    /// The percentage of the time the group was enabled that it was actually
+    /// running.
+    ///
+    /// See [`GroupData::percent_running`].
+    pub fn percent_running(&self) -> Option<f64> {
+        crate::percent_running(self.time_enabled?, self.time_running?)
     }
 }
 
@@ -268,25 +375,27 @@ impl fmt::Debug for GroupEntry {
 
 /// Iterator over the entries contained within [`GroupData`].
 #[derive(Clone)]
-pub struct GroupIter<'a>(crate::data::GroupIter<'a>);
+pub struct GroupIter<'a> {
+    iter: std::slice::Iter<'a, GroupEntry>,
+}
 
 impl<'a> Iterator for GroupIter<'a> {
     type Item = GroupEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(GroupEntry)
+        self.iter.next().copied()
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.size_hint()
+        self.iter.size_hint()
     }
 
     fn count(self) -> usize {
-        self.0.count()
+        self.iter.count()
     }
 
     fn nth(&mut self, n: usize) -> Option<Self::Item> {
-        self.0.nth(n).map(GroupEntry)
+        self.iter.nth(n).copied()
     }
 
     fn last(mut self) -> Option<Self::Item> {
@@ -296,17 +405,17 @@ impl<'a> Iterator for GroupIter<'a> {
 
 impl<'a> DoubleEndedIterator for GroupIter<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.0.next_back().map(GroupEntry)
+        self.iter.next_back().copied()
     }
 
     fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
-        self.0.nth_back(n).map(GroupEntry)
+        self.iter.nth_back(n).copied()
     }
 }
 
 impl<'a> ExactSizeIterator for GroupIter<'a> {
     fn len(&self) -> usize {
-        self.0.len()
+        self.iter.len()
     }
 }
 
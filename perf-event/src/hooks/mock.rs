@@ -0,0 +1,817 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::ffi::CStr;
+use std::io;
+use std::mem::size_of;
+use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_void};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use libc::{pid_t, size_t, ssize_t};
+use perf_event_open_sys::bindings::{
+    self, perf_event_attr, perf_event_header, perf_event_mmap_page, perf_event_query_bpf,
+};
+
+use super::Hooks;
+use crate::sampler::{atomic_load, atomic_store};
+use crate::{check_errno_syscall, ReadFormat, Sampler};
+
+/// The size of the memfd backing each mock counter.
+///
+/// This bounds the size of the mappings that can be created for a mock
+/// counter. The memfd is sparse so only the pages that are actually touched
+/// use any memory.
+const MAP_LEN: libc::off_t = 1 << 28;
+
+/// A simulated kernel implementing the [`Hooks`] trait.
+///
+/// `MockKernel` keeps track of every counter opened through it and answers
+/// the system calls and ioctls made by this crate the way the kernel would,
+/// except that all the values are under the control of the test. This makes
+/// it possible to test code using `perf_event` deterministically, on machines
+/// without a PMU, and without any special privileges.
+///
+/// Each counter is given a real file descriptor backed by a [memfd], so that
+/// counters can be closed, mapped with [`Counter::sampled`], and so on. Reads
+/// of the counter go through [`Hooks::read`] and are answered by the mock.
+///
+/// `MockKernel` is a cheap handle to shared state: install a clone of it with
+/// [`set_thread_hooks`] and keep the original around to control the
+/// simulation. Counters are identified by the ids returned by
+/// [`Counter::id`]. The methods that modify a counter panic if there is no
+/// counter with the given id.
+///
+/// The simulation covers:
+/// - **Counts:** set directly with [`set_count`], or scripted with
+///   [`script_counts`] so that successive reads return successive values.
+/// - **Time and multiplexing:** time only moves forward when [`advance`] is
+///   called. Enabled counters accumulate `time_enabled`, and `time_running`
+///   accumulates at the rate set by [`set_running_ratio`].
+/// - **Groups:** members are tracked along with their leader, ioctls with
+///   `PERF_IOC_FLAG_GROUP` apply to the whole group, and reading a counter with
+///   [`ReadFormat::GROUP`] returns the entire group. Reads with a buffer that
+///   is too small fail with `ENOSPC`.
+/// - **Errors:** [`set_attr_size`] makes `perf_event_open` fail with `E2BIG`
+///   for larger attrs, [`set_paranoid`] makes it fail with `EACCES` the way
+///   `perf_event_paranoid` would, and [`fail_next_open`] injects arbitrary
+///   errors.
+/// - **Sampling:** records can be written into the ring buffer of a [`Sampler`]
+///   with [`push_record`].
+///
+/// Note that the mock cannot tell when a counter's file descriptor is closed.
+/// Closed counters stay around, and remain part of their group, until their
+/// file descriptor is reused for a new counter.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+///
+/// use perf_event::events::Hardware;
+/// use perf_event::hooks::{clear_thread_hooks, set_thread_hooks, MockKernel};
+/// use perf_event::{Builder, ReadFormat};
+///
+/// let kernel = MockKernel::new();
+/// unsafe { set_thread_hooks(Box::new(kernel.clone())) };
+///
+/// let mut counter = Builder::new(Hardware::INSTRUCTIONS)
+///     .read_format(ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::TOTAL_TIME_RUNNING)
+///     .build()?;
+/// kernel.script_counts(counter.id(), [100, 250]);
+/// kernel.set_running_ratio(counter.id(), 0.5);
+///
+/// counter.enable()?;
+/// kernel.advance(Duration::from_millis(10));
+///
+/// assert_eq!(counter.read()?, 100);
+/// let data = counter.read_full()?;
+/// assert_eq!(data.count(), 250);
+/// assert_eq!(data.time_enabled(), Some(Duration::from_millis(10)));
+/// assert_eq!(data.time_running(), Some(Duration::from_millis(5)));
+///
+/// unsafe { clear_thread_hooks() };
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [memfd]: https://www.mankier.com/2/memfd_create
+/// [`Counter::id`]: crate::Counter::id
+/// [`Counter::sampled`]: crate::Counter::sampled
+/// [`set_thread_hooks`]: super::set_thread_hooks
+/// [`set_count`]: Self::set_count
+/// [`script_counts`]: Self::script_counts
+/// [`advance`]: Self::advance
+/// [`set_running_ratio`]: Self::set_running_ratio
+/// [`set_attr_size`]: Self::set_attr_size
+/// [`set_paranoid`]: Self::set_paranoid
+/// [`fail_next_open`]: Self::fail_next_open
+/// [`push_record`]: Self::push_record
+#[derive(Clone, Default)]
+pub struct MockKernel {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    events: Vec<Event>,
+    next_id: u64,
+    paranoid: i32,
+    attr_size: u32,
+    open_errors: VecDeque<c_int>,
+}
+
+struct Event {
+    fd: c_int,
+    id: u64,
+    attrs: perf_event_attr,
+    leader: Option<u64>,
+    members: Vec<u64>,
+    enabled: bool,
+    count: u64,
+    script: VecDeque<u64>,
+    lost: u64,
+    running_ratio: f64,
+    time_enabled: u64,
+    time_running: u64,
+    filter: Option<String>,
+    output: Option<u64>,
+    paused: bool,
+    bpf: Vec<u32>,
+}
+
+impl MockKernel {
+    /// Create a new mock kernel with no counters.
+    ///
+    /// By default every `perf_event_open` call that the real kernel would
+    /// accept succeeds, as if the caller were fully privileged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Simulate the `perf_event_paranoid` sysctl.
+    ///
+    /// - Above 0, opening counters that observe all processes on a CPU fails
+    ///   with `EACCES`.
+    /// - Above 1, opening counters that don't exclude the kernel fails with
+    ///   `EACCES`.
+    ///
+    /// The default is -1, which allows everything.
+    pub fn set_paranoid(&self, level: i32) {
+        self.state.borrow_mut().paranoid = level;
+    }
+
+    /// Set the size of the largest [`perf_event_attr`] supported by the
+    /// simulated kernel.
+    ///
+    /// Opening counters with larger attrs will fail with `E2BIG` after
+    /// writing `size` back into the attrs, the same as the real kernel.
+    pub fn set_attr_size(&self, size: u32) {
+        self.state.borrow_mut().attr_size = size;
+    }
+
+    /// Make the next call to `perf_event_open` fail with `errno`.
+    ///
+    /// Calling this multiple times queues up errors for subsequent calls.
+    pub fn fail_next_open(&self, errno: i32) {
+        self.state.borrow_mut().open_errors.push_back(errno);
+    }
+
+    /// Set the current count of counter `id`.
+    pub fn set_count(&self, id: u64, count: u64) {
+        self.with_event(id, |event| event.count = count);
+    }
+
+    /// Queue up values for counter `id` to return from its next reads.
+    ///
+    /// Each read takes the next value from the queue. Once the queue is
+    /// exhausted, reads keep returning the last value.
+    pub fn script_counts<I>(&self, id: u64, counts: I)
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        self.with_event(id, |event| event.script.extend(counts));
+    }
+
+    /// Set the fraction of the time that counter `id` is enabled for where it
+    /// is actually running on the simulated PMU.
+    ///
+    /// Values below 1 simulate multiplexing. The ratio is clamped to lie
+    /// between 0 and 1. Members of a group use the ratio of their leader, since
+    /// groups are always scheduled as a unit.
+    pub fn set_running_ratio(&self, id: u64, ratio: f64) {
+        self.with_event(id, |event| event.running_ratio = ratio.clamp(0.0, 1.0));
+    }
+
+    /// Set the number of lost samples reported for counter `id` when it is
+    /// read with [`ReadFormat::LOST`].
+    pub fn set_lost(&self, id: u64, lost: u64) {
+        self.with_event(id, |event| event.lost = lost);
+    }
+
+    /// Advance the simulated time by `duration`.
+    ///
+    /// Every enabled counter accumulates `duration` of enabled time and
+    /// `duration` multiplied by its running ratio of running time. Group
+    /// members only count as enabled while their leader is enabled.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = duration.as_nanos() as u64;
+        let mut state = self.state.borrow_mut();
+
+        for index in 0..state.events.len() {
+            let event = &state.events[index];
+            let leader = match event.leader {
+                Some(leader) => match state.find(leader) {
+                    Some(leader) => leader,
+                    None => continue,
+                },
+                None => event,
+            };
+
+            if !event.enabled || !leader.enabled {
+                continue;
+            }
+
+            let running = (nanos as f64 * leader.running_ratio) as u64;
+            let event = &mut state.events[index];
+            event.time_enabled += nanos;
+            event.time_running += running;
+        }
+    }
+
+    /// The ids of all counters opened so far, in the order they were opened.
+    pub fn ids(&self) -> Vec<u64> {
+        self.state.borrow().events.iter().map(|e| e.id).collect()
+    }
+
+    /// The attrs that counter `id` was opened with, as modified by any
+    /// subsequent ioctls.
+    pub fn attrs(&self, id: u64) -> Option<perf_event_attr> {
+        self.state.borrow().find(id).map(|e| e.attrs)
+    }
+
+    /// The id of the leader of the group that counter `id` is a member of.
+    ///
+    /// This returns `Some(id)` for group leaders and counters that are not
+    /// in a group.
+    pub fn leader(&self, id: u64) -> Option<u64> {
+        self.state
+            .borrow()
+            .find(id)
+            .map(|e| e.leader.unwrap_or(e.id))
+    }
+
+    /// Whether counter `id` is enabled.
+    pub fn is_enabled(&self, id: u64) -> Option<bool> {
+        self.state.borrow().find(id).map(|e| e.enabled)
+    }
+
+    /// The filter set on counter `id` with the `SET_FILTER` ioctl.
+    pub fn filter(&self, id: u64) -> Option<String> {
+        self.state.borrow().find(id).and_then(|e| e.filter.clone())
+    }
+
+    /// The id of the counter that counter `id` was redirected to with the
+    /// `SET_OUTPUT` ioctl.
+    pub fn output(&self, id: u64) -> Option<u64> {
+        self.state.borrow().find(id).and_then(|e| e.output)
+    }
+
+    /// Whether output to the ring buffer of counter `id` has been paused with
+    /// the `PAUSE_OUTPUT` ioctl.
+    pub fn is_paused(&self, id: u64) -> Option<bool> {
+        self.state.borrow().find(id).map(|e| e.paused)
+    }
+
+    /// Write a record into the ring buffer of `sampler`.
+    ///
+    /// `data` is the body of the record, following the
+    /// [`perf_event_header`]. It is padded with zeros to a multiple of 8
+    /// bytes, as the kernel does. The header is filled in using `ty`, `misc`,
+    /// and the padded length of `data`.
+    ///
+    /// # Errors
+    /// - An error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the
+    ///   record is too large to fit in a `perf_event_header`.
+    /// - An error if there is not enough free space left in the ring buffer.
+    pub fn push_record(
+        &self,
+        sampler: &Sampler,
+        ty: u32,
+        misc: u16,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let pagesize = check_errno_syscall(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) })? as u64;
+        let mmap = sampler.mmap();
+        let base = mmap.as_mut_ptr();
+        let page = base as *mut perf_event_mmap_page;
+
+        let padded = (data.len() + 7) & !7;
+        let size = size_of::<perf_event_header>() + padded;
+        let size: u16 = size.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record is too large to fit in a perf_event_header",
+            )
+        })?;
+
+        // SAFETY: the first page of the mapping is always a
+        //         perf_event_mmap_page, and the mock is the only thing acting as
+        //         the kernel for it.
+        unsafe {
+            // The mock doesn't know how large the mapping is until a record is
+            // written, so the data area is set up lazily.
+            if (*page).data_offset == 0 {
+                (*page).data_offset = pagesize;
+                (*page).data_size = mmap.len() as u64 - pagesize;
+            }
+        }
+
+        // SAFETY: page points to a valid instance of perf_event_mmap_page.
+        let (data_offset, data_size, head, tail) = unsafe {
+            (
+                (*page).data_offset,
+                (*page).data_size,
+                (*page).data_head,
+                atomic_load(std::ptr::addr_of!((*page).data_tail), Ordering::Acquire),
+            )
+        };
+
+        if head - tail + size as u64 > data_size {
+            return Err(io::Error::other("the ring buffer is full"));
+        }
+
+        let header = perf_event_header {
+            type_: ty,
+            misc,
+            size,
+        };
+        // SAFETY: perf_event_header is a plain C struct.
+        let header = unsafe {
+            std::slice::from_raw_parts(
+                &header as *const perf_event_header as *const u8,
+                size_of::<perf_event_header>(),
+            )
+        };
+
+        let padding = [0u8; 7];
+        let bytes = header
+            .iter()
+            .chain(data)
+            .chain(&padding[..padded - data.len()]);
+        for (offset, byte) in (head..).zip(bytes) {
+            // SAFETY: data_offset + data_size is within the mapping.
+            unsafe {
+                *base.add((data_offset + offset % data_size) as usize) = *byte;
+            }
+        }
+
+        // ATOMICS:
+        // - The release store here pairs with the acquire load in the sampler and
+        //   ensures that the record is visible before the new head.
+        // SAFETY: page points to a valid instance of perf_event_mmap_page.
+        unsafe {
+            atomic_store(
+                std::ptr::addr_of!((*page).data_head),
+                head + size as u64,
+                Ordering::Release,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn with_event<F>(&self, id: u64, func: F)
+    where
+        F: FnOnce(&mut Event),
+    {
+        let mut state = self.state.borrow_mut();
+        match state.find_mut(id) {
+            Some(event) => func(event),
+            None => panic!("no mock counter with id {}", id),
+        }
+    }
+
+    /// Run an ioctl against the counter with file descriptor `fd`, converting
+    /// the result into the form expected from the system call.
+    fn ioctl<F>(&mut self, fd: c_int, func: F) -> c_int
+    where
+        F: FnOnce(&mut State, usize) -> Result<(), c_int>,
+    {
+        let mut state = self.state.borrow_mut();
+        let result = match state.index(fd) {
+            Some(index) => func(&mut state, index),
+            None => Err(libc::EBADF),
+        };
+
+        match result {
+            Ok(()) => 0,
+            Err(errno) => fail(errno),
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            next_id: 0,
+            paranoid: -1,
+            attr_size: size_of::<perf_event_attr>() as u32,
+            open_errors: VecDeque::new(),
+        }
+    }
+}
+
+impl State {
+    fn find(&self, id: u64) -> Option<&Event> {
+        self.events.iter().find(|e| e.id == id)
+    }
+
+    fn find_mut(&mut self, id: u64) -> Option<&mut Event> {
+        self.events.iter_mut().find(|e| e.id == id)
+    }
+
+    fn index(&self, fd: c_int) -> Option<usize> {
+        self.events.iter().position(|e| e.fd == fd)
+    }
+
+    /// The ids of the events affected by an ioctl on the event at `index`.
+    fn targets(&self, index: usize, arg: c_uint) -> Vec<u64> {
+        let event = &self.events[index];
+        if arg & bindings::PERF_IOC_FLAG_GROUP == 0 {
+            return vec![event.id];
+        }
+
+        let leader = event.leader.unwrap_or(event.id);
+        match self.find(leader) {
+            Some(leader) => std::iter::once(leader.id)
+                .chain(leader.members.iter().copied())
+                .collect(),
+            None => vec![event.id],
+        }
+    }
+
+    fn set_enabled(&mut self, index: usize, arg: c_uint, enabled: bool) {
+        for id in self.targets(index, arg) {
+            if let Some(event) = self.find_mut(id) {
+                event.enabled = enabled;
+            }
+        }
+    }
+
+    fn open(
+        &mut self,
+        attrs: &mut perf_event_attr,
+        pid: pid_t,
+        cpu: c_int,
+        group_fd: c_int,
+        flags: c_ulong,
+    ) -> Result<c_int, c_int> {
+        if let Some(errno) = self.open_errors.pop_front() {
+            return Err(errno);
+        }
+
+        if attrs.size > self.attr_size {
+            attrs.size = self.attr_size;
+            return Err(libc::E2BIG);
+        }
+
+        if pid == -1 && cpu == -1 {
+            return Err(libc::EINVAL);
+        }
+
+        if self.paranoid > 0 && pid == -1 {
+            return Err(libc::EACCES);
+        }
+
+        if self.paranoid > 1 && attrs.exclude_kernel() == 0 {
+            return Err(libc::EACCES);
+        }
+
+        let leader = if group_fd == -1 {
+            None
+        } else {
+            let index = self.index(group_fd).ok_or(libc::EBADF)?;
+            let leader = &self.events[index];
+            if leader.leader.is_some() {
+                return Err(libc::EINVAL);
+            }
+
+            Some(leader.id)
+        };
+
+        let fd = memfd(flags & bindings::PERF_FLAG_FD_CLOEXEC as c_ulong != 0)?;
+
+        // Any event still using this fd must have been closed.
+        self.events.retain(|e| e.fd != fd);
+
+        self.next_id += 1;
+        let id = self.next_id;
+        self.events.push(Event {
+            fd,
+            id,
+            attrs: *attrs,
+            leader,
+            members: Vec::new(),
+            enabled: attrs.disabled() == 0,
+            count: 0,
+            script: VecDeque::new(),
+            lost: 0,
+            running_ratio: 1.0,
+            time_enabled: 0,
+            time_running: 0,
+            filter: None,
+            output: None,
+            paused: false,
+            bpf: Vec::new(),
+        });
+
+        if let Some(leader) = leader.and_then(|leader| self.find_mut(leader)) {
+            leader.members.push(id);
+        }
+
+        Ok(fd)
+    }
+
+    /// Take the next count for event `id`, advancing its script.
+    fn next_count(&mut self, id: u64) -> u64 {
+        match self.find_mut(id) {
+            Some(event) => {
+                if let Some(count) = event.script.pop_front() {
+                    event.count = count;
+                }
+
+                event.count
+            }
+            None => 0,
+        }
+    }
+
+    fn read(&mut self, index: usize, buf: &mut [u8]) -> Result<usize, c_int> {
+        let event = &self.events[index];
+        let read_format = ReadFormat::from_bits_retain(event.attrs.read_format);
+        let mut values = Vec::new();
+
+        if read_format.contains(ReadFormat::GROUP) {
+            let leader = event.leader.unwrap_or(event.id);
+            let (time_enabled, time_running, ids) = match self.find(leader) {
+                Some(leader) => (
+                    leader.time_enabled,
+                    leader.time_running,
+                    std::iter::once(leader.id)
+                        .chain(leader.members.iter().copied())
+                        .filter(|&id| self.find(id).is_some())
+                        .collect::<Vec<_>>(),
+                ),
+                None => (event.time_enabled, event.time_running, vec![event.id]),
+            };
+
+            values.push(ids.len() as u64);
+            if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+                values.push(time_enabled);
+            }
+            if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+                values.push(time_running);
+            }
+
+            for id in ids {
+                values.push(self.next_count(id));
+                if read_format.contains(ReadFormat::ID) {
+                    values.push(id);
+                }
+                if read_format.contains(ReadFormat::LOST) {
+                    values.push(self.find(id).map(|e| e.lost).unwrap_or(0));
+                }
+            }
+        } else {
+            let id = event.id;
+            let count = self.next_count(id);
+            let event = &self.events[index];
+
+            values.push(count);
+            if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+                values.push(event.time_enabled);
+            }
+            if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+                values.push(event.time_running);
+            }
+            if read_format.contains(ReadFormat::ID) {
+                values.push(event.id);
+            }
+            if read_format.contains(ReadFormat::LOST) {
+                values.push(event.lost);
+            }
+        }
+
+        let len = values.len() * size_of::<u64>();
+        if buf.len() < len {
+            return Err(libc::ENOSPC);
+        }
+
+        for (chunk, value) in buf.chunks_exact_mut(size_of::<u64>()).zip(values) {
+            chunk.copy_from_slice(&value.to_ne_bytes());
+        }
+
+        Ok(len)
+    }
+}
+
+impl Hooks for MockKernel {
+    unsafe fn perf_event_open(
+        &mut self,
+        attrs: *mut perf_event_attr,
+        pid: pid_t,
+        cpu: c_int,
+        group_fd: c_int,
+        flags: c_ulong,
+    ) -> c_int {
+        let mut state = self.state.borrow_mut();
+        match state.open(&mut *attrs, pid, cpu, group_fd, flags) {
+            Ok(fd) => fd,
+            Err(errno) => fail(errno),
+        }
+    }
+
+    unsafe fn read(&mut self, fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t {
+        let buf = std::slice::from_raw_parts_mut(buf as *mut u8, count);
+        let mut state = self.state.borrow_mut();
+        let result = match state.index(fd) {
+            Some(index) => state.read(index, buf),
+            None => Err(libc::EBADF),
+        };
+
+        match result {
+            Ok(len) => len as ssize_t,
+            Err(errno) => fail(errno) as ssize_t,
+        }
+    }
+
+    unsafe fn ENABLE(&mut self, fd: c_int, arg: c_uint) -> c_int {
+        self.ioctl(fd, |state, index| {
+            state.set_enabled(index, arg, true);
+            Ok(())
+        })
+    }
+
+    unsafe fn DISABLE(&mut self, fd: c_int, arg: c_uint) -> c_int {
+        self.ioctl(fd, |state, index| {
+            state.set_enabled(index, arg, false);
+            Ok(())
+        })
+    }
+
+    unsafe fn REFRESH(&mut self, fd: c_int, arg: c_int) -> c_int {
+        self.ioctl(fd, |state, index| {
+            let event = &mut state.events[index];
+            if arg < 0 || event.attrs.inherit() != 0 {
+                return Err(libc::EINVAL);
+            }
+
+            event.enabled = true;
+            Ok(())
+        })
+    }
+
+    unsafe fn RESET(&mut self, fd: c_int, arg: c_uint) -> c_int {
+        self.ioctl(fd, |state, index| {
+            for id in state.targets(index, arg) {
+                if let Some(event) = state.find_mut(id) {
+                    event.count = 0;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    unsafe fn PERIOD(&mut self, fd: c_int, arg: u64) -> c_int {
+        self.ioctl(fd, |state, index| {
+            // The ioctl takes a pointer to the new period, not the period itself.
+            let period = *(arg as *const u64);
+            if period == 0 {
+                return Err(libc::EINVAL);
+            }
+
+            state.events[index].attrs.__bindgen_anon_1.sample_period = period;
+            Ok(())
+        })
+    }
+
+    unsafe fn SET_OUTPUT(&mut self, fd: c_int, arg: c_int) -> c_int {
+        self.ioctl(fd, |state, index| {
+            let output = if arg == -1 {
+                None
+            } else {
+                let target = state.index(arg).ok_or(libc::EBADF)?;
+                if target == index {
+                    return Err(libc::EINVAL);
+                }
+
+                Some(state.events[target].id)
+            };
+
+            state.events[index].output = output;
+            Ok(())
+        })
+    }
+
+    unsafe fn SET_FILTER(&mut self, fd: c_int, arg: *mut c_char) -> c_int {
+        self.ioctl(fd, |state, index| {
+            let filter = CStr::from_ptr(arg).to_string_lossy().into_owned();
+            state.events[index].filter = Some(filter);
+            Ok(())
+        })
+    }
+
+    unsafe fn ID(&mut self, fd: c_int, arg: *mut u64) -> c_int {
+        self.ioctl(fd, |state, index| {
+            *arg = state.events[index].id;
+            Ok(())
+        })
+    }
+
+    unsafe fn SET_BPF(&mut self, fd: c_int, arg: u32) -> c_int {
+        self.ioctl(fd, |state, index| {
+            // There are no real BPF programs, so the fd doubles as the program id.
+            state.events[index].bpf.push(arg);
+            Ok(())
+        })
+    }
+
+    unsafe fn PAUSE_OUTPUT(&mut self, fd: c_int, arg: u32) -> c_int {
+        self.ioctl(fd, |state, index| {
+            state.events[index].paused = arg != 0;
+            Ok(())
+        })
+    }
+
+    unsafe fn QUERY_BPF(&mut self, fd: c_int, arg: *mut perf_event_query_bpf) -> c_int {
+        self.ioctl(fd, |state, index| {
+            let programs = &state.events[index].bpf;
+            let capacity = (*arg).ids_len as usize;
+            let ids = (*arg).ids.as_mut_ptr();
+
+            (*arg).prog_cnt = programs.len() as u32;
+            for (i, &id) in programs.iter().take(capacity).enumerate() {
+                *ids.add(i) = id;
+            }
+
+            if capacity < programs.len() {
+                return Err(libc::ENOSPC);
+            }
+
+            Ok(())
+        })
+    }
+
+    unsafe fn MODIFY_ATTRIBUTES(&mut self, fd: c_int, arg: *mut perf_event_attr) -> c_int {
+        self.ioctl(fd, |state, index| {
+            let event = &mut state.events[index];
+            let attrs = &mut event.attrs;
+            let new = &*arg;
+            if attrs.type_ != new.type_ {
+                return Err(libc::EINVAL);
+            }
+
+            // Like the real kernel, only breakpoints can be modified.
+            if attrs.type_ != bindings::PERF_TYPE_BREAKPOINT {
+                return Err(libc::EOPNOTSUPP);
+            }
+
+            attrs.bp_type = new.bp_type;
+            attrs.__bindgen_anon_3 = new.__bindgen_anon_3;
+            attrs.__bindgen_anon_4 = new.__bindgen_anon_4;
+            attrs.set_disabled(new.disabled());
+
+            // The kernel re-enables the breakpoint unless it is modified to be
+            // disabled.
+            event.enabled = new.disabled() == 0;
+            Ok(())
+        })
+    }
+}
+
+/// Create a memfd to act as the file descriptor of a mock counter.
+fn memfd(cloexec: bool) -> Result<c_int, c_int> {
+    let flags = if cloexec { libc::MFD_CLOEXEC } else { 0 };
+    let name = b"perf-event-mock\0";
+
+    let fd = check_errno_syscall(|| unsafe { libc::memfd_create(name.as_ptr().cast(), flags) })
+        .map_err(errno)?;
+
+    if let Err(e) = check_errno_syscall(|| unsafe { libc::ftruncate(fd, MAP_LEN) }) {
+        unsafe { libc::close(fd) };
+        return Err(errno(e));
+    }
+
+    Ok(fd)
+}
+
+fn errno(e: io::Error) -> c_int {
+    e.raw_os_error().unwrap_or(libc::EIO)
+}
+
+/// Set `errno` and return -1, the same as a failing system call.
+fn fail(errno: c_int) -> c_int {
+    unsafe { *libc::__errno_location() = errno };
+    -1
+}
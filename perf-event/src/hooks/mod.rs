@@ -30,9 +30,11 @@
 //!   so that subsequent `perf_event` operations use the real Linux system
 //!   calls.
 //!
-//! This functionality is too low-level for direct use in tests, but
-//! it does provide the means with which one can build more ergonomic
-//! test harnesses.
+//! This functionality is too low-level for direct use in most tests.
+//! The [`MockKernel`] type is a ready-made [`Hooks`] implementation
+//! that simulates counters without touching the real kernel, which
+//! lets tests exercise profiling code deterministically and without
+//! any special privileges.
 //!
 //! ## Stability
 //!
@@ -48,9 +50,9 @@
 //! they may break code using this module's functionality.
 
 use std::cell::RefCell;
-use std::os::raw::{c_char, c_int, c_uint, c_ulong};
+use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_void};
 
-use libc::pid_t;
+use libc::{pid_t, size_t, ssize_t};
 use perf_event_open_sys as real;
 use perf_event_open_sys::bindings;
 
@@ -59,6 +61,10 @@ use crate::{Counter, Group};
 used_in_docs!(Counter);
 used_in_docs!(Group);
 
+mod mock;
+
+pub use self::mock::MockKernel;
+
 std::thread_local! {
     static HOOKS: RefCell<Box<dyn Hooks + 'static>> = RefCell::new(Box::new(RealHooks));
 }
@@ -134,7 +140,7 @@ macro_rules! expand_trait_method {
         /// Wrapper for perf_event ioctl
         #[doc = stringify!($ioctl)]
         /// .
-        #[allow(non_snake_case)]
+        #[allow(non_snake_case, clippy::missing_safety_doc)]
         unsafe fn $name(&mut self, _fd: c_int, _arg: $arg_type) -> c_int {
             panic!(
                 "unimplemented `perf_event::hooks::Hooks` method: {}",
@@ -155,6 +161,9 @@ macro_rules! expand_trait_method {
 /// Each method has a default definition that panics. This means that
 /// you only need to provide definitions for the operations your tests
 /// actually use; if they touch anything else, you'll get a failure.
+/// The exception is [`read`](Hooks::read), which defaults to the real
+/// system call so that file descriptors returned by the real
+/// `perf_event_open` can still be read.
 ///
 /// The [`RealHooks`] type implements this trait in terms of the real
 /// Linux system calls and ioctls.
@@ -174,6 +183,16 @@ pub trait Hooks {
         group_fd: c_int,
         flags: c_ulong,
     ) -> c_int;
+
+    /// See [`read(2)`][read]. This is used to read the counts from a
+    /// counter's file descriptor.
+    ///
+    /// [read]: https://www.mankier.com/2/read
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn read(&mut self, fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t {
+        libc::read(fd, buf, count)
+    }
+
     define_ioctls!(expand_trait_method);
 }
 
@@ -217,6 +236,10 @@ impl Hooks for RealHooks {
         real::perf_event_open(attrs, pid, cpu, group_fd, flags)
     }
 
+    unsafe fn read(&mut self, fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t {
+        libc::read(fd, buf, count)
+    }
+
     define_ioctls!(expand_realhooks_impl);
 }
 
@@ -224,9 +247,9 @@ impl Hooks for RealHooks {
 /// intercepting system calls and returning simulated results, for
 /// testing.
 pub mod sys {
-    use std::os::raw::{c_int, c_ulong};
+    use std::os::raw::{c_int, c_ulong, c_void};
 
-    use libc::pid_t;
+    use libc::{pid_t, size_t, ssize_t};
     pub use perf_event_open_sys::bindings;
 
     use super::HOOKS;
@@ -249,6 +272,14 @@ pub mod sys {
         })
     }
 
+    /// See [`read(2)`][read].
+    ///
+    /// [read]: https://www.mankier.com/2/read
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn read(fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t {
+        HOOKS.with(|hooks| hooks.borrow_mut().read(fd, buf, count))
+    }
+
     #[allow(dead_code, non_snake_case)]
     /// See the [`perf_event_open_sys::ioctl` module][peosi].
     ///
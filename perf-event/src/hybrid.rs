@@ -0,0 +1,268 @@
+use std::io;
+
+use crate::events::{Event, OnPmu};
+use crate::pmu::Pmu;
+use crate::sys::bindings::{self, perf_event_attr};
+use crate::{Builder, Counter, CounterData};
+
+/// A set of counters, one per core PMU, that all count the same event.
+///
+/// On hybrid processors (e.g. Intel Alder Lake and later) each type of core
+/// has its own PMU and a [`Hardware`] or [`Cache`] event only counts on one of
+/// them. `HybridCounters` opens the event on every core PMU using [`OnPmu`]
+/// so that the sum of the counters covers all cores. On systems that are not
+/// hybrid, it opens a single counter for the event as-is.
+///
+/// Only [`Hardware`], [`Cache`], and [`Raw`] events can be opened on a
+/// specific core PMU. Other events, such as software events, already count on
+/// every core so opening them once per PMU would count everything multiple
+/// times.
+///
+/// Everything other than the event is taken from the [`Builder`], so this can
+/// be combined with observing a single process or CPU as usual.
+///
+/// # Example
+/// ```no_run
+/// use perf_event::events::Hardware;
+/// use perf_event::{Builder, HybridCounters};
+///
+/// let builder = Builder::new(Hardware::INSTRUCTIONS);
+/// let mut counters = HybridCounters::new(&builder, Hardware::INSTRUCTIONS)?;
+///
+/// counters.enable()?;
+/// println!("hello, world!");
+/// counters.disable()?;
+///
+/// let data = counters.read_full()?;
+/// for (pmu, data) in data.iter() {
+///     println!("{pmu}: {}", data.count());
+/// }
+/// println!("total: {}", data.total().count());
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Hardware`]: crate::events::Hardware
+/// [`Cache`]: crate::events::Cache
+/// [`Raw`]: crate::events::Raw
+#[derive(Debug)]
+pub struct HybridCounters {
+    counters: Vec<(String, Counter)>,
+}
+
+impl HybridCounters {
+    /// Open `event` on every core PMU, using `builder` for everything but the
+    /// event.
+    ///
+    /// If the system is not hybrid, this opens one counter for `event` on the
+    /// `cpu` PMU (or under the name `cpu` if there is no such PMU).
+    ///
+    /// # Errors
+    /// See [`with_pmus`](Self::with_pmus). On systems that are not hybrid,
+    /// any event is accepted.
+    pub fn new<E>(builder: &Builder, event: E) -> io::Result<Self>
+    where
+        E: Event + Clone,
+    {
+        let pmus = Pmu::core_pmus()?;
+        if pmus.len() <= 1 {
+            let name = pmus
+                .first()
+                .map(|pmu| pmu.name().to_owned())
+                .unwrap_or_else(|| "cpu".to_owned());
+            let counter = builder.clone().event(event).build()?;
+
+            return Ok(Self {
+                counters: vec![(name, counter)],
+            });
+        }
+
+        Self::with_pmus(builder, event, &pmus)
+    }
+
+    /// Open `event` on each of the PMUs in `pmus`.
+    ///
+    /// # Errors
+    /// - An error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if
+    ///   `event` is not a [`Hardware`], [`Cache`], or [`Raw`] event.
+    /// - Any errors from reading the type of each PMU or from building the
+    ///   counters.
+    ///
+    /// [`Hardware`]: crate::events::Hardware
+    /// [`Cache`]: crate::events::Cache
+    /// [`Raw`]: crate::events::Raw
+    pub fn with_pmus<E>(builder: &Builder, event: E, pmus: &[Pmu]) -> io::Result<Self>
+    where
+        E: Event + Clone,
+    {
+        let mut attr = perf_event_attr::default();
+        event.clone().update_attrs(&mut attr);
+        if !matches!(
+            attr.type_,
+            bindings::PERF_TYPE_HARDWARE | bindings::PERF_TYPE_HW_CACHE | bindings::PERF_TYPE_RAW
+        ) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only hardware, cache, and raw events can be opened on each core PMU",
+            ));
+        }
+
+        let mut builder = builder.clone();
+        let counters = pmus
+            .iter()
+            .map(|pmu| {
+                builder.event(OnPmu::new(event.clone(), pmu)?);
+                Ok((pmu.name().to_owned(), builder.build()?))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { counters })
+    }
+
+    /// The number of counters in this set.
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Whether this set contains no counters at all.
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// Get the counter for the PMU named `pmu`, if there is one.
+    pub fn get(&self, pmu: &str) -> Option<&Counter> {
+        self.counters
+            .iter()
+            .find(|(name, _)| name == pmu)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Get a mutable reference to the counter for the PMU named `pmu`, if
+    /// there is one.
+    pub fn get_mut(&mut self, pmu: &str) -> Option<&mut Counter> {
+        self.counters
+            .iter_mut()
+            .find(|(name, _)| name == pmu)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Iterate over the counters in this set along with the name of the PMU
+    /// that each one is counting on.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Counter)> {
+        self.counters
+            .iter()
+            .map(|(name, counter)| (name.as_str(), counter))
+    }
+
+    /// Iterate mutably over the counters in this set along with the name of
+    /// the PMU that each one is counting on.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Counter)> {
+        self.counters
+            .iter_mut()
+            .map(|(name, counter)| (name.as_str(), counter))
+    }
+
+    /// Convert this set into the individual counters that make it up.
+    pub fn into_counters(self) -> Vec<(String, Counter)> {
+        self.counters
+    }
+
+    /// Enable every counter in this set.
+    ///
+    /// See [`Counter::enable`] for details.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.for_each(Counter::enable)
+    }
+
+    /// Disable every counter in this set.
+    ///
+    /// See [`Counter::disable`] for details.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.for_each(Counter::disable)
+    }
+
+    /// Reset every counter in this set to zero.
+    ///
+    /// See [`Counter::reset`] for details.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.for_each(Counter::reset)
+    }
+
+    /// Read the sum of the values of all counters in this set.
+    ///
+    /// Note that this does not account for multiplexing. Use [`read_full`]
+    /// if you need the timesharing data as well.
+    ///
+    /// [`read_full`]: Self::read_full
+    pub fn read(&mut self) -> io::Result<u64> {
+        Ok(self.read_full()?.total().count())
+    }
+
+    /// Read all the data for every counter in this set.
+    ///
+    /// This returns both the values for each individual PMU and an aggregate
+    /// across all of them. See [`HybridCounterData`] for details.
+    pub fn read_full(&mut self) -> io::Result<HybridCounterData> {
+        let per_pmu = self
+            .counters
+            .iter_mut()
+            .map(|(name, counter)| Ok((name.clone(), counter.read_full()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(HybridCounterData::new(per_pmu))
+    }
+
+    fn for_each<F>(&mut self, mut func: F) -> io::Result<()>
+    where
+        F: FnMut(&mut Counter) -> io::Result<()>,
+    {
+        self.counters
+            .iter_mut()
+            .try_for_each(|(_, counter)| func(counter))
+    }
+}
+
+/// The data read from a [`HybridCounters`].
+///
+/// This contains the [`CounterData`] read from the counter on each core PMU,
+/// along with an aggregate of all of them.
+///
+/// A thread only runs on one type of core at a time, so each counter is only
+/// running while the thread is scheduled on a core of its type. This means
+/// that `time_running` of the individual counters will usually be less than
+/// `time_enabled` even when there is no multiplexing, and their counts should
+/// not be scaled. The aggregate sums up the counts and `time_running` but uses
+/// the largest `time_enabled` of any counter.
+#[derive(Clone, Debug)]
+pub struct HybridCounterData {
+    total: CounterData,
+    per_pmu: Vec<(String, CounterData)>,
+}
+
+impl HybridCounterData {
+    fn new(per_pmu: Vec<(String, CounterData)>) -> Self {
+        Self {
+            total: CounterData::merge(per_pmu.iter().map(|(_, data)| data)),
+            per_pmu,
+        }
+    }
+
+    /// The aggregate of the data across all core PMUs.
+    pub fn total(&self) -> &CounterData {
+        &self.total
+    }
+
+    /// Get the data for the PMU named `pmu`, if there is one.
+    pub fn get(&self, pmu: &str) -> Option<&CounterData> {
+        self.per_pmu
+            .iter()
+            .find(|(name, _)| name == pmu)
+            .map(|(_, data)| data)
+    }
+
+    /// Iterate over the data for each core PMU.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CounterData)> {
+        self.per_pmu
+            .iter()
+            .map(|(name, data)| (name.as_str(), data))
+    }
+}
@@ -0,0 +1,149 @@
+use std::io;
+
+use crate::{Counter, CounterData, Group, GroupData};
+
+/// Reads the change in a [`Counter`] or [`Group`] since it was last read.
+///
+/// This is useful for periodically polling counters in the same manner as
+/// `perf stat -I`. Each call to `read` returns the difference in the count,
+/// `time_enabled`, `time_running`, and `lost` values since the previous call.
+/// Since the times are also deltas, [`CounterData::scaled_count`] and
+/// [`GroupEntry::scaled_value`] scale the values according to how long the
+/// counter was running during that interval only.
+///
+/// The first call to `read` returns the values accumulated since the counter
+/// was created (or last reset). If the counter is reset between two reads then
+/// the value after the reset is returned for that interval.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+///
+/// use perf_event::events::Software;
+/// use perf_event::{Builder, IntervalReader};
+///
+/// let counter = Builder::new(Software::TASK_CLOCK)
+///     .enable_scaling()
+///     .enabled(true)
+///     .build()?;
+/// let mut reader = IntervalReader::new(counter);
+///
+/// for _ in 0..3 {
+///     std::thread::sleep(Duration::from_millis(10));
+///
+///     let delta = reader.read()?;
+///     println!(
+///         "{:?} ({:.2}%)",
+///         delta.scaled_count(),
+///         delta.percent_running().unwrap_or(0.0)
+///     );
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`GroupEntry::scaled_value`]: crate::GroupEntry::scaled_value
+pub struct IntervalReader<T: IntervalSource> {
+    inner: T,
+    previous: Option<T::Data>,
+}
+
+impl<T: IntervalSource> IntervalReader<T> {
+    /// Create a new `IntervalReader`.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            previous: None,
+        }
+    }
+
+    /// Access the wrapped counter or group.
+    pub fn as_inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Mutably access the wrapped counter or group.
+    pub fn as_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Convert this `IntervalReader` back into the wrapped counter or group.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Read the change in the counter or group since the last call to `read`.
+    ///
+    /// For groups, counters that were added to the group since the last read
+    /// are reported with their full value, while counters that have been
+    /// removed from the group are omitted.
+    ///
+    /// See [`Counter::read_full`] and [`Group::read`] for the errors that may
+    /// be returned.
+    pub fn read(&mut self) -> io::Result<T::Data> {
+        let current = self.inner.read_current()?;
+        let delta = match &self.previous {
+            Some(previous) => T::delta(&current, previous),
+            None => current.clone(),
+        };
+
+        self.previous = Some(current);
+        Ok(delta)
+    }
+}
+
+/// A counter or group that can be wrapped by an [`IntervalReader`].
+///
+/// This trait is sealed and is only implemented for [`Counter`] and
+/// [`Group`].
+pub trait IntervalSource: sealed::Sealed {
+    /// The type returned by [`IntervalReader::read`].
+    type Data: Clone;
+
+    #[doc(hidden)]
+    fn read_current(&mut self) -> io::Result<Self::Data>;
+
+    #[doc(hidden)]
+    fn delta(current: &Self::Data, previous: &Self::Data) -> Self::Data;
+}
+
+impl IntervalSource for Counter {
+    type Data = CounterData;
+
+    fn read_current(&mut self) -> io::Result<CounterData> {
+        self.read_full()
+    }
+
+    fn delta(current: &CounterData, previous: &CounterData) -> CounterData {
+        current.delta(previous)
+    }
+}
+
+impl IntervalSource for Group {
+    type Data = GroupData;
+
+    fn read_current(&mut self) -> io::Result<GroupData> {
+        self.read()
+    }
+
+    fn delta(current: &GroupData, previous: &GroupData) -> GroupData {
+        current.delta(previous)
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for crate::Counter {}
+    impl Sealed for crate::Group {}
+}
+
+/// Compute the change from `previous` to `current`.
+///
+/// If the value has decreased then it was reset in between and `current` is
+/// returned instead.
+pub(crate) fn delta(current: u64, previous: Option<u64>) -> u64 {
+    match previous {
+        Some(previous) if current >= previous => current - previous,
+        _ => current,
+    }
+}
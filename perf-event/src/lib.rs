@@ -103,16 +103,35 @@ use std::{fmt, io};
 
 use crate::data::endian::Native;
 use crate::data::parse::ParseConfig;
-use crate::sys::bindings::PERF_IOC_FLAG_GROUP;
+use crate::events::Filter;
+use crate::sys::bindings::{perf_event_attr, PERF_IOC_FLAG_GROUP};
 use crate::sys::ioctls;
 
 pub mod events;
+pub mod metrics;
+pub mod pmu;
 
+mod adaptive;
+mod arch;
+mod aux_buffer;
 mod builder;
+mod clock;
+mod cpu_sampler;
 mod flags;
+mod flight_recorder;
 mod group;
 mod group_data;
+mod hybrid;
+mod interval;
+mod owned_record;
+mod per_cpu;
+mod perf_data;
 mod sampler;
+mod uncore;
+mod user_counter;
+
+#[cfg(test)]
+mod test_util;
 
 // Make sure the examples in the readme are tested.
 #[doc = include_str!("../README.md")]
@@ -142,13 +161,25 @@ pub use perf_event_data as data;
 #[cfg(not(feature = "hooks"))]
 use perf_event_open_sys as sys;
 
+pub use crate::adaptive::AdaptivePeriod;
+pub use crate::aux_buffer::{AuxBuffer, AuxData};
 pub use crate::builder::{Builder, UnsupportedOptionsError};
+pub use crate::clock::PerfClock;
+pub use crate::cpu_sampler::CpuSampler;
 #[doc(inline)]
 pub use crate::data::{ReadFormat, SampleFlags as SampleFlag};
 pub use crate::flags::{Clock, SampleBranchFlag, SampleSkid};
+pub use crate::flight_recorder::FlightRecorder;
 pub use crate::group::Group;
 pub use crate::group_data::{GroupData, GroupEntry, GroupIter};
-pub use crate::sampler::{Record, Sampler, UserReadData};
+pub use crate::hybrid::{HybridCounterData, HybridCounters};
+pub use crate::interval::{IntervalReader, IntervalSource};
+pub use crate::owned_record::OwnedRecord;
+pub use crate::per_cpu::{CpuCounterData, CpuCounters};
+pub use crate::perf_data::{BuildId, PerfDataEvent, PerfDataReader, PerfDataWriter};
+pub use crate::sampler::{PausedSampler, Record, Sampler, UserReadData, UserReadError};
+pub use crate::uncore::{UncoreCounterData, UncoreCounters};
+pub use crate::user_counter::{UserCounter, UserGroup};
 
 /// A counter for a single kernel or hardware event.
 ///
@@ -196,17 +227,21 @@ pub struct Counter {
     /// The parse config used by this counter.
     config: ParseConfig<Native>,
 
+    /// The attributes that were used to create this counter.
+    attrs: perf_event_attr,
+
     /// If we are a `Group`, then this is the count of how many members we have.
     member_count: u32,
 }
 
 impl Counter {
     /// Common initialization code shared between counters and groups.
-    pub(crate) fn new_internal(file: File, config: ParseConfig<Native>) -> std::io::Result<Self> {
+    pub(crate) fn new_internal(file: File, attrs: perf_event_attr) -> std::io::Result<Self> {
         let mut counter = Self {
             file,
             id: 0,
-            config,
+            config: ParseConfig::from(attrs),
+            attrs,
             member_count: 1,
         };
 
@@ -229,6 +264,11 @@ impl Counter {
         &self.config
     }
 
+    /// The attributes that were used to create this `Counter`.
+    pub(crate) fn attrs(&self) -> &perf_event_attr {
+        &self.attrs
+    }
+
     /// Allow this `Counter` to begin counting its designated event.
     ///
     /// This does not affect whatever value the `Counter` had previously; new
@@ -382,6 +422,181 @@ impl Counter {
             .map(drop)
     }
 
+    /// Get the ids of the eBPF programs attached to this counter.
+    ///
+    /// This will only work if this counter was created as a kprobe, uprobe,
+    /// or tracepoint event. The kernel also requires `CAP_PERFMON` (or
+    /// `CAP_SYS_ADMIN`) and will return `EPERM` otherwise.
+    ///
+    /// This method corresponds to the `IOC_QUERY_BPF` ioctl.
+    pub fn attached_bpf_programs(&self) -> io::Result<Vec<u32>> {
+        use std::mem::size_of;
+
+        use crate::sys::bindings::perf_event_query_bpf;
+
+        // perf_event_query_bpf is made up of two u32s followed by a flexible
+        // array of u32 ids so we can use a Vec<u32> as backing storage.
+        const HEADER_LEN: usize = size_of::<perf_event_query_bpf>() / size_of::<u32>();
+
+        // Start by asking the kernel how many programs there are. If more
+        // programs get attached between calls then the kernel will return
+        // ENOSPC and we try again with a larger buffer.
+        let mut capacity = 0;
+        loop {
+            let mut buffer = vec![0u32; HEADER_LEN + capacity];
+            let query = buffer.as_mut_ptr() as *mut perf_event_query_bpf;
+
+            // SAFETY: buffer is large enough and suitably aligned to hold a
+            //         perf_event_query_bpf with `capacity` ids.
+            unsafe { (*query).ids_len = capacity as u32 };
+
+            match self.ioctl(|fd| unsafe { ioctls::QUERY_BPF(fd, query) }) {
+                Ok(()) => (),
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => (),
+                Err(e) => return Err(e),
+            }
+
+            // SAFETY: the kernel has filled in the query struct.
+            let prog_cnt = unsafe { (*query).prog_cnt } as usize;
+            if prog_cnt <= capacity {
+                buffer.truncate(HEADER_LEN + prog_cnt);
+                buffer.drain(..HEADER_LEN);
+                return Ok(buffer);
+            }
+
+            capacity = prog_cnt;
+        }
+    }
+
+    /// Set a filter on this counter.
+    ///
+    /// For tracepoint, kprobe, and uprobe events this is an ftrace filter
+    /// expression that is evaluated by the kernel for every event. Events that
+    /// do not match the filter are dropped without being counted or recorded.
+    /// See [`TracepointFilter`] for a way to validate such an expression
+    /// against the fields of a tracepoint before setting it.
+    ///
+    /// For PMUs that support address filtering (e.g. Intel PT) this is a list
+    /// of address ranges to filter on. See [`AddressFilter`] for a way to
+    /// build one.
+    ///
+    /// The kernel will return `EINVAL` if the filter is not valid for this
+    /// counter. Filters cannot contain interior NUL bytes, attempting to set
+    /// one that does will result in an error with kind
+    /// [`io::ErrorKind::InvalidInput`].
+    ///
+    /// This method corresponds to the `IOC_SET_FILTER` ioctl.
+    ///
+    /// [`TracepointFilter`]: crate::events::TracepointFilter
+    /// [`AddressFilter`]: crate::events::AddressFilter
+    pub fn set_filter(&mut self, filter: &impl Filter) -> io::Result<()> {
+        let filter = std::ffi::CString::new(filter.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        // The kernel copies the filter string and never writes to it so
+        // casting away the const here is fine.
+        self.ioctl(|fd| unsafe { ioctls::SET_FILTER(fd, filter.as_ptr() as *mut _) })
+    }
+
+    /// Change the sampling period of this counter.
+    ///
+    /// If this counter was created with [`Builder::sample_frequency`] then
+    /// this sets the sampling frequency instead. The new period takes effect
+    /// after the next overflow of the counter.
+    ///
+    /// This method corresponds to the `IOC_PERIOD` ioctl.
+    pub fn set_period(&mut self, period: u64) -> io::Result<()> {
+        let mut value = period;
+
+        // The ioctl takes a pointer to the new period, not the period itself.
+        self.ioctl(|fd| unsafe { ioctls::PERIOD(fd, &mut value as *mut u64 as u64) })?;
+
+        if self.attrs.freq() != 0 {
+            self.attrs.sample_freq = period;
+        } else {
+            self.attrs.sample_period = period;
+        }
+
+        Ok(())
+    }
+
+    /// Enable this counter for `count` more overflows, after which it will be
+    /// disabled again.
+    ///
+    /// Every time the counter overflows it decrements the refresh count. Once
+    /// the refresh count reaches zero the counter is disabled and, if the
+    /// counter is being sampled, the ring buffer will signal `POLLHUP`. This
+    /// allows for taking a fixed number of samples before stopping. Calling
+    /// `refresh` again will add to the count and re-enable the counter.
+    ///
+    /// Note that this only makes sense for counters that have a sampling
+    /// period. The kernel will return `EINVAL` for inherited counters or if
+    /// `count` is 0.
+    ///
+    /// This method corresponds to the `IOC_REFRESH` ioctl.
+    pub fn refresh(&mut self, count: u32) -> io::Result<()> {
+        let count: libc::c_int = count
+            .try_into()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        self.ioctl(|fd| unsafe { ioctls::REFRESH(fd, count) })
+    }
+
+    /// Change the breakpoint that this counter is watching.
+    ///
+    /// This is much cheaper than closing the counter and building a new one.
+    /// The counter keeps its current value across the change. Only the
+    /// address, length, and access type of the breakpoint can be changed, all
+    /// other options stay as they were when the counter was built.
+    ///
+    /// The kernel decides whether the counter should be enabled after the
+    /// change based on the attributes passed in so the counter will always be
+    /// enabled once this method returns, even if it was disabled before.
+    ///
+    /// This will return an error with kind [`io::ErrorKind::InvalidInput`] if
+    /// this counter was not built with a [`Breakpoint`] event.
+    ///
+    /// This method corresponds to the `IOC_MODIFY_ATTRIBUTES` ioctl.
+    ///
+    /// # Example
+    /// ```
+    /// use perf_event::events::Breakpoint;
+    /// use perf_event::Builder;
+    ///
+    /// let mut data = [0u64; 2];
+    /// let mut counter = Builder::new(Breakpoint::write(&data[0] as *const _ as u64, 8)).build()?;
+    /// counter.enable()?;
+    ///
+    /// unsafe { std::ptr::write_volatile(&mut data[0], 1) };
+    /// counter.modify_breakpoint(Breakpoint::write(&data[1] as *const _ as u64, 8))?;
+    /// unsafe { std::ptr::write_volatile(&mut data[0], 2) };
+    /// unsafe { std::ptr::write_volatile(&mut data[1], 3) };
+    ///
+    /// assert_eq!(counter.read()?, 2);
+    /// # std::io::Result::Ok(())
+    /// ```
+    ///
+    /// [`Breakpoint`]: crate::events::Breakpoint
+    pub fn modify_breakpoint(&mut self, breakpoint: events::Breakpoint) -> io::Result<()> {
+        use crate::events::Event;
+
+        if self.attrs.type_ != sys::bindings::PERF_TYPE_BREAKPOINT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "counter was not created with a breakpoint event",
+            ));
+        }
+
+        let mut attrs = self.attrs;
+        breakpoint.update_attrs(&mut attrs);
+        attrs.set_disabled(0);
+
+        self.ioctl(|fd| unsafe { ioctls::MODIFY_ATTRIBUTES(fd, &mut attrs) })?;
+        self.attrs = attrs;
+
+        Ok(())
+    }
+
     /// Map a buffer for samples from this counter, returning a [`Sampler`]
     /// that can be used to access them.
     ///
@@ -420,6 +635,27 @@ impl Counter {
         Ok(Sampler::new(self, mmap))
     }
 
+    /// Map a buffer for records from this counter that the kernel will
+    /// continuously overwrite, returning a [`FlightRecorder`] that can be used
+    /// to read the most recent records on demand.
+    ///
+    /// The counter must have been built with [`Builder::write_backward`],
+    /// otherwise an error with kind [`io::ErrorKind::InvalidInput`] will be
+    /// returned. `map_len` is rounded the same way as in
+    /// [`sampled`](Self::sampled).
+    pub fn flight_recorder(self, map_len: usize) -> io::Result<FlightRecorder> {
+        FlightRecorder::new(self, map_len)
+    }
+
+    /// Map the metadata page of this counter, returning a [`UserCounter`] that
+    /// can be read directly from userspace.
+    ///
+    /// Unlike [`sampled`](Self::sampled), this does not map a data area for
+    /// records.
+    pub fn user_counter(self) -> io::Result<UserCounter> {
+        UserCounter::new(self)
+    }
+
     /// Helper function for doing ioctls on a counter.
     pub(crate) fn ioctl<F>(&self, ioctl: F) -> io::Result<()>
     where
@@ -525,9 +761,14 @@ impl Counter {
 
         let group = self.do_read_group()?;
         let entry = group.get(self).unwrap();
-        let data = crate::data::ReadValue::from_group_and_entry(&group.data, &entry.0);
 
-        Ok(CounterData(data))
+        Ok(CounterData::from_parts(
+            entry.value(),
+            group.time_enabled().map(|time| time.as_nanos() as u64),
+            group.time_running().map(|time| time.as_nanos() as u64),
+            Some(entry.id()),
+            entry.lost(),
+        ))
     }
 
     /// Read the values of all the counters in the current group.
@@ -574,7 +815,12 @@ impl Counter {
         if self.is_group() {
             self.do_read_group()
         } else {
-            Ok(GroupData::new(self.do_read_single()?.0.into()))
+            let data = self.do_read_single()?;
+            Ok(GroupData::from_parts(
+                data.time_enabled,
+                data.time_running,
+                Some((data.count, data.id, data.lost)),
+            ))
         }
     }
 
@@ -648,7 +894,6 @@ impl Counter {
 
     /// Actual read implementation for when `ReadFormat::GROUP` is not set.
     fn do_read_single(&mut self) -> io::Result<CounterData> {
-        use std::io::Read;
         use std::mem::size_of;
 
         use crate::flags::ReadFormatExt;
@@ -656,7 +901,7 @@ impl Counter {
         debug_assert!(!self.is_group());
 
         let mut data = [0u8; ReadFormat::MAX_NON_GROUP_SIZE * size_of::<u64>()];
-        let len = self.file.read(&mut data)?;
+        let len = self.read_raw(&mut data)?;
 
         if len == 0 {
             return Err(io::Error::new(
@@ -668,12 +913,17 @@ impl Counter {
         let mut parser = crate::data::parse::Parser::new(&data[..len], self.config.clone());
         let value: crate::data::ReadValue = parser.parse().map_err(io::Error::other)?;
 
-        Ok(CounterData(value))
+        Ok(CounterData::from_parts(
+            value.value(),
+            value.time_enabled(),
+            value.time_running(),
+            value.id(),
+            value.lost(),
+        ))
     }
 
     /// Actual read implementation for when `ReadFormat::GROUP` is set.
     fn do_read_group(&mut self) -> io::Result<GroupData> {
-        use std::io::Read;
         use std::mem::size_of;
 
         use crate::data::ReadGroup;
@@ -708,7 +958,7 @@ impl Counter {
         // The next time around self.member_count will be set to the correct
         // count and we won't need to go through this loop multiple times.
         let len = loop {
-            match self.file.read(&mut data) {
+            match self.read_raw(&mut data) {
                 Ok(len) => break len,
                 Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => {
                     elements *= 2;
@@ -727,11 +977,7 @@ impl Counter {
 
         data.truncate(len);
         let mut parser = crate::data::parse::Parser::new(data.as_slice(), self.config.clone());
-        let data: ReadGroup = parser
-            .parse::<ReadGroup>()
-            .map_err(io::Error::other)?
-            .into_owned();
-
+        let data: ReadGroup = parser.parse::<ReadGroup>().map_err(io::Error::other)?;
         let data = GroupData::new(data);
 
         self.member_count = data
@@ -741,6 +987,26 @@ impl Counter {
 
         Ok(data)
     }
+
+    /// Read the raw `read_format` data for this counter from its file
+    /// descriptor.
+    #[cfg(not(feature = "hooks"))]
+    fn read_raw(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use std::io::Read;
+
+        self.file.read(buf)
+    }
+
+    /// Read the raw `read_format` data for this counter from its file
+    /// descriptor.
+    #[cfg(feature = "hooks")]
+    fn read_raw(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let fd = self.as_raw_fd();
+        let len =
+            check_errno_syscall(|| unsafe { sys::read(fd, buf.as_mut_ptr().cast(), buf.len()) })?;
+
+        Ok(len as usize)
+    }
 }
 
 impl AsRawFd for Counter {
@@ -778,15 +1044,102 @@ impl fmt::Debug for Counter {
 
 /// The data retrieved by reading from a [`Counter`].
 #[derive(Clone, Debug)]
-pub struct CounterData(crate::data::ReadValue);
+pub struct CounterData {
+    count: u64,
+    time_enabled: Option<u64>,
+    time_running: Option<u64>,
+    id: Option<u64>,
+    lost: Option<u64>,
+}
 
 impl CounterData {
+    /// Construct a `CounterData` directly from its component values.
+    ///
+    /// Any fields that are `None` will not be present in the resulting
+    /// `CounterData`.
+    pub(crate) fn from_parts(
+        count: u64,
+        time_enabled: Option<u64>,
+        time_running: Option<u64>,
+        id: Option<u64>,
+        lost: Option<u64>,
+    ) -> Self {
+        Self {
+            count,
+            time_enabled,
+            time_running,
+            id,
+            lost,
+        }
+    }
+
+    /// Sum up the values of several `CounterData`s.
+    ///
+    /// Optional fields are only present in the result if they were present
+    /// in every input.
+    pub(crate) fn sum<'a, I>(data: I) -> Self
+    where
+        I: IntoIterator<Item = &'a CounterData>,
+    {
+        fn add(acc: Option<u64>, value: Option<u64>) -> Option<u64> {
+            Some(acc?.wrapping_add(value?))
+        }
+
+        let (count, time_enabled, time_running, lost) = data.into_iter().fold(
+            (0u64, Some(0), Some(0), Some(0)),
+            |(count, time_enabled, time_running, lost), data| {
+                (
+                    count.wrapping_add(data.count()),
+                    add(time_enabled, data.time_enabled),
+                    add(time_running, data.time_running),
+                    add(lost, data.lost()),
+                )
+            },
+        );
+
+        Self::from_parts(count, time_enabled, time_running, None, lost)
+    }
+
+    /// Merge the values of counters that each counted part of the same period
+    /// of time.
+    ///
+    /// This is the same as [`sum`](Self::sum) except that `time_enabled` is
+    /// the maximum of the inputs instead of their sum.
+    pub(crate) fn merge<'a, I>(data: I) -> Self
+    where
+        I: IntoIterator<Item = &'a CounterData> + Clone,
+    {
+        let time_enabled = data
+            .clone()
+            .into_iter()
+            .map(|data| data.time_enabled)
+            .try_fold(0, |acc, time| Some(acc.max(time?)));
+        let sum = Self::sum(data);
+
+        Self::from_parts(sum.count, time_enabled, sum.time_running, None, sum.lost)
+    }
+
+    /// Compute the change in each value since `previous` was read.
+    pub(crate) fn delta(&self, previous: &CounterData) -> Self {
+        use crate::interval::delta;
+
+        Self::from_parts(
+            delta(self.count, Some(previous.count)),
+            self.time_enabled
+                .map(|time| delta(time, previous.time_enabled)),
+            self.time_running
+                .map(|time| delta(time, previous.time_running)),
+            self.id,
+            self.lost.map(|lost| delta(lost, previous.lost)),
+        )
+    }
+
     /// The counter value.
     ///
     /// The meaning of this field depends on how the counter was configured when
     /// it was built; see ['Builder'].
     pub fn count(&self) -> u64 {
-        self.0.value()
+        self.count
     }
 
     /// How long this counter was enabled by the program.
@@ -794,7 +1147,7 @@ impl CounterData {
     /// This will be present if [`ReadFormat::TOTAL_TIME_ENABLED`] was
     /// specified in `read_format` when the counter was built.
     pub fn time_enabled(&self) -> Option<Duration> {
-        self.0.time_enabled().map(Duration::from_nanos)
+        self.time_enabled.map(Duration::from_nanos)
     }
 
     /// How long the kernel actually ran this counter.
@@ -807,7 +1160,15 @@ impl CounterData {
     /// This will be present if [`ReadFormat::TOTAL_TIME_RUNNING`] was
     /// specified in `read_format` when the counter was built.
     pub fn time_running(&self) -> Option<Duration> {
-        self.0.time_running().map(Duration::from_nanos)
+        self.time_running.map(Duration::from_nanos)
+    }
+
+    /// The kernel-assigned unique id of the counter that was read.
+    ///
+    /// This will be present if [`ReadFormat::ID`] was specified in
+    /// `read_format` when the counter was built.
+    pub fn id(&self) -> Option<u64> {
+        self.id
     }
 
     /// The number of lost samples of this event.
@@ -815,7 +1176,29 @@ impl CounterData {
     /// This will be present if [`ReadFormat::LOST`] was specified in
     /// `read_format` when the counter was built.
     pub fn lost(&self) -> Option<u64> {
-        self.0.lost()
+        self.lost
+    }
+
+    /// The counter value, scaled to estimate what it would have been had the
+    /// counter been running for the entire time it was enabled.
+    ///
+    /// This will be `None` if the counter never ran or if `read_format` did
+    /// not include both [`ReadFormat::TOTAL_TIME_ENABLED`] and
+    /// [`ReadFormat::TOTAL_TIME_RUNNING`]. [`Builder::enable_scaling`] will
+    /// set both.
+    pub fn scaled_count(&self) -> Option<u64> {
+        scale(self.count, self.time_enabled?, self.time_running?)
+    }
+
+    /// The percentage of the time the counter was enabled that it was
+    /// actually running.
+    ///
+    /// This is the value that `perf stat` prints in parentheses after a
+    /// counter. It will be less than 100% if the counter had to share the
+    /// underlying hardware with other counters. It is `None` if the counter was
+    /// never enabled or if the times were not included in `read_format`.
+    pub fn percent_running(&self) -> Option<f64> {
+        percent_running(self.time_enabled?, self.time_running?)
     }
 }
 
@@ -849,6 +1232,30 @@ pub struct CountAndTime {
     pub time_running: u64,
 }
 
+/// Scale `count` by `time_enabled / time_running` to account for the counter
+/// being multiplexed with others.
+///
+/// Returns `None` if the counter never ran.
+fn scale(count: u64, time_enabled: u64, time_running: u64) -> Option<u64> {
+    if time_running == 0 {
+        return None;
+    }
+
+    let scaled = count as u128 * time_enabled as u128 / time_running as u128;
+    Some(scaled.try_into().unwrap_or(u64::MAX))
+}
+
+/// Compute the percentage of `time_enabled` that the counter was running.
+///
+/// Returns `None` if the counter was never enabled.
+fn percent_running(time_enabled: u64, time_running: u64) -> Option<f64> {
+    if time_enabled == 0 {
+        return None;
+    }
+
+    Some(time_running as f64 / time_enabled as f64 * 100.0)
+}
+
 /// Produce an `io::Result` from an errno-style system call.
 ///
 /// An 'errno-style' system call is one that reports failure by returning -1 and
@@ -916,4 +1323,30 @@ mod tests {
             assert!(count <= value.count(), "{count} <= {}", value.count());
         }
     }
+
+    #[test]
+    fn scaling() {
+        assert_eq!(scale(100, 20, 10), Some(200));
+        assert_eq!(scale(100, 10, 10), Some(100));
+        assert_eq!(scale(100, 10, 0), None);
+        assert_eq!(scale(u64::MAX, 2, 1), Some(u64::MAX));
+
+        assert_eq!(percent_running(20, 10), Some(50.0));
+        assert_eq!(percent_running(0, 0), None);
+    }
+
+    #[test]
+    fn scaled_read() {
+        let mut counter = Builder::new(events::Software::TASK_CLOCK)
+            .read_format(ReadFormat::LOST)
+            .enable_scaling()
+            .enabled(true)
+            .build()
+            .expect("failed to build counter");
+
+        let data = counter.read_full().unwrap();
+        assert!(data.lost().is_some());
+        assert_eq!(data.scaled_count(), Some(data.count()));
+        assert_eq!(data.percent_running(), Some(100.0));
+    }
 }
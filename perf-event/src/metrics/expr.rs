@@ -0,0 +1,764 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// An arithmetic expression over named events.
+///
+/// The syntax is the one used for `MetricExpr` in the `perf` tool's
+/// pmu-events JSON files. Expressions support:
+/// - decimal numbers (e.g. `100`, `0.5`, `1e9`),
+/// - event names, which start with a letter, `_`, or `#` and may contain
+///   letters, digits, and any of `_.#@:?`. Other characters can be included by
+///   escaping them with `\`, e.g. `cpu@INST_RETIRED.ANY\,cmask\=1@`,
+/// - the binary operators `+`, `-`, `*`, and `/` with the usual precedence,
+/// - unary `-`,
+/// - the comparisons `<` and `>`, which evaluate to 1 or 0,
+/// - conditionals of the form `a if cond else b`,
+/// - parentheses, and
+/// - the functions `min(a, b, ...)`, `max(a, b, ...)`, and `d_ratio(a, b)`.
+///   `d_ratio` is division that evaluates to 0 instead of failing when `b` is
+///   0.
+///
+/// # Example
+/// ```
+/// use perf_event::metrics::Expr;
+///
+/// let expr: Expr = "100 * misses / accesses".parse()?;
+/// let value = expr.eval(|name| match name {
+///     "misses" => Some(5.0),
+///     "accesses" => Some(20.0),
+///     _ => None,
+/// });
+///
+/// assert_eq!(value, Some(25.0));
+/// # Ok::<(), perf_event::metrics::ParseExprError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    root: Node,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Number(f64),
+    Event(String),
+    Neg(Box<Node>),
+    Binary(BinOp, Box<Node>, Box<Node>),
+    If {
+        then: Box<Node>,
+        cond: Box<Node>,
+        otherwise: Box<Node>,
+    },
+    Call(Func, Vec<Node>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BinOp {
+    Lt,
+    Gt,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Lt | Self::Gt => 1,
+            Self::Add | Self::Sub => 2,
+            Self::Mul | Self::Div => 3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Func {
+    Min,
+    Max,
+    DRatio,
+}
+
+impl Func {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::DRatio => "d_ratio",
+        }
+    }
+}
+
+impl Expr {
+    /// Parse an expression.
+    pub fn parse(expr: &str) -> Result<Self, ParseExprError> {
+        let mut parser = Parser {
+            input: expr,
+            pos: 0,
+            depth: 0,
+        };
+        let root = parser.expr()?;
+
+        parser.skip_whitespace();
+        if parser.pos != expr.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+
+        Ok(Self { root })
+    }
+
+    /// Iterate over the names of all events referenced by this expression.
+    ///
+    /// Events that are referenced multiple times will be returned multiple
+    /// times.
+    pub fn events(&self) -> impl Iterator<Item = &str> {
+        let mut events = Vec::new();
+        self.root.events(&mut events);
+        events.into_iter()
+    }
+
+    /// Evaluate this expression, using `lookup` to get the value of each
+    /// event.
+    ///
+    /// Returns `None` if `lookup` returns `None` for any of the events that
+    /// are needed or if the expression divides by zero.
+    pub fn eval<F>(&self, mut lookup: F) -> Option<f64>
+    where
+        F: FnMut(&str) -> Option<f64>,
+    {
+        self.root.eval(&mut lookup)
+    }
+
+    /// Create a new expression which multiplies this one by `factor`.
+    pub(crate) fn scaled(self, factor: f64) -> Self {
+        Self {
+            root: Node::Binary(
+                BinOp::Mul,
+                Box::new(Node::Number(factor)),
+                Box::new(self.root),
+            ),
+        }
+    }
+
+    /// Create a new expression where each event for which `replace` returns
+    /// an expression is replaced by that expression.
+    pub(crate) fn replace_events<E>(
+        &self,
+        replace: &mut dyn FnMut(&str) -> Result<Option<Expr>, E>,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            root: self.root.replace_events(replace)?,
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+impl Node {
+    fn events<'a>(&'a self, events: &mut Vec<&'a str>) {
+        match self {
+            Self::Number(_) => (),
+            Self::Event(name) => events.push(name),
+            Self::Neg(node) => node.events(events),
+            Self::Binary(_, lhs, rhs) => {
+                lhs.events(events);
+                rhs.events(events);
+            }
+            Self::If {
+                then,
+                cond,
+                otherwise,
+            } => {
+                then.events(events);
+                cond.events(events);
+                otherwise.events(events);
+            }
+            Self::Call(_, args) => args.iter().for_each(|arg| arg.events(events)),
+        }
+    }
+
+    fn eval(&self, lookup: &mut dyn FnMut(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Self::Number(value) => *value,
+            Self::Event(name) => lookup(name)?,
+            Self::Neg(node) => -node.eval(lookup)?,
+            Self::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(lookup)?;
+                let rhs = rhs.eval(lookup)?;
+
+                match op {
+                    BinOp::Lt => (lhs < rhs) as u8 as f64,
+                    BinOp::Gt => (lhs > rhs) as u8 as f64,
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div if rhs == 0.0 => return None,
+                    BinOp::Div => lhs / rhs,
+                }
+            }
+            // Only the branch that is taken is evaluated so that it doesn't
+            // matter if the other one would fail.
+            Self::If {
+                then,
+                cond,
+                otherwise,
+            } => {
+                if cond.eval(lookup)? != 0.0 {
+                    then.eval(lookup)?
+                } else {
+                    otherwise.eval(lookup)?
+                }
+            }
+            Self::Call(func, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(arg.eval(lookup)?);
+                }
+
+                match (func, values.as_slice()) {
+                    (Func::Min, _) => values.into_iter().fold(f64::INFINITY, f64::min),
+                    (Func::Max, _) => values.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                    (Func::DRatio, &[_, 0.0]) => 0.0,
+                    (Func::DRatio, &[num, denom]) => num / denom,
+                    (Func::DRatio, _) => return None,
+                }
+            }
+        })
+    }
+
+    fn replace_events<E>(
+        &self,
+        replace: &mut dyn FnMut(&str) -> Result<Option<Expr>, E>,
+    ) -> Result<Self, E> {
+        Ok(match self {
+            Self::Number(value) => Self::Number(*value),
+            Self::Event(name) => match replace(name)? {
+                Some(expr) => expr.root,
+                None => Self::Event(name.clone()),
+            },
+            Self::Neg(node) => Self::Neg(Box::new(node.replace_events(replace)?)),
+            Self::Binary(op, lhs, rhs) => Self::Binary(
+                *op,
+                Box::new(lhs.replace_events(replace)?),
+                Box::new(rhs.replace_events(replace)?),
+            ),
+            Self::If {
+                then,
+                cond,
+                otherwise,
+            } => Self::If {
+                then: Box::new(then.replace_events(replace)?),
+                cond: Box::new(cond.replace_events(replace)?),
+                otherwise: Box::new(otherwise.replace_events(replace)?),
+            },
+            Self::Call(func, args) => Self::Call(
+                *func,
+                args.iter()
+                    .map(|arg| arg.replace_events(replace))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::If { .. } => 0,
+            Self::Binary(op, ..) => op.precedence(),
+            Self::Neg(_) => 4,
+            _ => 5,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(value) => write!(f, "{}", value),
+            Self::Event(name) => {
+                for c in name.chars() {
+                    if !is_name_char(c) {
+                        f.write_str("\\")?;
+                    }
+                    write!(f, "{}", c)?;
+                }
+                Ok(())
+            }
+            Self::Neg(node) => {
+                f.write_str("-")?;
+                node.fmt_operand(f, 5)
+            }
+            Self::Binary(op, lhs, rhs) => {
+                // All operators are left-associative so the right hand side
+                // needs parentheses if it has the same precedence.
+                lhs.fmt_operand(f, op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                rhs.fmt_operand(f, op.precedence() + 1)
+            }
+            Self::If {
+                then,
+                cond,
+                otherwise,
+            } => {
+                then.fmt_operand(f, 1)?;
+                f.write_str(" if ")?;
+                cond.fmt_operand(f, 1)?;
+                f.write_str(" else ")?;
+                otherwise.fmt_operand(f, 0)
+            }
+            Self::Call(func, args) => {
+                write!(f, "{}(", func.name())?;
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '#'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.#@:?".contains(c)
+}
+
+/// The maximum depth of the tree for an [`Expr`].
+///
+/// Both parsing and evaluating an expression are recursive so this keeps an
+/// untrusted expression from overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+/// A recursive descent parser for [`Expr`].
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+
+    /// An upper bound on the depth of the node currently being parsed.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> ParseExprError {
+        ParseExprError {
+            message,
+            offset: self.pos,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+
+        let rest = self.rest();
+        match rest.strip_prefix(keyword) {
+            Some(after) if !after.starts_with(|c| is_name_char(c) || c == '\\') => {
+                self.pos += keyword.len();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move one level deeper into the expression tree.
+    fn descend(&mut self) -> Result<(), ParseExprError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("expression is nested too deeply"));
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Parse a child node one level deeper in the expression tree.
+    fn nested<F>(&mut self, parse: F) -> Result<Node, ParseExprError>
+    where
+        F: FnOnce(&mut Self) -> Result<Node, ParseExprError>,
+    {
+        self.descend()?;
+        let node = parse(self);
+        self.depth -= 1;
+        node
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    // expr := cond ('if' cond 'else' expr)?
+    fn expr(&mut self) -> Result<Node, ParseExprError> {
+        let then = self.cond()?;
+        if !self.eat_keyword("if") {
+            return Ok(then);
+        }
+
+        let cond = self.cond()?;
+        if !self.eat_keyword("else") {
+            return Err(self.error("expected `else`"));
+        }
+        let otherwise = self.nested(Self::expr)?;
+
+        Ok(Node::If {
+            then: Box::new(then),
+            cond: Box::new(cond),
+            otherwise: Box::new(otherwise),
+        })
+    }
+
+    // cond := sum (('<' | '>') sum)*
+    fn cond(&mut self) -> Result<Node, ParseExprError> {
+        let depth = self.depth;
+        let mut lhs = self.sum()?;
+
+        loop {
+            let op = if self.eat('<') {
+                BinOp::Lt
+            } else if self.eat('>') {
+                BinOp::Gt
+            } else {
+                self.depth = depth;
+                return Ok(lhs);
+            };
+
+            // Each operator nests everything to its left one level deeper.
+            self.descend()?;
+            let rhs = self.sum()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // sum := term (('+' | '-') term)*
+    fn sum(&mut self) -> Result<Node, ParseExprError> {
+        let depth = self.depth;
+        let mut lhs = self.term()?;
+
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                self.depth = depth;
+                return Ok(lhs);
+            };
+
+            // Each operator nests everything to its left one level deeper.
+            self.descend()?;
+            let rhs = self.term()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, ParseExprError> {
+        let depth = self.depth;
+        let mut lhs = self.unary()?;
+
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else {
+                self.depth = depth;
+                return Ok(lhs);
+            };
+
+            // Each operator nests everything to its left one level deeper.
+            self.descend()?;
+            let rhs = self.unary()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // unary := '-' unary | primary
+    fn unary(&mut self) -> Result<Node, ParseExprError> {
+        if self.eat('-') {
+            return Ok(Node::Neg(Box::new(self.nested(Self::unary)?)));
+        }
+
+        self.primary()
+    }
+
+    // number := [0-9.]+ ('e' '-'? [0-9]+)?
+    fn number(&mut self) -> Result<Node, ParseExprError> {
+        let start = self.pos;
+        self.take_while(|c| c.is_ascii_digit() || c == '.');
+
+        let rest = self.rest();
+        if let Some(exponent) = rest.strip_prefix('e') {
+            let digits = exponent.strip_prefix('-').unwrap_or(exponent);
+            if digits.starts_with(|c: char| c.is_ascii_digit()) {
+                self.pos += rest.len() - digits.len();
+                self.take_while(|c| c.is_ascii_digit());
+            }
+        }
+
+        self.input[start..self.pos]
+            .parse()
+            .map(Node::Number)
+            .map_err(|_| ParseExprError {
+                message: "invalid number",
+                offset: start,
+            })
+    }
+
+    fn name(&mut self) -> Result<String, ParseExprError> {
+        let mut name = String::new();
+        let mut chars = self.rest().char_indices();
+
+        while let Some((_, c)) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some((_, escaped)) => name.push(escaped),
+                    None => {
+                        self.pos = self.input.len();
+                        return Err(self.error("unterminated escape sequence"));
+                    }
+                }
+            } else if is_name_char(c) {
+                name.push(c);
+            } else {
+                self.pos += self.rest().len() - chars.as_str().len() - c.len_utf8();
+                return Ok(name);
+            }
+        }
+
+        self.pos = self.input.len();
+        Ok(name)
+    }
+
+    // primary := number | name | name '(' args ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Node, ParseExprError> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let node = self.nested(Self::expr)?;
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if is_name_start(c) || c == '\\' => {
+                let start = self.pos;
+                let name = self.name()?;
+
+                if !self.eat('(') {
+                    return Ok(Node::Event(name));
+                }
+
+                let func = match name.as_str() {
+                    "min" => Func::Min,
+                    "max" => Func::Max,
+                    "d_ratio" => Func::DRatio,
+                    _ => {
+                        return Err(ParseExprError {
+                            message: "unknown function",
+                            offset: start,
+                        })
+                    }
+                };
+
+                let mut args = vec![self.nested(Self::expr)?];
+                while self.eat(',') {
+                    args.push(self.nested(Self::expr)?);
+                }
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+
+                if func == Func::DRatio && args.len() != 2 {
+                    return Err(ParseExprError {
+                        message: "d_ratio takes exactly 2 arguments",
+                        offset: start,
+                    });
+                }
+
+                Ok(Node::Call(func, args))
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+}
+
+/// Error returned when an [`Expr`] cannot be parsed.
+#[derive(Clone, Debug)]
+pub struct ParseExprError {
+    message: &'static str,
+    offset: usize,
+}
+
+impl ParseExprError {
+    /// The byte offset within the expression at which the error occurred.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for ParseExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseExprError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Option<f64> {
+        Expr::parse(expr).unwrap().eval(|name| match name {
+            "a" => Some(2.0),
+            "b" => Some(8.0),
+            "cpu.cycles" => Some(100.0),
+            "zero" => Some(0.0),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Some(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Some(9.0));
+        assert_eq!(eval("8 / 4 / 2"), Some(1.0));
+        assert_eq!(eval("8 - 4 - 2"), Some(2.0));
+        assert_eq!(eval("-a * 3"), Some(-6.0));
+    }
+
+    #[test]
+    fn events_and_functions() {
+        assert_eq!(eval("b / a"), Some(4.0));
+        assert_eq!(eval("cpu.cycles / (a + b)"), Some(10.0));
+        assert_eq!(eval("min(a, b, 5)"), Some(2.0));
+        assert_eq!(eval("max(a, b) * 0.5"), Some(4.0));
+    }
+
+    #[test]
+    fn perf_syntax() {
+        assert_eq!(eval("d_ratio(a, zero)"), Some(0.0));
+        assert_eq!(eval("d_ratio(b, a)"), Some(4.0));
+        assert_eq!(eval("a if b > a else b"), Some(2.0));
+        assert_eq!(eval("a if b < a else b"), Some(8.0));
+        assert_eq!(eval("1 if a < b else missing"), Some(1.0));
+        assert_eq!(eval("1e3 * a"), Some(2000.0));
+        assert_eq!(eval("2.5e-1 * b"), Some(2.0));
+
+        let expr = Expr::parse(r"cpu@INST_RETIRED.ANY\,cmask\=1@ / #slots").unwrap();
+        assert_eq!(
+            expr.events().collect::<Vec<_>>(),
+            ["cpu@INST_RETIRED.ANY,cmask=1@", "#slots"]
+        );
+    }
+
+    #[test]
+    fn eval_failures() {
+        assert_eq!(eval("a / zero"), None);
+        assert_eq!(eval("a + missing"), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Expr::parse("a +").unwrap_err().offset(), 3);
+        assert_eq!(Expr::parse("(a + b").unwrap_err().offset(), 6);
+        assert_eq!(Expr::parse("a b").unwrap_err().offset(), 2);
+        assert_eq!(Expr::parse("foo(a)").unwrap_err().offset(), 0);
+        assert_eq!(Expr::parse("a $ b").unwrap_err().offset(), 2);
+        assert_eq!(Expr::parse("a if b").unwrap_err().offset(), 6);
+        assert_eq!(Expr::parse("d_ratio(a)").unwrap_err().offset(), 0);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let parens = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        Expr::parse(&parens(100)).unwrap();
+        assert!(Expr::parse(&parens(100_000)).is_err());
+
+        assert!(Expr::parse(&format!("{}a", "-".repeat(100_000))).is_err());
+        assert!(Expr::parse(&format!("{}a", "a + ".repeat(100_000))).is_err());
+        assert!(Expr::parse(&format!("{}0", "a if b else ".repeat(100_000))).is_err());
+    }
+
+    #[test]
+    fn events() {
+        let expr = Expr::parse("a / (b + a) * 100").unwrap();
+        assert_eq!(expr.events().collect::<Vec<_>>(), ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for expr in [
+            "a - (b - 1)",
+            "-(a + b) / 2",
+            "min(a, b * 2)",
+            "a / b * 100",
+        ] {
+            let parsed = Expr::parse(expr).unwrap();
+            assert_eq!(parsed.to_string(), expr);
+            assert_eq!(Expr::parse(&parsed.to_string()).unwrap(), parsed);
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Derived metrics computed from a group of counters.
+//!
+//! Raw counter values are often not all that useful on their own. What you
+//! usually want is some ratio between them: instructions per cycle, the
+//! fraction of branches that were mispredicted, and so on. A [`Metric`]
+//! describes such a value as an [`Expr`] over a set of named events. A
+//! [`MetricGroup`] opens all the events needed by a set of metrics as a single
+//! [`Group`] so that they all cover the same period of execution, and then
+//! evaluates the metrics whenever it is read.
+//!
+//! A few common metrics are provided as constructors on [`Metric`]:
+//! - [`Metric::ipc`]
+//! - [`Metric::branch_miss_percent`]
+//! - [`Metric::llc_miss_percent`]
+//! - [`Metric::frontend_stall_ratio`]
+//! - [`Metric::backend_stall_ratio`]
+//!
+//! Vendor-specific events and metrics can be loaded from the JSON files used
+//! by the `perf` tool by using [`PmuEvents`].
+//!
+//! # Example
+//! ```
+//! use perf_event::events::Hardware;
+//! use perf_event::metrics::{Metric, MetricGroup};
+//!
+//! let mut cpi = Metric::new("CPI", "cycles / instructions", "cycles per insn")?;
+//! cpi.event("cycles", Hardware::CPU_CYCLES)
+//!     .event("instructions", Hardware::INSTRUCTIONS);
+//!
+//! let mut group = MetricGroup::new(vec![cpi, Metric::branch_miss_percent()])?;
+//!
+//! group.enable()?;
+//! println!("{:?}", (0..=51).collect::<Vec<_>>());
+//! group.disable()?;
+//!
+//! for value in group.read()? {
+//!     println!("{}: {:.2}", value.name(), value);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::{fmt, io};
+
+use crate::events::{Cache, CacheId, CacheOp, CacheResult, Event, Hardware};
+use crate::{Builder, Counter, Group, GroupData};
+
+mod expr;
+mod json;
+mod pmu_events;
+mod topdown;
+
+pub use self::expr::{Expr, ParseExprError};
+pub use self::pmu_events::{JsonEvent, JsonMetric, PmuEvents, ResolvedEvent};
+pub use self::topdown::{
+    TopDown, TopDownData, TopDownKind, TopDownLevel, TopDownLevel1, TopDownLevel2,
+};
+
+/// A value derived from one or more events.
+///
+/// A metric is made up of a name, an [`Expr`] computing the value of the
+/// metric, the unit of the value, and the events referenced by the expression.
+/// Every name referenced in the expression must be defined via either
+/// [`event`](Self::event) or [`constant`](Self::constant) before the metric
+/// can be added to a [`MetricGroup`].
+///
+/// # Example
+/// ```
+/// use perf_event::events::{Hardware, Software};
+/// use perf_event::metrics::Metric;
+///
+/// let mut metric = Metric::new(
+///     "cycles per page fault",
+///     "cycles / faults",
+///     "cycles per fault",
+/// )?;
+/// metric
+///     .event("cycles", Hardware::CPU_CYCLES)
+///     .event("faults", Software::PAGE_FAULTS);
+/// # Ok::<(), perf_event::metrics::ParseExprError>(())
+/// ```
+#[derive(Clone)]
+pub struct Metric {
+    name: Cow<'static, str>,
+    unit: Cow<'static, str>,
+    expr: Expr,
+    events: Vec<NamedEvent>,
+    constants: Vec<(String, f64)>,
+}
+
+#[derive(Clone)]
+struct NamedEvent {
+    name: String,
+    event: Arc<dyn MetricEvent>,
+}
+
+/// Object-safe wrapper around [`Event`] so that metrics can hold events of
+/// different types.
+trait MetricEvent: Send + Sync {
+    fn configure(&self, builder: &mut Builder);
+}
+
+impl<E> MetricEvent for E
+where
+    E: Event + Clone + Send + Sync,
+{
+    fn configure(&self, builder: &mut Builder) {
+        builder.event(self.clone());
+    }
+}
+
+impl Metric {
+    /// Create a new metric by parsing `expr`.
+    ///
+    /// The events referenced by `expr` must then be defined using
+    /// [`event`](Self::event).
+    pub fn new(
+        name: impl Into<Cow<'static, str>>,
+        expr: &str,
+        unit: impl Into<Cow<'static, str>>,
+    ) -> Result<Self, ParseExprError> {
+        Ok(Self::from_expr(name, Expr::parse(expr)?, unit))
+    }
+
+    /// Create a new metric from an already-parsed expression.
+    pub fn from_expr(
+        name: impl Into<Cow<'static, str>>,
+        expr: Expr,
+        unit: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            unit: unit.into(),
+            expr,
+            events: Vec::new(),
+            constants: Vec::new(),
+        }
+    }
+
+    /// Define the event referred to by `name` within the expression.
+    ///
+    /// Defining the same name twice replaces the earlier definition.
+    pub fn event<E>(&mut self, name: impl Into<String>, event: E) -> &mut Self
+    where
+        E: Event + Clone + Send + Sync + 'static,
+    {
+        let name = name.into();
+        let event = Arc::new(event);
+
+        match self.events.iter_mut().find(|named| named.name == name) {
+            Some(named) => named.event = event,
+            None => self.events.push(NamedEvent { name, event }),
+        }
+
+        self
+    }
+
+    /// Define a name within the expression to have a fixed value.
+    ///
+    /// This takes precedence over an event with the same name.
+    pub fn constant(&mut self, name: impl Into<String>, value: f64) -> &mut Self {
+        let name = name.into();
+
+        match self.constants.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.constants.push((name, value)),
+        }
+
+        self
+    }
+
+    /// The name of this metric.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The unit of the values of this metric.
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// The expression used to compute this metric.
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// The names of the events defined for this metric.
+    pub fn events(&self) -> impl Iterator<Item = &str> {
+        self.events.iter().map(|named| named.name.as_str())
+    }
+
+    /// The constants defined for this metric.
+    pub fn constants(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.constants
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+
+    fn builtin(
+        name: &'static str,
+        expr: &str,
+        unit: &'static str,
+        events: &[(&'static str, Hardware)],
+    ) -> Self {
+        let mut metric = Self::new(name, expr, unit).expect("built-in metric failed to parse");
+        for &(name, event) in events {
+            metric.event(name, event);
+        }
+        metric
+    }
+
+    /// Instructions retired per CPU cycle.
+    pub fn ipc() -> Self {
+        Self::builtin(
+            "IPC",
+            "instructions / cycles",
+            "insn per cycle",
+            &[
+                ("instructions", Hardware::INSTRUCTIONS),
+                ("cycles", Hardware::CPU_CYCLES),
+            ],
+        )
+    }
+
+    /// The percentage of branch instructions that were mispredicted.
+    pub fn branch_miss_percent() -> Self {
+        Self::builtin(
+            "branch-miss",
+            "100 * branch_misses / branches",
+            "%",
+            &[
+                ("branch_misses", Hardware::BRANCH_MISSES),
+                ("branches", Hardware::BRANCH_INSTRUCTIONS),
+            ],
+        )
+    }
+
+    /// The percentage of last-level cache reads that missed the cache.
+    pub fn llc_miss_percent() -> Self {
+        const ACCESS: Cache = Cache {
+            which: CacheId::LL,
+            operation: CacheOp::READ,
+            result: CacheResult::ACCESS,
+        };
+        const MISS: Cache = Cache {
+            result: CacheResult::MISS,
+            ..ACCESS
+        };
+
+        let mut metric = Self::new("LLC-miss", "100 * llc_misses / llc_loads", "%")
+            .expect("built-in metric failed to parse");
+        metric.event("llc_misses", MISS).event("llc_loads", ACCESS);
+        metric
+    }
+
+    /// The fraction of CPU cycles during which the frontend of the processor
+    /// was stalled.
+    pub fn frontend_stall_ratio() -> Self {
+        Self::builtin(
+            "frontend-stall",
+            "stalled_cycles_frontend / cycles",
+            "stalled cycles per cycle",
+            &[
+                ("stalled_cycles_frontend", Hardware::STALLED_CYCLES_FRONTEND),
+                ("cycles", Hardware::CPU_CYCLES),
+            ],
+        )
+    }
+
+    /// The fraction of CPU cycles during which the backend of the processor
+    /// was stalled.
+    pub fn backend_stall_ratio() -> Self {
+        Self::builtin(
+            "backend-stall",
+            "stalled_cycles_backend / cycles",
+            "stalled cycles per cycle",
+            &[
+                ("stalled_cycles_backend", Hardware::STALLED_CYCLES_BACKEND),
+                ("cycles", Hardware::CPU_CYCLES),
+            ],
+        )
+    }
+}
+
+impl fmt::Debug for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metric")
+            .field("name", &self.name)
+            .field("unit", &self.unit)
+            .field("expr", &format_args!("{}", self.expr))
+            .field("events", &self.events().collect::<Vec<_>>())
+            .field("constants", &self.constants)
+            .finish()
+    }
+}
+
+/// A set of [`Metric`]s backed by a single [`Group`].
+///
+/// Creating a `MetricGroup` opens one counter for every distinct event name
+/// used by its metrics. Metrics which use the same name for an event share
+/// the counter, the definition from the first metric that uses the name is
+/// the one that gets opened.
+///
+/// Since counters in a group are scheduled together, the hardware must be able
+/// to count all the events at once. Adding too many hardware events will cause
+/// the group to never be scheduled, in which case every metric will evaluate
+/// to `None`.
+///
+/// See the [module docs](self) for an example.
+pub struct MetricGroup {
+    group: Group,
+    counters: Vec<(String, Counter)>,
+    metrics: Vec<Metric>,
+}
+
+impl MetricGroup {
+    /// Open the counters required by `metrics` for the current process on any
+    /// CPU.
+    pub fn new(metrics: impl IntoIterator<Item = Metric>) -> io::Result<Self> {
+        Self::with_builder(&Group::builder(), metrics)
+    }
+
+    /// Open the counters required by `metrics` using `builder`.
+    ///
+    /// `builder` is used to create the group itself and, with the event
+    /// replaced, each of the counters within the group. This allows
+    /// observing a different process or CPU, including kernel events, and so
+    /// on. [`Group::builder`] is a good starting point.
+    ///
+    /// # Errors
+    /// - An error of kind [`InvalidInput`] is returned if one of the metrics
+    ///   references an event which it does not define.
+    /// - Any errors returned by [`Builder::build_group`] or
+    ///   [`Builder::build_with_group`].
+    ///
+    /// [`InvalidInput`]: io::ErrorKind::InvalidInput
+    pub fn with_builder(
+        builder: &Builder,
+        metrics: impl IntoIterator<Item = Metric>,
+    ) -> io::Result<Self> {
+        let metrics: Vec<Metric> = metrics.into_iter().collect();
+
+        let mut events: Vec<&NamedEvent> = Vec::new();
+        for metric in &metrics {
+            for name in metric.expr.events() {
+                if metric.constants.iter().any(|(n, _)| n == name)
+                    || events.iter().any(|named| named.name == name)
+                {
+                    continue;
+                }
+
+                let named = metric
+                    .events
+                    .iter()
+                    .find(|named| named.name == name)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("metric `{}` uses undefined event `{}`", metric.name, name),
+                        )
+                    })?;
+                events.push(named);
+            }
+        }
+
+        let mut group = builder.build_group()?;
+        let mut member = builder.clone();
+        let mut counters = Vec::with_capacity(events.len());
+        for named in events {
+            named.event.configure(&mut member);
+            let counter = member.build_with_group(&mut group)?;
+            counters.push((named.name.clone(), counter));
+        }
+
+        Ok(Self {
+            group,
+            counters,
+            metrics,
+        })
+    }
+
+    /// The metrics computed by this group.
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    /// Get the counter that was opened for the event named `name`.
+    pub fn counter(&self, name: &str) -> Option<&Counter> {
+        self.counters
+            .iter()
+            .find(|(counter_name, _)| counter_name == name)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Access the underlying group.
+    pub fn as_group(&self) -> &Group {
+        &self.group
+    }
+
+    /// Mutably access the underlying group.
+    pub fn as_group_mut(&mut self) -> &mut Group {
+        &mut self.group
+    }
+
+    /// Enable all counters in the group.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.group.enable()
+    }
+
+    /// Disable all counters in the group.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.group.disable()
+    }
+
+    /// Reset all counters in the group to zero.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.group.reset()
+    }
+
+    /// Read the group and evaluate all metrics.
+    ///
+    /// See [`Group::read`] for the errors that may be returned.
+    pub fn read(&mut self) -> io::Result<Vec<MetricValue>> {
+        let data = self.group.read()?;
+        Ok(self.evaluate(&data))
+    }
+
+    /// Evaluate all metrics using counter values that have already been read
+    /// from the group.
+    ///
+    /// This allows computing metrics over values that have been processed in
+    /// some way. For example, the deltas returned by an [`IntervalReader`]
+    /// wrapping [`as_group_mut`](Self::as_group_mut).
+    ///
+    /// Counter values are scaled to account for multiplexing when the group
+    /// was read with both `TOTAL_TIME_ENABLED` and `TOTAL_TIME_RUNNING`.
+    ///
+    /// [`IntervalReader`]: crate::IntervalReader
+    pub fn evaluate(&self, data: &GroupData) -> Vec<MetricValue> {
+        let counter_value = |name: &str| {
+            let (_, counter) = self.counters.iter().find(|(n, _)| n == name)?;
+            let entry = data.get(counter)?;
+            let value = match (data.time_enabled(), data.time_running()) {
+                (Some(_), Some(_)) => entry.scaled_value()?,
+                _ => entry.value(),
+            };
+
+            Some(value as f64)
+        };
+
+        self.metrics
+            .iter()
+            .map(|metric| {
+                let value = metric.expr.eval(|name| {
+                    match metric.constants.iter().find(|(n, _)| n == name) {
+                        Some(&(_, value)) => Some(value),
+                        None => counter_value(name),
+                    }
+                });
+
+                MetricValue {
+                    name: metric.name.clone(),
+                    unit: metric.unit.clone(),
+                    value,
+                }
+            })
+            .collect()
+    }
+}
+
+impl AsRef<Group> for MetricGroup {
+    fn as_ref(&self) -> &Group {
+        &self.group
+    }
+}
+
+impl AsMut<Group> for MetricGroup {
+    fn as_mut(&mut self) -> &mut Group {
+        &mut self.group
+    }
+}
+
+/// The value of a [`Metric`] as read from a [`MetricGroup`].
+///
+/// The `Display` implementation shows the value followed by its unit, or
+/// `<not counted>` if the metric could not be computed.
+#[derive(Clone, Debug)]
+pub struct MetricValue {
+    name: Cow<'static, str>,
+    unit: Cow<'static, str>,
+    value: Option<f64>,
+}
+
+impl MetricValue {
+    /// The name of the metric.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The unit of the metric.
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// The value of the metric.
+    ///
+    /// This will be `None` if the value could not be computed. This happens if
+    /// the expression divides by zero or if the counters were never scheduled
+    /// onto the CPU.
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+impl fmt::Display for MetricValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(value) => {
+                match f.precision() {
+                    Some(precision) => write!(f, "{:.*}", precision, value)?,
+                    None => write!(f, "{}", value)?,
+                }
+                write!(f, " {}", self.unit)
+            }
+            None => f.write_str("<not counted>"),
+        }
+    }
+}
@@ -0,0 +1,944 @@
+use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+use perf_event_open_sys::bindings::perf_event_attr;
+
+use crate::events::{Dynamic, DynamicBuilder, Event, Raw};
+use crate::metrics::json::{self, Value};
+use crate::metrics::{Expr, Metric};
+use crate::pmu::Pmu;
+
+/// Event and metric definitions loaded from the `perf` tool's pmu-events JSON
+/// files.
+///
+/// The linux kernel source tree contains a description of the vendor-specific
+/// events and metrics for most CPUs under `tools/perf/pmu-events/arch`. This
+/// type loads those JSON files so that events can be referred to by name
+/// instead of by their raw encoding, and so that the metrics defined there can
+/// be computed using a [`MetricGroup`], similar to `perf stat -M`.
+///
+/// Events are resolved to a [`Dynamic`] event using the format of the CPU PMU
+/// under `/sys/bus/event_source/devices/cpu`. If that PMU is not present then
+/// they are instead encoded as a [`Raw`] event using the layout of the current
+/// architecture.
+///
+/// Nothing here checks that the JSON files match the current CPU. It is up to
+/// you to load the right directory for the processor that the code is running
+/// on.
+///
+/// # Example
+/// ```no_run
+/// use perf_event::metrics::{MetricGroup, PmuEvents};
+///
+/// let events = PmuEvents::load("tools/perf/pmu-events/arch/x86/skylake")?;
+/// let mut group = MetricGroup::new(events.metric_group("Summary")?)?;
+///
+/// group.enable()?;
+/// // ... do some work ...
+/// group.disable()?;
+///
+/// for value in group.read()? {
+///     println!("{}: {:.2}", value.name(), value);
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`MetricGroup`]: crate::metrics::MetricGroup
+#[derive(Clone, Debug)]
+pub struct PmuEvents {
+    events: Vec<JsonEvent>,
+    metrics: Vec<JsonMetric>,
+    pmu: PathBuf,
+    literals: Vec<(String, f64)>,
+}
+
+/// An event definition from a pmu-events JSON file.
+#[derive(Clone, Debug)]
+pub struct JsonEvent {
+    name: String,
+    fields: Vec<(String, String)>,
+}
+
+impl JsonEvent {
+    /// The name of the event, as given by `EventName`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A short description of the event, if there is one.
+    pub fn description(&self) -> Option<&str> {
+        self.get("BriefDescription")
+    }
+
+    /// Get the value of any field of the JSON object for this event.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A metric definition from a pmu-events JSON file.
+#[derive(Clone, Debug)]
+pub struct JsonMetric {
+    name: String,
+    expr: String,
+    scale_unit: Option<String>,
+    groups: Option<String>,
+    description: Option<String>,
+}
+
+impl JsonMetric {
+    /// The name of the metric, as given by `MetricName`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The unparsed `MetricExpr` of the metric.
+    pub fn expr(&self) -> &str {
+        &self.expr
+    }
+
+    /// The `ScaleUnit` of the metric, e.g. `100%`.
+    ///
+    /// The value of the expression is multiplied by the number at the start of
+    /// the scale unit and the rest of it is the unit of the metric.
+    pub fn scale_unit(&self) -> Option<&str> {
+        self.scale_unit.as_deref()
+    }
+
+    /// The groups that this metric belongs to.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.groups
+            .as_deref()
+            .unwrap_or("")
+            .split(';')
+            .filter(|group| !group.is_empty())
+    }
+
+    /// A short description of the metric, if there is one.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+}
+
+/// An event resolved from a pmu-events definition.
+///
+/// This is returned by [`PmuEvents::resolve_event`].
+#[derive(Copy, Clone, Debug)]
+pub enum ResolvedEvent {
+    /// The event was resolved using the format exposed by the PMU in sysfs.
+    Dynamic(Dynamic),
+
+    /// The event was encoded directly, either because it specifies its own
+    /// raw config value or because the PMU was not available in sysfs.
+    Raw(Raw),
+}
+
+impl Event for ResolvedEvent {
+    fn update_attrs(self, attr: &mut perf_event_attr) {
+        match self {
+            Self::Dynamic(event) => event.update_attrs(attr),
+            Self::Raw(event) => event.update_attrs(attr),
+        }
+    }
+}
+
+impl PmuEvents {
+    /// Create an empty set of definitions.
+    pub fn new() -> Self {
+        Self {
+            events: Vec::new(),
+            metrics: Vec::new(),
+            pmu: PathBuf::from("cpu"),
+            literals: Vec::new(),
+        }
+    }
+
+    /// Load all definitions from `path`.
+    ///
+    /// See [`add_path`](Self::add_path).
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut events = Self::new();
+        events.add_path(path)?;
+        Ok(events)
+    }
+
+    /// Load the definitions in `path`.
+    ///
+    /// `path` can either be a single JSON file or a directory, in which case
+    /// all `.json` files within the directory and its subdirectories are
+    /// loaded.
+    ///
+    /// # Errors
+    /// Any IO errors from reading the files will be returned directly. Files
+    /// that are not valid JSON or do not have the expected structure result
+    /// in an error of kind [`InvalidData`](io::ErrorKind::InvalidData).
+    pub fn add_path(&mut self, path: impl AsRef<Path>) -> io::Result<&mut Self> {
+        self._add_path(path.as_ref(), &mut HashSet::new())?;
+        Ok(self)
+    }
+
+    /// `visited` contains the device and inode of every directory that has
+    /// already been loaded so that symlink cycles are only walked once.
+    fn _add_path(&mut self, path: &Path, visited: &mut HashSet<(u64, u64)>) -> io::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_dir() {
+            let json = std::fs::read_to_string(path)?;
+            return self
+                ._add_json(&json)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)));
+        }
+
+        if !visited.insert((metadata.dev(), metadata.ino())) {
+            return Ok(());
+        }
+
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "json") {
+                self._add_path(&entry, visited)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load the definitions from the contents of a JSON file.
+    ///
+    /// The JSON must be an array of objects. Objects with a `MetricExpr` are
+    /// metrics and objects with an `EventName` (or an `ArchStdEvent`) are
+    /// events, anything else is ignored.
+    pub fn add_json(&mut self, json: &str) -> io::Result<&mut Self> {
+        self._add_json(json)?;
+        Ok(self)
+    }
+
+    fn _add_json(&mut self, json: &str) -> io::Result<()> {
+        let entries = match json::parse(json) {
+            Ok(Value::Array(entries)) => entries,
+            Ok(_) => return Err(invalid_data("expected a JSON array")),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        for entry in entries {
+            let fields = match &entry {
+                Value::Object(fields) => fields,
+                _ => return Err(invalid_data("expected a JSON object")),
+            };
+
+            if let (Some(name), Some(expr)) =
+                (entry.get_str("MetricName"), entry.get_str("MetricExpr"))
+            {
+                self.metrics.push(JsonMetric {
+                    name,
+                    expr,
+                    scale_unit: entry.get_str("ScaleUnit"),
+                    groups: entry.get_str("MetricGroup"),
+                    description: entry.get_str("BriefDescription"),
+                });
+                continue;
+            }
+
+            let name = match entry
+                .get_str("EventName")
+                .or_else(|| entry.get_str("ArchStdEvent"))
+            {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let fields = fields
+                .iter()
+                .filter_map(|(key, _)| Some((key.clone(), entry.get_str(key)?)))
+                .collect();
+
+            self.events.push(JsonEvent { name, fields });
+        }
+
+        Ok(())
+    }
+
+    /// Set the PMU used to resolve events.
+    ///
+    /// `pmu` can be either the name of a PMU under
+    /// `/sys/bus/event_source/devices` or an absolute path to a PMU directory.
+    /// By default, this is `cpu`.
+    pub fn pmu(&mut self, pmu: impl AsRef<Path>) -> &mut Self {
+        self.pmu = pmu.as_ref().to_path_buf();
+        self
+    }
+
+    /// Set the value of a `#literal` used within metric expressions.
+    ///
+    /// `#smt_on`, `#core_wide`, `#num_cpus`, `#num_cpus_online`, and `#slots`
+    /// are determined automatically from sysfs if they are not set here. Any
+    /// other literals must be set before building a metric that uses them.
+    pub fn literal(&mut self, name: impl Into<String>, value: f64) -> &mut Self {
+        let name = name.into();
+
+        match self
+            .literals
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+        {
+            Some((_, v)) => *v = value,
+            None => self.literals.push((name, value)),
+        }
+
+        self
+    }
+
+    /// Iterate over all the loaded events.
+    pub fn events(&self) -> impl Iterator<Item = &JsonEvent> {
+        self.events.iter()
+    }
+
+    /// Iterate over all the loaded metrics.
+    pub fn metrics(&self) -> impl Iterator<Item = &JsonMetric> {
+        self.metrics.iter()
+    }
+
+    /// Find an event by name. Names are not case-sensitive.
+    pub fn find_event(&self, name: &str) -> Option<&JsonEvent> {
+        self.events
+            .iter()
+            .find(|event| event.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Find a metric by name. Names are not case-sensitive.
+    pub fn find_metric(&self, name: &str) -> Option<&JsonMetric> {
+        self.metrics
+            .iter()
+            .find(|metric| metric.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Resolve an event name, as used within a `MetricExpr`, to an event that
+    /// can be counted.
+    ///
+    /// `name` can be
+    /// - the name of an event from the loaded JSON files,
+    /// - the name of an event exposed by the PMU in sysfs, or,
+    /// - an event with extra terms in the form `pmu@event,term=value@`, e.g.
+    ///   `cpu@INST_RETIRED.ANY,cmask=1@` or `msr@tsc@`.
+    ///
+    /// # Errors
+    /// - An error of kind [`NotFound`] if no event with the name exists.
+    /// - An error of kind [`Unsupported`] if the event uses features that are
+    ///   not supported, such as event modifiers (`EVENT:k`) or belonging to a
+    ///   PMU other than the CPU.
+    /// - An error of kind [`InvalidData`] if the definition of the event does
+    ///   not match the PMU.
+    /// - Any errors from reading the PMU format using [`DynamicBuilder`].
+    ///
+    /// [`NotFound`]: io::ErrorKind::NotFound
+    /// [`Unsupported`]: io::ErrorKind::Unsupported
+    /// [`InvalidData`]: io::ErrorKind::InvalidData
+    pub fn resolve_event(&self, name: &str) -> io::Result<ResolvedEvent> {
+        if let Some((pmu, rest)) = name.split_once('@') {
+            let (spec, modifiers) = rest.split_once('@').unwrap_or((rest, ""));
+            if !modifiers.is_empty() {
+                return Err(unsupported_modifiers(name));
+            }
+
+            return self
+                .resolve_pmu_event(pmu, spec)
+                .map(ResolvedEvent::Dynamic);
+        }
+
+        if name.contains(':') {
+            return Err(unsupported_modifiers(name));
+        }
+
+        if let Some(event) = self.find_event(name) {
+            return self.resolve_json_event(event);
+        }
+
+        let mut builder = DynamicBuilder::with_zeroed_fields(&self.pmu)?;
+        match builder.event(name) {
+            Ok(_) => Ok(ResolvedEvent::Dynamic(builder.build()?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown event `{}`", name),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Build a [`Metric`] for the metric with the given name.
+    ///
+    /// References to other metrics within the expression are expanded and
+    /// every event used by the expression is resolved using
+    /// [`resolve_event`](Self::resolve_event).
+    ///
+    /// # Errors
+    /// - An error of kind [`NotFound`] if there is no metric with the name.
+    /// - An error of kind [`InvalidData`] if the expression cannot be parsed.
+    /// - An error of kind [`Unsupported`] if the expression uses a literal
+    ///   whose value is not known.
+    /// - Any errors from [`resolve_event`](Self::resolve_event).
+    ///
+    /// [`NotFound`]: io::ErrorKind::NotFound
+    /// [`Unsupported`]: io::ErrorKind::Unsupported
+    /// [`InvalidData`]: io::ErrorKind::InvalidData
+    pub fn metric(&self, name: &str) -> io::Result<Metric> {
+        let json = self.find_metric(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown metric `{}`", name),
+            )
+        })?;
+
+        let mut expr = self.metric_expr(json, 0)?;
+        let (scale, unit) = parse_scale_unit(json.scale_unit().unwrap_or(""));
+        if scale != 1.0 {
+            expr = expr.scaled(scale);
+        }
+
+        let mut names: Vec<&str> = expr.events().collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut metric = Metric::from_expr(json.name.clone(), expr.clone(), unit.to_owned());
+        for name in names {
+            if name.starts_with('#') {
+                metric.constant(name, self.literal_value(name)?);
+            } else {
+                metric.event(name, self.resolve_event(name)?);
+            }
+        }
+
+        Ok(metric)
+    }
+
+    /// Build all metrics which belong to `group`.
+    ///
+    /// Group names are not case-sensitive. Vendor metric groups often contain
+    /// metrics that use features which are not supported here, such as
+    /// `duration_time`, event modifiers, or literals like `#num_packages`.
+    /// Like `perf stat -M`, those metrics are left out instead of failing the
+    /// whole group. Use [`metric_group_with_skipped`] to find out which
+    /// metrics were left out and why.
+    ///
+    /// # Errors
+    /// - An error of kind [`NotFound`](io::ErrorKind::NotFound) if no metrics
+    ///   belong to `group`.
+    /// - The error for the first metric that was left out if none of the
+    ///   metrics in `group` could be built.
+    /// - See [`metric_group_with_skipped`] for the other errors.
+    ///
+    /// [`metric_group_with_skipped`]: Self::metric_group_with_skipped
+    pub fn metric_group(&self, group: &str) -> io::Result<Vec<Metric>> {
+        let (metrics, skipped) = self.metric_group_with_skipped(group)?;
+
+        match skipped.into_iter().next() {
+            Some((_, error)) if metrics.is_empty() => Err(error),
+            _ => Ok(metrics),
+        }
+    }
+
+    /// Build all metrics which belong to `group`, along with the name of each
+    /// metric that could not be built and the reason why.
+    ///
+    /// A metric is left out if [`metric`](Self::metric) fails with an error of
+    /// kind [`NotFound`], [`Unsupported`], or [`InvalidData`].
+    ///
+    /// # Errors
+    /// - An error of kind [`NotFound`] if no metrics belong to `group`.
+    /// - Any other errors from [`metric`](Self::metric), e.g. from failing to
+    ///   read the PMU from sysfs.
+    ///
+    /// [`NotFound`]: io::ErrorKind::NotFound
+    /// [`Unsupported`]: io::ErrorKind::Unsupported
+    /// [`InvalidData`]: io::ErrorKind::InvalidData
+    #[allow(clippy::type_complexity)]
+    pub fn metric_group_with_skipped(
+        &self,
+        group: &str,
+    ) -> io::Result<(Vec<Metric>, Vec<(String, io::Error)>)> {
+        let mut metrics = Vec::new();
+        let mut skipped = Vec::new();
+
+        for json in self
+            .metrics
+            .iter()
+            .filter(|metric| metric.groups().any(|g| g.eq_ignore_ascii_case(group)))
+        {
+            match self.metric(&json.name) {
+                Ok(metric) => metrics.push(metric),
+                Err(e) => match e.kind() {
+                    io::ErrorKind::NotFound
+                    | io::ErrorKind::Unsupported
+                    | io::ErrorKind::InvalidData => skipped.push((json.name.clone(), e)),
+                    _ => return Err(e),
+                },
+            }
+        }
+
+        if metrics.is_empty() && skipped.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no metrics in group `{}`", group),
+            ));
+        }
+
+        Ok((metrics, skipped))
+    }
+
+    /// Parse the expression for a metric, inlining the expressions of any
+    /// other metrics that it refers to.
+    fn metric_expr(&self, metric: &JsonMetric, depth: usize) -> io::Result<Expr> {
+        // Metrics referring to each other in a cycle would otherwise recurse
+        // forever.
+        const MAX_DEPTH: usize = 32;
+
+        if depth > MAX_DEPTH {
+            return Err(invalid_data(format!(
+                "metric `{}` is nested too deeply",
+                metric.name
+            )));
+        }
+
+        let expr = Expr::parse(&metric.expr).map_err(|e| {
+            invalid_data(format!(
+                "unable to parse expression for metric `{}`: {}",
+                metric.name, e
+            ))
+        })?;
+
+        expr.replace_events(&mut |name| {
+            if self.find_event(name).is_some() {
+                return Ok(None);
+            }
+
+            match self.find_metric(name) {
+                Some(referenced) => self.metric_expr(referenced, depth + 1).map(Some),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn literal_value(&self, name: &str) -> io::Result<f64> {
+        if let Some(&(_, value)) = self
+            .literals
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            return Ok(value);
+        }
+
+        let smt_on = || -> io::Result<f64> {
+            match std::fs::read_to_string("/sys/devices/system/cpu/smt/active") {
+                Ok(active) => Ok(parse_number(active.trim()).unwrap_or(0) as f64),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0.0),
+                Err(e) => Err(e),
+            }
+        };
+
+        match name.to_ascii_lowercase().as_str() {
+            "#smt_on" => smt_on(),
+            // Counters are opened per-thread so they only cover the whole core
+            // if there is one thread per core.
+            "#core_wide" => Ok(1.0 - smt_on()?),
+            "#num_cpus" | "#num_cpus_online" => Ok(crate::per_cpu::online_cpus()?.len() as f64),
+            "#slots" => {
+                let pmu = Pmu::new(&self.pmu)?;
+                let slots = pmu.cap("slots")?.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("PMU `{}` does not report its number of slots", pmu.name()),
+                    )
+                })?;
+
+                parse_number(&slots)
+                    .map(|slots| slots as f64)
+                    .ok_or_else(|| invalid_data(format!("invalid number of slots `{}`", slots)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("the value of literal `{}` is not known", name),
+            )),
+        }
+    }
+
+    /// Resolve an event of the form `pmu@spec@`.
+    fn resolve_pmu_event(&self, pmu: &str, spec: &str) -> io::Result<Dynamic> {
+        let pmu = match pmu {
+            "cpu" => self.pmu.clone(),
+            pmu => PathBuf::from(pmu),
+        };
+        let mut builder = DynamicBuilder::with_zeroed_fields(&pmu)?;
+
+        for term in spec.split(',') {
+            match term.split_once('=') {
+                Some((field, value)) => {
+                    let value = parse_number(value).ok_or_else(|| {
+                        invalid_data(format!("invalid value for term `{}`", term))
+                    })?;
+                    builder.field(field, value)?;
+                }
+                None => match self.find_event(term) {
+                    Some(event) => {
+                        for (field, value) in self.event_terms(event)?.terms {
+                            set_field(&mut builder, event, field, value)?;
+                        }
+                    }
+                    None => {
+                        builder.event(term)?;
+                    }
+                },
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+
+    fn resolve_json_event(&self, event: &JsonEvent) -> io::Result<ResolvedEvent> {
+        let terms = self.event_terms(event)?;
+        if let Some(config) = terms.config {
+            return Ok(ResolvedEvent::Raw(Raw::new(config)));
+        }
+
+        let mut builder = match DynamicBuilder::with_zeroed_fields(&self.pmu) {
+            Ok(builder) => builder,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return raw_encoding(event, &terms.terms).map(ResolvedEvent::Raw)
+            }
+            Err(e) => return Err(e),
+        };
+
+        for &(field, value) in &terms.terms {
+            set_field(&mut builder, event, field, value)?;
+        }
+
+        Ok(ResolvedEvent::Dynamic(builder.build()?))
+    }
+
+    /// Convert the fields of a JSON event into the terms used by the PMU
+    /// format, following what `perf` does in `jevents.py`.
+    fn event_terms(&self, event: &JsonEvent) -> io::Result<EventTerms> {
+        const FIELD_TERMS: &[(&str, &str)] = &[
+            ("UMask", "umask"),
+            ("CounterMask", "cmask"),
+            ("Invert", "inv"),
+            ("EdgeDetect", "edge"),
+            ("AnyThread", "any"),
+            ("PortMask", "ch_mask"),
+            ("FCMask", "fc_mask"),
+        ];
+
+        // ArchStdEvent refers to a common definition that the event may
+        // override fields of.
+        let base = event
+            .get("ArchStdEvent")
+            .and_then(|name| self.find_event(name))
+            .filter(|base| !std::ptr::eq(*base, event));
+        let get = |field: &str| {
+            event
+                .get(field)
+                .or_else(|| base.and_then(|base| base.get(field)))
+        };
+        let number = |field: &str| -> io::Result<Option<u64>> {
+            let value = match get(field) {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+
+            // Some fields list multiple values, only the first one is used.
+            let first = value.split(',').next().unwrap_or("").trim();
+            match parse_number(first) {
+                Some(value) => Ok(Some(value)),
+                None => Err(invalid_data(format!(
+                    "event `{}` has an invalid value for `{}`: {:?}",
+                    event.name, field, value
+                ))),
+            }
+        };
+
+        if let Some(unit) = get("Unit") {
+            if !unit.eq_ignore_ascii_case("cpu") {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "event `{}` belongs to the `{}` PMU which is not supported",
+                        event.name, unit
+                    ),
+                ));
+            }
+        }
+
+        // The events for the fixed counters on Intel use encodings which are
+        // only meaningful to perf so it replaces them with the equivalent
+        // generic encodings.
+        const FIXED_EVENTS: &[(&str, &[(&str, u64)])] = &[
+            ("inst_retired.any", &[("event", 0xC0)]),
+            ("inst_retired.any_p", &[("event", 0xC0)]),
+            ("cpu_clk_unhalted.ref", &[("event", 0x00), ("umask", 0x03)]),
+            ("cpu_clk_unhalted.thread", &[("event", 0x3C)]),
+            ("cpu_clk_unhalted.core", &[("event", 0x3C)]),
+            (
+                "cpu_clk_unhalted.thread_any",
+                &[("event", 0x3C), ("any", 1)],
+            ),
+        ];
+
+        if let Some((_, fixed)) = FIXED_EVENTS
+            .iter()
+            .find(|(name, _)| event.name.eq_ignore_ascii_case(name))
+        {
+            return Ok(EventTerms {
+                config: None,
+                terms: fixed.to_vec(),
+            });
+        }
+
+        let mut terms = EventTerms {
+            config: number("ConfigCode")?,
+            terms: Vec::new(),
+        };
+
+        if terms.config.is_none() {
+            let code = number("EventCode")?.unwrap_or(0);
+            let extsel = number("ExtSel")?.unwrap_or(0);
+            terms.terms.push(("event", code | (extsel << 8)));
+        }
+
+        for &(field, term) in FIELD_TERMS {
+            if let Some(value) = number(field)? {
+                terms.terms.push((term, value));
+            }
+        }
+
+        if let (Some(index), Some(value)) = (number("MSRIndex")?, number("MSRValue")?) {
+            let term = match index {
+                0x3F6 => "ldlat",
+                0x1A6 | 0x1A7 => "offcore_rsp",
+                0x3F7 => "frontend",
+                _ if value == 0 => "",
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("event `{}` uses unsupported MSR {:#x}", event.name, index),
+                    ))
+                }
+            };
+
+            if !term.is_empty() {
+                terms.terms.push((term, value));
+            }
+        }
+
+        Ok(terms)
+    }
+}
+
+impl Default for PmuEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct EventTerms {
+    /// The raw config value from `ConfigCode`, if present.
+    config: Option<u64>,
+    terms: Vec<(&'static str, u64)>,
+}
+
+fn set_field(
+    builder: &mut DynamicBuilder,
+    event: &JsonEvent,
+    field: &str,
+    value: u64,
+) -> io::Result<()> {
+    if builder.fields().any(|(name, _)| name == field) {
+        builder.field(field, value)?;
+    } else if value != 0 {
+        // Fields that are set to 0 are fine to skip since that is the default
+        // anyway. This happens with e.g. `AnyThread` on newer CPUs.
+        return Err(invalid_data(format!(
+            "event `{}` needs field `{}` which is not supported by the PMU",
+            event.name, field
+        )));
+    }
+
+    Ok(())
+}
+
+/// Encode the terms of an event into a `Raw` event using the layout of the
+/// architectural CPU PMU on x86.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn raw_encoding(event: &JsonEvent, terms: &[(&str, u64)]) -> io::Result<Raw> {
+    let mut raw = Raw::new(0);
+
+    for &(term, value) in terms {
+        match term {
+            // Bits 8-11 of the event select go in bits 32-35 on AMD.
+            "event" => raw.config |= (value & 0xFF) | ((value >> 8) & 0xF) << 32,
+            "umask" => raw.config |= (value & 0xFF) << 8,
+            "edge" => raw.config |= (value & 0x1) << 18,
+            "any" => raw.config |= (value & 0x1) << 21,
+            "inv" => raw.config |= (value & 0x1) << 23,
+            "cmask" => raw.config |= (value & 0xFF) << 24,
+            "ldlat" | "offcore_rsp" | "frontend" => raw.config1 = value,
+            _ if value == 0 => (),
+            _ => return Err(unencodable(event, term)),
+        }
+    }
+
+    Ok(raw)
+}
+
+/// Encode the terms of an event into a `Raw` event where the event code is
+/// the whole config value, as is the case on ARM and RISC-V.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn raw_encoding(event: &JsonEvent, terms: &[(&str, u64)]) -> io::Result<Raw> {
+    let mut raw = Raw::new(0);
+
+    for &(term, value) in terms {
+        match term {
+            "event" => raw.config = value,
+            _ if value == 0 => (),
+            _ => return Err(unencodable(event, term)),
+        }
+    }
+
+    Ok(raw)
+}
+
+fn unencodable(event: &JsonEvent, term: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "cannot encode field `{}` of event `{}` without the PMU format in sysfs",
+            term, event.name
+        ),
+    )
+}
+
+fn unsupported_modifiers(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("event modifiers are not supported: `{}`", name),
+    )
+}
+
+fn invalid_data(message: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Parse a number which may either be hex with a `0x` prefix or decimal.
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Split a `ScaleUnit` such as `100%` into the scale and the unit.
+fn parse_scale_unit(scale_unit: &str) -> (f64, &str) {
+    let scale_unit = scale_unit.trim();
+
+    (1..=scale_unit.len())
+        .rev()
+        .filter(|&len| scale_unit.is_char_boundary(len))
+        .find_map(|len| {
+            let scale = scale_unit[..len].parse::<f64>().ok()?;
+            Some((scale, scale_unit[len..].trim()))
+        })
+        .unwrap_or((1.0, scale_unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_unit() {
+        assert_eq!(parse_scale_unit("100%"), (100.0, "%"));
+        assert_eq!(parse_scale_unit("1per_instr"), (1.0, "per_instr"));
+        assert_eq!(parse_scale_unit("1e9 ns"), (1e9, "ns"));
+        assert_eq!(parse_scale_unit("1events"), (1.0, "events"));
+        assert_eq!(parse_scale_unit("GHz"), (1.0, "GHz"));
+        assert_eq!(parse_scale_unit(""), (1.0, ""));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x3c"), Some(0x3c));
+        assert_eq!(parse_number("0X3C"), Some(0x3c));
+        assert_eq!(parse_number("12"), Some(12));
+        assert_eq!(parse_number("0xZZ"), None);
+    }
+
+    #[test]
+    fn event_terms() {
+        let mut events = PmuEvents::new();
+        events
+            .add_json(
+                r#"[
+                    {
+                        "EventCode": "0xB7, 0xBB",
+                        "EventName": "OFFCORE_RESPONSE",
+                        "MSRIndex": "0x1a6,0x1a7",
+                        "MSRValue": "0x10001",
+                        "UMask": "0x01"
+                    },
+                    {"EventCode": "0x1C0", "EventName": "ext", "ExtSel": "1"},
+                    {"EventName": "uncore", "EventCode": "1", "Unit": "imc"},
+                    {"ArchStdEvent": "BASE", "EventCode": "0x11"},
+                    {"ArchStdEvent": "BASE", "EventName": "DERIVED", "UMask": "2"}
+                ]"#,
+            )
+            .unwrap();
+
+        let terms = |name| events.event_terms(events.find_event(name).unwrap());
+
+        let offcore = terms("offcore_response").unwrap();
+        assert_eq!(
+            offcore.terms,
+            [("event", 0xB7), ("umask", 1), ("offcore_rsp", 0x10001)]
+        );
+        assert_eq!(terms("ext").unwrap().terms, [("event", 0x1C0 | 0x100)]);
+        assert_eq!(
+            terms("uncore").err().unwrap().kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            terms("derived").unwrap().terms,
+            [("event", 0x11), ("umask", 2)]
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn x86_raw_encoding() {
+        let event = JsonEvent {
+            name: "TEST".into(),
+            fields: Vec::new(),
+        };
+
+        let raw = raw_encoding(
+            &event,
+            &[
+                ("event", 0x1C0),
+                ("umask", 0x2),
+                ("cmask", 1),
+                ("inv", 1),
+                ("edge", 0),
+                ("offcore_rsp", 0x10),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(raw.config, 0x1_0180_02C0);
+        assert_eq!(raw.config1, 0x10);
+        assert!(raw_encoding(&event, &[("ch_mask", 1)]).is_err());
+    }
+}
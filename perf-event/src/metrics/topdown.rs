@@ -0,0 +1,871 @@
+use std::path::Path;
+use std::{fmt, io};
+
+use crate::events::{Dynamic, DynamicBuilder, Event, Raw, Software};
+use crate::{Builder, Counter, Group, GroupData, ReadFormat};
+
+/// The PMU that the top-down events are read from.
+const CPU_PMU: &str = "/sys/bus/event_source/devices/cpu";
+
+/// Top-down microarchitecture analysis (TMA).
+///
+/// Top-down analysis breaks down how the pipeline slots of the processor were
+/// used. At level 1, every slot is attributed to one of four categories:
+/// - **retiring**: the slot was used by an operation that eventually retired,
+/// - **bad speculation**: the slot was used by an operation that was later
+///   discarded, e.g. due to a branch misprediction,
+/// - **frontend bound**: the frontend did not deliver an operation for the
+///   slot, and,
+/// - **backend bound**: the backend was not able to accept an operation.
+///
+/// Level 2 further splits each of the level 1 categories in two. See
+/// [`TopDownLevel2`] for details.
+///
+/// The events needed for top-down analysis, and the constraints on how they
+/// can be grouped, vary by processor. `TopDown` takes care of opening the
+/// right events for the current processor. See [`TopDownKind`] for the
+/// processors that are supported.
+///
+/// # Example
+/// ```no_run
+/// use perf_event::metrics::{TopDown, TopDownLevel};
+///
+/// let mut topdown = TopDown::new(TopDownLevel::Level2)?;
+///
+/// topdown.enable()?;
+/// // ... do some work ...
+/// topdown.disable()?;
+///
+/// if let Some(data) = topdown.read()? {
+///     println!("retiring:        {:5.1}%", data.level1.retiring);
+///     println!("bad speculation: {:5.1}%", data.level1.bad_speculation);
+///     println!("frontend bound:  {:5.1}%", data.level1.frontend_bound);
+///     println!("backend bound:   {:5.1}%", data.level1.backend_bound);
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+pub struct TopDown {
+    kind: TopDownKind,
+    level: TopDownLevel,
+    groups: Vec<Group>,
+    members: Vec<Member>,
+}
+
+#[derive(Debug)]
+struct Member {
+    role: Role,
+    group: usize,
+    counter: Counter,
+    scale: f64,
+}
+
+impl fmt::Debug for TopDown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TopDown")
+            .field("kind", &self.kind)
+            .field("level", &self.level)
+            .field("members", &self.members)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Which set of events is used to compute the top-down breakdown.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TopDownKind {
+    /// Intel processors from Ice Lake onwards, which have a fixed `slots`
+    /// counter and report the breakdown in the `PERF_METRICS` register.
+    ///
+    /// The `slots` event must be the leader of the group containing the
+    /// top-down events. Level 2 requires Sapphire Rapids or later.
+    PerfMetrics,
+
+    /// Intel processors before Ice Lake, which expose the `topdown-*` slot
+    /// and bubble events. Only level 1 is supported.
+    IntelSlots,
+
+    /// AMD processors based on Zen 4, using the pipeline utilization events.
+    ///
+    /// Slots lost to contention with the other SMT thread are not attributed
+    /// to any category so the level 1 percentages may add up to less than
+    /// 100%.
+    AmdZen4,
+}
+
+impl TopDownKind {
+    /// Detect which kind of top-down analysis the current processor supports.
+    ///
+    /// Returns `None` if the processor is not supported.
+    pub fn detect() -> Option<Self> {
+        let events = Path::new(CPU_PMU).join("events");
+
+        if events.join("topdown-retiring").exists() {
+            Some(Self::PerfMetrics)
+        } else if events.join("topdown-total-slots").exists() {
+            Some(Self::IntelSlots)
+        } else if is_amd_zen4() {
+            Some(Self::AmdZen4)
+        } else {
+            None
+        }
+    }
+}
+
+/// How detailed the top-down breakdown should be.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TopDownLevel {
+    /// Only compute the level 1 breakdown.
+    Level1,
+
+    /// Compute both the level 1 and level 2 breakdown.
+    ///
+    /// This requires more counters.
+    Level2,
+}
+
+/// The result of reading a [`TopDown`].
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TopDownData {
+    /// The level 1 breakdown.
+    pub level1: TopDownLevel1,
+
+    /// The level 2 breakdown, if [`TopDownLevel::Level2`] was requested.
+    pub level2: Option<TopDownLevel2>,
+}
+
+/// The level 1 top-down breakdown, as a percentage of all pipeline slots.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TopDownLevel1 {
+    /// Slots used by operations that retired.
+    pub retiring: f64,
+
+    /// Slots used by operations that were discarded.
+    pub bad_speculation: f64,
+
+    /// Slots where the frontend did not supply an operation.
+    pub frontend_bound: f64,
+
+    /// Slots where the backend could not accept an operation.
+    pub backend_bound: f64,
+}
+
+/// The level 2 top-down breakdown, as a percentage of all pipeline slots.
+///
+/// Each pair of fields adds up to the corresponding category in
+/// [`TopDownLevel1`].
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TopDownLevel2 {
+    /// Retiring slots used by simple operations.
+    pub light_operations: f64,
+
+    /// Retiring slots used by operations that decode into multiple uops or
+    /// that come from the microcode sequencer.
+    pub heavy_operations: f64,
+
+    /// Bad speculation caused by branch mispredictions.
+    pub branch_mispredicts: f64,
+
+    /// Bad speculation caused by the pipeline being flushed for other
+    /// reasons.
+    pub machine_clears: f64,
+
+    /// Frontend bound slots where no operations were delivered at all.
+    pub fetch_latency: f64,
+
+    /// Frontend bound slots where some, but not enough, operations were
+    /// delivered.
+    pub fetch_bandwidth: f64,
+
+    /// Backend bound slots caused by the memory subsystem.
+    pub memory_bound: f64,
+
+    /// Backend bound slots caused by the execution units.
+    pub core_bound: f64,
+}
+
+/// The events used by the different kinds of top-down analysis.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Role {
+    // PerfMetrics
+    Retiring,
+    BadSpec,
+    FrontendBound,
+    BackendBound,
+    HeavyOps,
+    BranchMispredict,
+    FetchLatency,
+    MemoryBound,
+
+    // IntelSlots
+    TotalSlots,
+    SlotsIssued,
+    SlotsRetired,
+    FetchBubbles,
+    RecoveryBubbles,
+
+    // AmdZen4
+    Cycles,
+    NoOpsFromFrontend,
+    BackendStalls,
+    OpsDispatched,
+    OpsRetired,
+    FrontendLatencyCycles,
+    BranchMispredicts,
+    Resyncs,
+    LoadNotComplete,
+    NotComplete,
+    MicrocodeOpsRetired,
+}
+
+impl TopDown {
+    /// Open the counters for top-down analysis of the current process on any
+    /// CPU.
+    ///
+    /// # Errors
+    /// See [`with_kind`](Self::with_kind).
+    pub fn new(level: TopDownLevel) -> io::Result<Self> {
+        Self::with_builder(&Group::builder(), level)
+    }
+
+    /// Open the counters for top-down analysis using `builder`.
+    ///
+    /// The events and read format of `builder` are replaced but everything
+    /// else, such as the process and CPU being observed, is used as-is. For
+    /// example, to measure a single CPU:
+    ///
+    /// ```no_run
+    /// use perf_event::metrics::{TopDown, TopDownLevel};
+    /// use perf_event::Group;
+    ///
+    /// let mut builder = Group::builder();
+    /// builder.one_cpu(0).any_pid();
+    ///
+    /// let topdown = TopDown::with_builder(&builder, TopDownLevel::Level1)?;
+    /// # std::io::Result::Ok(())
+    /// ```
+    ///
+    /// # Errors
+    /// - An error of kind [`Unsupported`](io::ErrorKind::Unsupported) if the
+    ///   current processor does not support top-down analysis.
+    /// - See [`with_kind`](Self::with_kind) for the other errors.
+    pub fn with_builder(builder: &Builder, level: TopDownLevel) -> io::Result<Self> {
+        let kind = TopDownKind::detect().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "top-down analysis is not supported on this processor",
+            )
+        })?;
+
+        Self::with_kind(builder, kind, level)
+    }
+
+    /// Open the counters for a specific kind of top-down analysis using
+    /// `builder`.
+    ///
+    /// # Errors
+    /// - An error of kind [`Unsupported`](io::ErrorKind::Unsupported) if `kind`
+    ///   does not support level 2 and it was requested.
+    /// - Any errors from reading the PMU events from sysfs.
+    /// - Any errors from building the counters.
+    pub fn with_kind(
+        builder: &Builder,
+        kind: TopDownKind,
+        level: TopDownLevel,
+    ) -> io::Result<Self> {
+        Self::open(builder, Path::new(CPU_PMU), kind, level)
+    }
+
+    /// Open the counters using the events of the CPU PMU at `pmu`.
+    fn open(
+        builder: &Builder,
+        pmu: &Path,
+        kind: TopDownKind,
+        level: TopDownLevel,
+    ) -> io::Result<Self> {
+        let mut builder = builder.clone();
+        builder.read_format(
+            ReadFormat::GROUP
+                | ReadFormat::TOTAL_TIME_ENABLED
+                | ReadFormat::TOTAL_TIME_RUNNING
+                | ReadFormat::ID,
+        );
+
+        let mut topdown = Self {
+            kind,
+            level,
+            groups: Vec::new(),
+            members: Vec::new(),
+        };
+
+        match kind {
+            TopDownKind::PerfMetrics => {
+                // The slots counter must lead the group, otherwise the kernel
+                // will refuse to open the metric events.
+                topdown.add_group(&mut builder, sysfs_event(pmu, "slots")?.0)?;
+
+                let mut events = vec![
+                    (Role::Retiring, "topdown-retiring"),
+                    (Role::BadSpec, "topdown-bad-spec"),
+                    (Role::FrontendBound, "topdown-fe-bound"),
+                    (Role::BackendBound, "topdown-be-bound"),
+                ];
+                if level == TopDownLevel::Level2 {
+                    if !pmu.join("events/topdown-heavy-ops").exists() {
+                        return Err(level2_unsupported());
+                    }
+
+                    events.extend_from_slice(&[
+                        (Role::HeavyOps, "topdown-heavy-ops"),
+                        (Role::BranchMispredict, "topdown-br-mispredict"),
+                        (Role::FetchLatency, "topdown-fetch-lat"),
+                        (Role::MemoryBound, "topdown-mem-bound"),
+                    ]);
+                }
+
+                for (role, name) in events {
+                    topdown.add_sysfs(&mut builder, pmu, 0, role, name)?;
+                }
+            }
+            TopDownKind::IntelSlots => {
+                if level == TopDownLevel::Level2 {
+                    return Err(level2_unsupported());
+                }
+
+                topdown.add_group(&mut builder, Software::DUMMY)?;
+                for (role, name) in [
+                    (Role::TotalSlots, "topdown-total-slots"),
+                    (Role::SlotsIssued, "topdown-slots-issued"),
+                    (Role::SlotsRetired, "topdown-slots-retired"),
+                    (Role::FetchBubbles, "topdown-fetch-bubbles"),
+                    (Role::RecoveryBubbles, "topdown-recovery-bubbles"),
+                ] {
+                    topdown.add_sysfs(&mut builder, pmu, 0, role, name)?;
+                }
+            }
+            TopDownKind::AmdZen4 => {
+                // Zen 4 has 6 general purpose counters so level 2 needs to go
+                // in a separate group. The kernel will multiplex the two.
+                topdown.add_group(&mut builder, Software::DUMMY)?;
+                for (role, event) in [
+                    (Role::Cycles, amd_event(0x076, 0x00, 0)),
+                    (Role::NoOpsFromFrontend, amd_event(0x1A0, 0x01, 0)),
+                    (Role::BackendStalls, amd_event(0x1A0, 0x1E, 0)),
+                    (Role::OpsDispatched, amd_event(0x0AA, 0x07, 0)),
+                    (Role::OpsRetired, amd_event(0x0C1, 0x00, 0)),
+                ] {
+                    topdown.add(&mut builder, 0, role, event, 1.0)?;
+                }
+
+                if level == TopDownLevel::Level2 {
+                    topdown.add_group(&mut builder, Software::DUMMY)?;
+                    for (role, event) in [
+                        (Role::FrontendLatencyCycles, amd_event(0x1A0, 0x01, 6)),
+                        (Role::BranchMispredicts, amd_event(0x0C3, 0x00, 0)),
+                        (Role::Resyncs, amd_event(0x096, 0x00, 0)),
+                        (Role::LoadNotComplete, amd_event(0x0D6, 0xA2, 0)),
+                        (Role::NotComplete, amd_event(0x0D6, 0x02, 0)),
+                        (Role::MicrocodeOpsRetired, amd_event(0x1C1, 0x00, 0)),
+                    ] {
+                        topdown.add(&mut builder, 1, role, event, 1.0)?;
+                    }
+                }
+            }
+        }
+
+        Ok(topdown)
+    }
+
+    fn add_group(&mut self, builder: &mut Builder, leader: impl Event) -> io::Result<()> {
+        builder.event(leader);
+        self.groups.push(builder.build_group()?);
+        Ok(())
+    }
+
+    fn add(
+        &mut self,
+        builder: &mut Builder,
+        group: usize,
+        role: Role,
+        event: impl Event,
+        scale: f64,
+    ) -> io::Result<()> {
+        builder.event(event);
+        let counter = builder.build_with_group(&mut self.groups[group])?;
+
+        self.members.push(Member {
+            role,
+            group,
+            counter,
+            scale,
+        });
+        Ok(())
+    }
+
+    fn add_sysfs(
+        &mut self,
+        builder: &mut Builder,
+        pmu: &Path,
+        group: usize,
+        role: Role,
+        name: &str,
+    ) -> io::Result<()> {
+        let (event, scale) = sysfs_event(pmu, name)?;
+        self.add(builder, group, role, event, scale)
+    }
+
+    /// The kind of top-down analysis being performed.
+    pub fn kind(&self) -> TopDownKind {
+        self.kind
+    }
+
+    /// Enable all the counters.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.groups.iter_mut().try_for_each(|group| group.enable())
+    }
+
+    /// Disable all the counters.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.groups.iter_mut().try_for_each(|group| group.disable())
+    }
+
+    /// Reset all the counters to zero.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.groups.iter_mut().try_for_each(|group| group.reset())
+    }
+
+    /// Read the counters and compute the top-down breakdown.
+    ///
+    /// Returns `None` if the counters have not counted anything yet, e.g.
+    /// because they were never enabled or never scheduled onto the CPU.
+    pub fn read(&mut self) -> io::Result<Option<TopDownData>> {
+        let data = self
+            .groups
+            .iter_mut()
+            .map(|group| group.read())
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(self.compute(&data))
+    }
+
+    fn compute(&self, data: &[GroupData]) -> Option<TopDownData> {
+        let value = |role: Role| -> Option<f64> {
+            let member = self.members.iter().find(|member| member.role == role)?;
+            let entry = data[member.group].get(&member.counter)?;
+            Some(entry.scaled_value()? as f64 * member.scale)
+        };
+        let level2 = self.level == TopDownLevel::Level2;
+
+        match self.kind {
+            TopDownKind::PerfMetrics => {
+                let level1 = [
+                    value(Role::Retiring)?,
+                    value(Role::BadSpec)?,
+                    value(Role::FrontendBound)?,
+                    value(Role::BackendBound)?,
+                ];
+                let level2 = if level2 {
+                    Some([
+                        value(Role::HeavyOps)?,
+                        value(Role::BranchMispredict)?,
+                        value(Role::FetchLatency)?,
+                        value(Role::MemoryBound)?,
+                    ])
+                } else {
+                    None
+                };
+
+                perf_metrics_breakdown(level1, level2)
+            }
+            TopDownKind::IntelSlots => intel_slots_breakdown(
+                value(Role::TotalSlots)?,
+                value(Role::SlotsIssued)?,
+                value(Role::SlotsRetired)?,
+                value(Role::FetchBubbles)?,
+                value(Role::RecoveryBubbles)?,
+            ),
+            TopDownKind::AmdZen4 => {
+                let level1 = [
+                    value(Role::Cycles)?,
+                    value(Role::NoOpsFromFrontend)?,
+                    value(Role::BackendStalls)?,
+                    value(Role::OpsDispatched)?,
+                    value(Role::OpsRetired)?,
+                ];
+                let level2 = if level2 {
+                    Some([
+                        value(Role::FrontendLatencyCycles)?,
+                        value(Role::BranchMispredicts)?,
+                        value(Role::Resyncs)?,
+                        value(Role::LoadNotComplete)?,
+                        value(Role::NotComplete)?,
+                        value(Role::MicrocodeOpsRetired)?,
+                    ])
+                } else {
+                    None
+                };
+
+                amd_zen4_breakdown(level1, level2)
+            }
+        }
+    }
+}
+
+impl TopDownData {
+    /// Decode the raw value of the `PERF_METRICS` register on Intel
+    /// processors.
+    ///
+    /// Each byte of the register is the fraction of slots, out of 255, for
+    /// one of the top-down categories. This is useful if you are reading the
+    /// register directly (e.g. via `rdpmc`) instead of through [`TopDown`].
+    ///
+    /// The level 2 breakdown is only returned if the level 2 bytes are
+    /// non-zero. Returns `None` if the level 1 bytes are all zero.
+    pub fn from_perf_metrics(raw: u64) -> Option<Self> {
+        let byte = |index: u32| ((raw >> (index * 8)) & 0xFF) as f64;
+
+        let level1 = [byte(0), byte(1), byte(2), byte(3)];
+        let level2 = [byte(4), byte(5), byte(6), byte(7)];
+        let has_level2 = level2.iter().any(|&value| value != 0.0);
+
+        perf_metrics_breakdown(level1, has_level2.then_some(level2))
+    }
+}
+
+/// Compute the breakdown from the values of the `topdown-*` metric events,
+/// or the bytes of the `PERF_METRICS` register.
+fn perf_metrics_breakdown(level1: [f64; 4], level2: Option<[f64; 4]>) -> Option<TopDownData> {
+    let [retiring, bad_speculation, frontend_bound, backend_bound] = level1;
+
+    // The metrics don't always add up to exactly the number of slots due to
+    // rounding so normalize them the same way as perf does.
+    let total = retiring + bad_speculation + frontend_bound + backend_bound;
+    if total == 0.0 {
+        return None;
+    }
+    let percent = |value: f64| value / total * 100.0;
+
+    let level1 = TopDownLevel1 {
+        retiring: percent(retiring),
+        bad_speculation: percent(bad_speculation),
+        frontend_bound: percent(frontend_bound),
+        backend_bound: percent(backend_bound),
+    };
+
+    let level2 = level2.map(|[heavy_ops, br_mispredict, fetch_lat, mem_bound]| {
+        let heavy_operations = percent(heavy_ops);
+        let branch_mispredicts = percent(br_mispredict);
+        let fetch_latency = percent(fetch_lat);
+        let memory_bound = percent(mem_bound);
+
+        TopDownLevel2 {
+            light_operations: non_negative(level1.retiring - heavy_operations),
+            heavy_operations,
+            branch_mispredicts,
+            machine_clears: non_negative(level1.bad_speculation - branch_mispredicts),
+            fetch_latency,
+            fetch_bandwidth: non_negative(level1.frontend_bound - fetch_latency),
+            memory_bound,
+            core_bound: non_negative(level1.backend_bound - memory_bound),
+        }
+    });
+
+    Some(TopDownData { level1, level2 })
+}
+
+/// Compute the level 1 breakdown for Intel processors before Ice Lake.
+///
+/// `total_slots` and `recovery_bubbles` must already have been multiplied by
+/// the scale given in sysfs.
+fn intel_slots_breakdown(
+    total_slots: f64,
+    slots_issued: f64,
+    slots_retired: f64,
+    fetch_bubbles: f64,
+    recovery_bubbles: f64,
+) -> Option<TopDownData> {
+    if total_slots == 0.0 {
+        return None;
+    }
+    let percent = |value: f64| (value / total_slots * 100.0).clamp(0.0, 100.0);
+
+    let retiring = percent(slots_retired);
+    let bad_speculation = percent(slots_issued - slots_retired + recovery_bubbles);
+    let frontend_bound = percent(fetch_bubbles);
+    let backend_bound = non_negative(100.0 - retiring - bad_speculation - frontend_bound);
+
+    Some(TopDownData {
+        level1: TopDownLevel1 {
+            retiring,
+            bad_speculation,
+            frontend_bound,
+            backend_bound,
+        },
+        level2: None,
+    })
+}
+
+/// Compute the breakdown for AMD Zen 4 processors using the same formulas as
+/// the pipeline utilization metrics that ship with perf.
+fn amd_zen4_breakdown(level1: [f64; 5], level2: Option<[f64; 6]>) -> Option<TopDownData> {
+    /// The number of dispatch slots per cycle.
+    const DISPATCH_WIDTH: f64 = 6.0;
+
+    let [cycles, no_ops_from_frontend, backend_stalls, ops_dispatched, ops_retired] = level1;
+
+    let total_slots = DISPATCH_WIDTH * cycles;
+    if total_slots == 0.0 {
+        return None;
+    }
+    let percent = |value: f64| non_negative(value / total_slots * 100.0);
+    let ratio = |num: f64, denom: f64| if denom == 0.0 { 0.0 } else { num / denom };
+
+    let level1 = TopDownLevel1 {
+        retiring: percent(ops_retired),
+        bad_speculation: percent(ops_dispatched - ops_retired),
+        frontend_bound: percent(no_ops_from_frontend),
+        backend_bound: percent(backend_stalls),
+    };
+
+    let level2 = level2.map(
+        |[frontend_latency_cycles, mispredicts, resyncs, load_not_complete, not_complete, microcode_ops]| {
+            let fetch_latency = percent(DISPATCH_WIDTH * frontend_latency_cycles)
+                .min(level1.frontend_bound);
+            let branch_mispredicts =
+                level1.bad_speculation * ratio(mispredicts, mispredicts + resyncs);
+            let memory_bound = level1.backend_bound * ratio(load_not_complete, not_complete).min(1.0);
+            let heavy_operations = level1.retiring * ratio(microcode_ops, ops_retired).min(1.0);
+
+            TopDownLevel2 {
+                light_operations: level1.retiring - heavy_operations,
+                heavy_operations,
+                branch_mispredicts,
+                machine_clears: level1.bad_speculation - branch_mispredicts,
+                fetch_latency,
+                fetch_bandwidth: level1.frontend_bound - fetch_latency,
+                memory_bound,
+                core_bound: level1.backend_bound - memory_bound,
+            }
+        },
+    );
+
+    Some(TopDownData { level1, level2 })
+}
+
+fn non_negative(value: f64) -> f64 {
+    value.max(0.0)
+}
+
+fn level2_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "level 2 top-down analysis is not supported on this processor",
+    )
+}
+
+/// Look up an event exposed by the PMU at `pmu` in sysfs, along with its
+/// scale.
+fn sysfs_event(pmu: &Path, name: &str) -> io::Result<(Dynamic, f64)> {
+    let mut builder = DynamicBuilder::with_zeroed_fields(pmu)?;
+    builder.event(name)?;
+
+    let scale = builder.scale()?.unwrap_or(1.0);
+    Ok((builder.build()?, scale))
+}
+
+/// Encode an event for the core PMU on AMD processors.
+fn amd_event(event: u64, umask: u64, cmask: u64) -> Raw {
+    Raw::new((event & 0xFF) | (umask << 8) | (cmask << 24) | ((event >> 8) & 0xF) << 32)
+}
+
+/// Check whether `/proc/cpuinfo` describes an AMD Zen 4 processor.
+fn is_amd_zen4() -> bool {
+    let cpuinfo = match std::fs::read_to_string("/proc/cpuinfo") {
+        Ok(cpuinfo) => cpuinfo,
+        Err(_) => return false,
+    };
+
+    let field = |name: &str| {
+        cpuinfo.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim())
+        })
+    };
+    let number = |name: &str| field(name).and_then(|value| value.parse::<u32>().ok());
+
+    if field("vendor_id") != Some("AuthenticAMD") || number("cpu family") != Some(0x19) {
+        return false;
+    }
+
+    // Family 19h also includes Zen 3, which does not have the pipeline
+    // utilization events.
+    matches!(
+        number("model"),
+        Some(0x10..=0x1F | 0x60..=0x7F | 0xA0..=0xAF)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn decode_perf_metrics() {
+        // retiring = 0x33, bad spec = 0x11, fe bound = 0x22, be bound = 0x99
+        let data = TopDownData::from_perf_metrics(0x9922_1133).unwrap();
+        let total = (0x33 + 0x11 + 0x22 + 0x99) as f64;
+
+        assert_close(data.level1.retiring, 0x33 as f64 / total * 100.0);
+        assert_close(data.level1.bad_speculation, 0x11 as f64 / total * 100.0);
+        assert_close(data.level1.frontend_bound, 0x22 as f64 / total * 100.0);
+        assert_close(data.level1.backend_bound, 0x99 as f64 / total * 100.0);
+        assert_eq!(data.level2, None);
+
+        assert_eq!(TopDownData::from_perf_metrics(0), None);
+    }
+
+    #[test]
+    fn decode_perf_metrics_level2() {
+        let data = TopDownData::from_perf_metrics(0x1010_1010_4040_4040).unwrap();
+        let level2 = data.level2.unwrap();
+
+        assert_close(data.level1.retiring, 25.0);
+        assert_close(level2.heavy_operations, 6.25);
+        assert_close(level2.light_operations, 18.75);
+        assert_close(level2.machine_clears, 18.75);
+        assert_close(level2.fetch_bandwidth, 18.75);
+        assert_close(level2.core_bound, 18.75);
+    }
+
+    #[test]
+    fn intel_slots() {
+        let data = intel_slots_breakdown(1000.0, 600.0, 400.0, 250.0, 50.0).unwrap();
+
+        assert_close(data.level1.retiring, 40.0);
+        assert_close(data.level1.bad_speculation, 25.0);
+        assert_close(data.level1.frontend_bound, 25.0);
+        assert_close(data.level1.backend_bound, 10.0);
+        assert_eq!(intel_slots_breakdown(0.0, 0.0, 0.0, 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn amd_zen4() {
+        let data = amd_zen4_breakdown(
+            [100.0, 120.0, 180.0, 240.0, 180.0],
+            Some([10.0, 3.0, 1.0, 30.0, 40.0, 18.0]),
+        )
+        .unwrap();
+        let level2 = data.level2.unwrap();
+
+        assert_close(data.level1.retiring, 30.0);
+        assert_close(data.level1.bad_speculation, 10.0);
+        assert_close(data.level1.frontend_bound, 20.0);
+        assert_close(data.level1.backend_bound, 30.0);
+
+        assert_close(level2.fetch_latency, 10.0);
+        assert_close(level2.fetch_bandwidth, 10.0);
+        assert_close(level2.branch_mispredicts, 7.5);
+        assert_close(level2.machine_clears, 2.5);
+        assert_close(level2.memory_bound, 22.5);
+        assert_close(level2.core_bound, 7.5);
+        assert_close(level2.heavy_operations, 3.0);
+        assert_close(level2.light_operations, 27.0);
+    }
+
+    /// Open a `TopDown` against the mock kernel and return the `(type, config)`
+    /// of every counter along with the index of its group leader in the order
+    /// they were opened.
+    #[cfg(feature = "hooks")]
+    fn layout(kind: TopDownKind, level: TopDownLevel) -> Vec<(u32, u64, usize)> {
+        use crate::hooks::{clear_thread_hooks, set_thread_hooks, MockKernel};
+
+        let pmu = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/data/topdown-pmu"
+        ));
+        let kernel = MockKernel::new();
+        unsafe { set_thread_hooks(Box::new(kernel.clone())) };
+        let topdown = TopDown::open(&Group::builder(), pmu, kind, level);
+        unsafe { clear_thread_hooks() };
+
+        let topdown = topdown.unwrap();
+        assert!(format!("{:?}", topdown).starts_with("TopDown {"));
+
+        let ids = kernel.ids();
+        ids.iter()
+            .map(|&id| {
+                let attrs = kernel.attrs(id).unwrap();
+                let leader = kernel.leader(id).unwrap();
+                let leader = ids.iter().position(|&id| id == leader).unwrap();
+                (attrs.type_, attrs.config, leader)
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "hooks")]
+    fn perf_metrics_layout() {
+        // slots must lead the group, followed by the level 1 and then the
+        // level 2 metric events.
+        let expected: Vec<_> = std::iter::once((4, 0x0400, 0))
+            .chain((0x80..=0x87).map(|umask| (4, umask << 8, 0)))
+            .collect();
+        assert_eq!(
+            layout(TopDownKind::PerfMetrics, TopDownLevel::Level2),
+            expected
+        );
+        assert_eq!(
+            layout(TopDownKind::PerfMetrics, TopDownLevel::Level1),
+            expected[..5]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hooks")]
+    fn amd_zen4_layout() {
+        use crate::hooks::sys::bindings::{PERF_COUNT_SW_DUMMY, PERF_TYPE_RAW, PERF_TYPE_SOFTWARE};
+
+        let dummy = |leader| (PERF_TYPE_SOFTWARE, PERF_COUNT_SW_DUMMY as u64, leader);
+        let raw = |event: Raw, leader| (PERF_TYPE_RAW, event.config, leader);
+
+        // Level 2 doesn't fit in the same group as level 1 so it gets a
+        // second group with its own leader.
+        let expected = vec![
+            dummy(0),
+            raw(amd_event(0x076, 0x00, 0), 0),
+            raw(amd_event(0x1A0, 0x01, 0), 0),
+            raw(amd_event(0x1A0, 0x1E, 0), 0),
+            raw(amd_event(0x0AA, 0x07, 0), 0),
+            raw(amd_event(0x0C1, 0x00, 0), 0),
+            dummy(6),
+            raw(amd_event(0x1A0, 0x01, 6), 6),
+            raw(amd_event(0x0C3, 0x00, 0), 6),
+            raw(amd_event(0x096, 0x00, 0), 6),
+            raw(amd_event(0x0D6, 0xA2, 0), 6),
+            raw(amd_event(0x0D6, 0x02, 0), 6),
+            raw(amd_event(0x1C1, 0x00, 0), 6),
+        ];
+        assert_eq!(layout(TopDownKind::AmdZen4, TopDownLevel::Level2), expected);
+        assert_eq!(
+            layout(TopDownKind::AmdZen4, TopDownLevel::Level1),
+            expected[..6]
+        );
+    }
+
+    #[test]
+    fn amd_event_encoding() {
+        assert_eq!(amd_event(0x1A0, 0x01, 6).config, 0x1_0600_01A0);
+        assert_eq!(amd_event(0x0C1, 0x00, 0).config, 0xC1);
+    }
+}
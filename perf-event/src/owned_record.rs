@@ -0,0 +1,131 @@
+use std::fmt;
+
+use crate::data::endian::Native;
+use crate::data::parse::{ParseConfig, ParseResult, Parser};
+use crate::data::{self, SampleId};
+use crate::sys::bindings::perf_event_header;
+use crate::Record;
+
+used_in_docs!(Record);
+
+/// A record that owns its data.
+///
+/// Unlike [`Record`], this type does not reference a ring buffer so it can
+/// be kept around for as long as needed. It holds onto the [`ParseConfig`]
+/// of the counter that produced it so that it can still be parsed.
+#[derive(Clone)]
+pub struct OwnedRecord {
+    header: perf_event_header,
+    data: Vec<u8>,
+    config: ParseConfig<Native>,
+}
+
+impl OwnedRecord {
+    /// Create a new record from its header and the bytes following the
+    /// header.
+    pub(crate) fn new(
+        header: perf_event_header,
+        data: Vec<u8>,
+        config: ParseConfig<Native>,
+    ) -> Self {
+        Self {
+            header,
+            data,
+            config,
+        }
+    }
+
+    /// Access the `type` field of the kernel record header.
+    ///
+    /// This indicates the type of the record emitted by the kernel.
+    pub fn ty(&self) -> u32 {
+        self.header.type_
+    }
+
+    /// Access the `misc` field of the kernel record header.
+    ///
+    /// This contains a set of flags that carry some additional metadata on the
+    /// record being emitted by the kernel.
+    pub fn misc(&self) -> u16 {
+        self.header.misc
+    }
+
+    /// Get the length, in bytes, of the data in this record. This does not
+    /// include the record header.
+    #[allow(clippy::len_without_is_empty)] // Records are never empty
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Access the bytes of this record.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Convert this record into its bytes.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The [`ParseConfig`] used to parse this record.
+    pub fn config(&self) -> &ParseConfig<Native> {
+        &self.config
+    }
+
+    /// Parse the data in this record to a [`data::Record`] enum.
+    pub fn parse_record(&self) -> ParseResult<data::Record<'_>> {
+        let mut parser = Parser::new(self.data.as_slice(), self.config.clone());
+        data::Record::parse_with_header(&mut parser, self.header)
+    }
+
+    /// Parse the sample id for the record.
+    ///
+    /// See [`Record::parse_sample_id`] for details.
+    pub fn parse_sample_id(&self) -> ParseResult<SampleId> {
+        crate::sampler::parse_sample_id(self.header, self.data.as_slice(), &self.config)
+    }
+}
+
+impl fmt::Debug for OwnedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedRecord")
+            .field("type", &self.ty())
+            .field("misc", &self.misc())
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::bindings::{perf_event_attr, PERF_RECORD_MMAP};
+    use crate::SampleFlag;
+
+    #[test]
+    fn mmap_sample_id() {
+        let mut attrs = perf_event_attr::default();
+        attrs.sample_type = (SampleFlag::TID | SampleFlag::TIME).bits();
+        attrs.set_sample_id_all(1);
+
+        // The trailing sample id contains the pid/tid and the time.
+        let mut data = Vec::new();
+        data.extend_from_slice(&[1u32, 1].map(u32::to_ne_bytes).concat());
+        data.extend_from_slice(&[0x1000u64, 0x2000, 0].map(u64::to_ne_bytes).concat());
+        data.extend_from_slice(b"/usr/bin/ls\0\0\0\0\0");
+        data.extend_from_slice(&[1u32, 2].map(u32::to_ne_bytes).concat());
+        data.extend_from_slice(&200u64.to_ne_bytes());
+
+        let header = perf_event_header {
+            type_: PERF_RECORD_MMAP,
+            misc: 0,
+            size: (std::mem::size_of::<perf_event_header>() + data.len()) as u16,
+        };
+        let record = OwnedRecord::new(header, data, ParseConfig::from(attrs));
+        let id = record.parse_sample_id().unwrap();
+
+        assert_eq!(id.pid(), Some(1));
+        assert_eq!(id.tid(), Some(2));
+        assert_eq!(id.time(), Some(200));
+    }
+}
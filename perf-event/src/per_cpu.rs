@@ -0,0 +1,316 @@
+use std::io;
+use std::path::Path;
+
+use crate::{Builder, Counter, CounterData};
+
+/// A set of counters, one per CPU, that all count the same event.
+///
+/// The kernel only allows a counter to observe either a single process on any
+/// CPU or every process on a single CPU. Counting an event across the whole
+/// system therefore requires one counter for every CPU. `CpuCounters` opens
+/// and manages that set of counters for you and can sum up their values.
+///
+/// A `CpuCounters` is built from a [`Builder`]. The CPU set on the builder (if
+/// any) is ignored and replaced with each CPU in turn. For system-wide
+/// counting you will usually want to call [`any_pid`] on the builder as well.
+///
+/// # Example
+/// Count context switches across the whole system:
+/// ```no_run
+/// use perf_event::events::Software;
+/// use perf_event::{Builder, CpuCounters};
+///
+/// let mut builder = Builder::new(Software::CONTEXT_SWITCHES);
+/// builder.any_pid();
+///
+/// let mut counters = CpuCounters::new(&builder)?;
+/// counters.enable()?;
+/// std::thread::sleep(std::time::Duration::from_secs(1));
+/// counters.disable()?;
+///
+/// let data = counters.read_full()?;
+/// for (cpu, data) in data.iter() {
+///     println!("cpu {cpu}: {}", data.count());
+/// }
+/// println!("total: {}", data.total().count());
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`any_pid`]: Builder::any_pid
+#[derive(Debug)]
+pub struct CpuCounters {
+    counters: Vec<(usize, Counter)>,
+}
+
+impl CpuCounters {
+    /// Open a counter on every online CPU.
+    ///
+    /// The set of online CPUs is read from `/sys/devices/system/cpu/online`.
+    pub fn new(builder: &Builder) -> io::Result<Self> {
+        Self::with_cpus(builder, online_cpus()?)
+    }
+
+    /// Open a counter on each of the CPUs in `cpus`.
+    pub fn with_cpus<I>(builder: &Builder, cpus: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut builder = builder.clone();
+        let counters = cpus
+            .into_iter()
+            .map(|cpu| Ok((cpu, builder.one_cpu(cpu).build()?)))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { counters })
+    }
+
+    /// The number of counters in this set.
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Whether this set contains no counters at all.
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// Get the counter for `cpu`, if there is one.
+    pub fn get(&self, cpu: usize) -> Option<&Counter> {
+        self.counters
+            .iter()
+            .find(|(c, _)| *c == cpu)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Get a mutable reference to the counter for `cpu`, if there is one.
+    pub fn get_mut(&mut self, cpu: usize) -> Option<&mut Counter> {
+        self.counters
+            .iter_mut()
+            .find(|(c, _)| *c == cpu)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Iterate over the counters in this set along with the CPU that each one
+    /// is observing.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Counter)> {
+        self.counters.iter().map(|(cpu, counter)| (*cpu, counter))
+    }
+
+    /// Iterate mutably over the counters in this set along with the CPU that
+    /// each one is observing.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut Counter)> {
+        self.counters
+            .iter_mut()
+            .map(|(cpu, counter)| (*cpu, counter))
+    }
+
+    /// Convert this set into the individual counters that make it up.
+    pub fn into_counters(self) -> Vec<(usize, Counter)> {
+        self.counters
+    }
+
+    /// Enable every counter in this set.
+    ///
+    /// See [`Counter::enable`] for details.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.for_each(Counter::enable)
+    }
+
+    /// Disable every counter in this set.
+    ///
+    /// See [`Counter::disable`] for details.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.for_each(Counter::disable)
+    }
+
+    /// Reset every counter in this set to zero.
+    ///
+    /// See [`Counter::reset`] for details.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.for_each(Counter::reset)
+    }
+
+    /// Read the sum of the values of all counters in this set.
+    ///
+    /// Note that this does not account for multiplexing. Use [`read_full`]
+    /// if you need the timesharing data as well.
+    ///
+    /// [`read_full`]: Self::read_full
+    pub fn read(&mut self) -> io::Result<u64> {
+        Ok(self.read_full()?.total().count())
+    }
+
+    /// Read all the data for every counter in this set.
+    ///
+    /// This returns both the values for each individual CPU and an aggregate
+    /// across all CPUs. See [`CpuCounterData`] for details.
+    pub fn read_full(&mut self) -> io::Result<CpuCounterData> {
+        let per_cpu = self
+            .counters
+            .iter_mut()
+            .map(|(cpu, counter)| Ok((*cpu, counter.read_full()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(CpuCounterData::new(per_cpu))
+    }
+
+    fn for_each<F>(&mut self, mut func: F) -> io::Result<()>
+    where
+        F: FnMut(&mut Counter) -> io::Result<()>,
+    {
+        self.counters
+            .iter_mut()
+            .try_for_each(|(_, counter)| func(counter))
+    }
+}
+
+/// The data read from a [`CpuCounters`].
+///
+/// This contains the [`CounterData`] read from the counter on each CPU, along
+/// with an aggregate of all of them. The aggregate sums up the counts,
+/// `time_enabled`, `time_running`, and lost values across all CPUs. Since the
+/// times are summed as well, the ratio of `time_enabled` to `time_running`
+/// can still be used to scale the total count.
+#[derive(Clone, Debug)]
+pub struct CpuCounterData {
+    total: CounterData,
+    per_cpu: Vec<(usize, CounterData)>,
+}
+
+impl CpuCounterData {
+    fn new(per_cpu: Vec<(usize, CounterData)>) -> Self {
+        Self {
+            total: CounterData::sum(per_cpu.iter().map(|(_, data)| data)),
+            per_cpu,
+        }
+    }
+
+    /// The aggregate of the data across all CPUs.
+    pub fn total(&self) -> &CounterData {
+        &self.total
+    }
+
+    /// Get the data for `cpu`, if there is one.
+    pub fn get(&self, cpu: usize) -> Option<&CounterData> {
+        self.per_cpu
+            .iter()
+            .find(|(c, _)| *c == cpu)
+            .map(|(_, data)| data)
+    }
+
+    /// Iterate over the data for each CPU.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &CounterData)> {
+        self.per_cpu.iter().map(|(cpu, data)| (*cpu, data))
+    }
+}
+
+/// The sysfs directory containing the CPU devices.
+pub(crate) const CPU_ROOT: &str = "/sys/devices/system/cpu";
+
+/// Read the list of online CPUs from `/sys/devices/system/cpu/online`.
+pub(crate) fn online_cpus() -> io::Result<Vec<usize>> {
+    let list = std::fs::read_to_string(Path::new(CPU_ROOT).join("online"))?;
+    parse_cpu_list(&list)
+}
+
+/// Read the topology id `name` (e.g. `physical_package_id` or `die_id`) of
+/// `cpu` from the CPU devices in `root`.
+///
+/// Returns `None` if the kernel does not export that id.
+pub(crate) fn topology_id(root: &Path, cpu: usize, name: &str) -> io::Result<Option<usize>> {
+    let path = root.join(format!("cpu{cpu}/topology/{name}"));
+    let id = match std::fs::read_to_string(&path) {
+        Ok(id) => id,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    id.trim().parse().map(Some).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid {} `{}` in `{}`", name, id.trim(), path.display()),
+        )
+    })
+}
+
+/// Parse a CPU list in the format used by the kernel within sysfs.
+///
+/// A CPU list is a comma-separated list of either individual CPU numbers or
+/// inclusive ranges of CPUs (e.g. `0-3,8,10-11`).
+pub(crate) fn parse_cpu_list(list: &str) -> io::Result<Vec<usize>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid cpu list `{}`", list.trim()),
+        )
+    };
+
+    let mut cpus = Vec::new();
+    for item in list.trim().split(',').filter(|item| !item.is_empty()) {
+        let (start, end) = match item.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (item, item),
+        };
+
+        let start: usize = start.trim().parse().map_err(|_| invalid())?;
+        let end: usize = end.trim().parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+
+        cpus.extend(start..=end);
+    }
+
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_list_single() {
+        assert_eq!(parse_cpu_list("0\n").unwrap(), [0]);
+    }
+
+    #[test]
+    fn cpu_list_ranges() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n").unwrap(),
+            [0, 1, 2, 3, 8, 10, 11]
+        );
+    }
+
+    #[test]
+    fn cpu_list_empty() {
+        assert_eq!(parse_cpu_list("\n").unwrap(), []);
+    }
+
+    #[test]
+    fn cpu_list_invalid() {
+        assert!(parse_cpu_list("0-").is_err());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a,b").is_err());
+    }
+
+    #[test]
+    fn aggregate_sums_times() {
+        let data = CpuCounterData::new(vec![
+            (
+                0,
+                CounterData::from_parts(10, Some(100), Some(50), None, None),
+            ),
+            (
+                1,
+                CounterData::from_parts(20, Some(100), Some(100), None, None),
+            ),
+        ]);
+
+        let total = data.total();
+        assert_eq!(total.count(), 30);
+        assert_eq!(total.time_enabled().unwrap().as_nanos(), 200);
+        assert_eq!(total.time_running().unwrap().as_nanos(), 150);
+        assert_eq!(total.lost(), None);
+        assert_eq!(data.get(1).unwrap().count(), 20);
+    }
+}
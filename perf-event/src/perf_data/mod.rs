@@ -0,0 +1,74 @@
+//! Support for the `perf.data` file format used by the `perf` tool.
+//!
+//! The format is not formally specified anywhere, the closest thing to a
+//! specification is [`perf.data-file-format.txt`][0] within the linux kernel
+//! repository.
+//!
+//! [0]: https://github.com/torvalds/linux/blob/master/tools/perf/Documentation/perf.data-file-format.txt
+
+use std::io::{self, Write};
+
+mod reader;
+mod writer;
+
+pub use self::reader::{PerfDataEvent, PerfDataReader};
+pub use self::writer::{BuildId, PerfDataWriter};
+
+/// The magic value at the start of a `perf.data` file, `"PERFILE2"`, when
+/// read as a little-endian u64.
+const MAGIC: u64 = u64::from_le_bytes(*b"PERFILE2");
+
+/// The size of `struct perf_file_header`.
+const HEADER_SIZE: u64 = 104;
+
+/// The size of `struct perf_file_section`.
+const SECTION_SIZE: u64 = 16;
+
+/// The number of bits in the `adds_features` bitmap in the file header.
+const FEATURE_BITS: usize = 256;
+
+/// Strings within feature sections are padded out to a multiple of this.
+const NAME_ALIGN: usize = 64;
+
+/// Size of the build id field in a build id record. It is long enough to
+/// contain the longest build id (20 bytes) padded out to a multiple of 8.
+const BUILD_ID_SIZE: usize = 24;
+
+/// Set in the `misc` field of a build id record when the size of the build id
+/// is stored in the last byte of the build id field.
+const PERF_RECORD_MISC_BUILD_ID_SIZE: u16 = 1 << 15;
+
+/// Feature section ids.
+///
+/// These are the bit indices within the `adds_features` bitmap.
+mod feature {
+    pub const BUILD_ID: usize = 2;
+    pub const HOSTNAME: usize = 3;
+    pub const OSRELEASE: usize = 4;
+    pub const NRCPUS: usize = 7;
+    pub const CMDLINE: usize = 11;
+}
+
+/// A `struct perf_file_section`.
+struct Section {
+    offset: u64,
+    size: u64,
+}
+
+impl Section {
+    fn new(offset: u64, size: usize) -> Self {
+        Self {
+            offset,
+            size: size as u64,
+        }
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.offset.to_ne_bytes())?;
+        writer.write_all(&self.size.to_ne_bytes())
+    }
+}
+
+fn align(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
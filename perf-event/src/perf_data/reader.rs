@@ -0,0 +1,578 @@
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem::size_of;
+
+use super::*;
+use crate::data::endian::Native;
+use crate::data::parse::ParseConfig;
+use crate::sys::bindings::{perf_event_attr, perf_event_header, PERF_RECORD_SAMPLE};
+use crate::{OwnedRecord, SampleFlag};
+
+/// Reads records from a file in the `perf.data` format.
+///
+/// Both regular files (as written by `perf record -o perf.data`) and pipe-mode
+/// files (as written by `perf record -o -`) are supported. Records are
+/// returned as [`OwnedRecord`]s that can be parsed in the same way as records
+/// read from a [`Sampler`].
+///
+/// Only files with the same endianness as the current machine can be read.
+///
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+///
+/// use perf_event::data::Record;
+/// use perf_event::PerfDataReader;
+///
+/// let mut reader = PerfDataReader::new(File::open("perf.data")?)?;
+/// for record in &mut reader {
+///     if let Ok(Record::Sample(sample)) = record?.parse_record() {
+///         println!("{:x?}", sample.ip());
+///     }
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Sampler`]: crate::Sampler
+pub struct PerfDataReader<R> {
+    reader: R,
+    events: Vec<PerfDataEvent>,
+
+    /// The number of bytes remaining in the data section, or `None` for
+    /// pipe-mode files.
+    remaining: Option<u64>,
+}
+
+impl<R: Read + Seek> PerfDataReader<R> {
+    /// Read the header and attribute section of a `perf.data` file.
+    ///
+    /// The file is read starting at the beginning of `reader`. Pipe-mode files
+    /// are detected automatically.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        reader.seek(SeekFrom::Start(0))?;
+
+        let size = read_magic(&mut reader)?;
+        if size == PIPE_HEADER_SIZE {
+            return Self::from_pipe_body(reader);
+        }
+        if size != HEADER_SIZE {
+            return Err(invalid_data("perf.data file header had an unexpected size"));
+        }
+
+        let attr_size = read_u64(&mut reader)?;
+        let attrs = read_section(&mut reader)?;
+        let data = read_section(&mut reader)?;
+
+        if attr_size < SECTION_SIZE {
+            return Err(invalid_data("perf.data attr size was too small"));
+        }
+        if attr_size > MAX_ATTR_SIZE + SECTION_SIZE || (attrs.size != 0 && attr_size > attrs.size) {
+            return Err(invalid_data("perf.data attr size was too large"));
+        }
+        if attrs.offset.checked_add(attrs.size).is_none() {
+            return Err(invalid_data("perf.data attr section was out of bounds"));
+        }
+
+        let mut events = Vec::new();
+        for index in 0..attrs.size / attr_size {
+            let offset = index
+                .checked_mul(attr_size)
+                .and_then(|offset| offset.checked_add(attrs.offset))
+                .ok_or_else(|| invalid_data("perf.data attr section was out of bounds"))?;
+
+            reader.seek(SeekFrom::Start(offset))?;
+            let attr = read_attr(&mut reader, attr_size - SECTION_SIZE)?;
+            let ids = read_section(&mut reader)?;
+
+            reader.seek(SeekFrom::Start(ids.offset))?;
+            let ids = (0..ids.size / size_of::<u64>() as u64)
+                .map(|_| read_u64(&mut reader))
+                .collect::<io::Result<_>>()?;
+
+            events.push(PerfDataEvent::new(attr, ids));
+        }
+
+        reader.seek(SeekFrom::Start(data.offset))?;
+
+        Ok(Self {
+            reader,
+            events,
+            remaining: Some(data.size),
+        })
+    }
+}
+
+impl<R: Read> PerfDataReader<R> {
+    /// Read the header of a pipe-mode `perf.data` file.
+    ///
+    /// Unlike [`new`](Self::new) this does not require `reader` to be
+    /// seekable. Attributes for each event are read from the stream as they
+    /// are encountered, so [`events`](Self::events) will only be complete
+    /// once all records referencing them have been read.
+    pub fn from_pipe(mut reader: R) -> io::Result<Self> {
+        if read_magic(&mut reader)? != PIPE_HEADER_SIZE {
+            return Err(invalid_data("not a pipe-mode perf.data file"));
+        }
+
+        Self::from_pipe_body(reader)
+    }
+
+    fn from_pipe_body(reader: R) -> io::Result<Self> {
+        Ok(Self {
+            reader,
+            events: Vec::new(),
+            remaining: None,
+        })
+    }
+
+    /// The events that records in this file were generated by.
+    pub fn events(&self) -> &[PerfDataEvent] {
+        &self.events
+    }
+
+    /// Read the next record from the data section.
+    ///
+    /// Returns `Ok(None)` once the end of the data section has been reached.
+    pub fn next_record(&mut self) -> io::Result<Option<OwnedRecord>> {
+        loop {
+            let (header, data) = match self.read_raw()? {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+
+            match header.type_ {
+                PERF_RECORD_HEADER_ATTR => {
+                    self.process_header_attr(&data)?;
+                    continue;
+                }
+                PERF_RECORD_HEADER_TRACING_DATA => self.skip_tracing_data(&data)?,
+                _ => (),
+            }
+
+            let config = self.config_for(&header, &data);
+            return Ok(Some(OwnedRecord::new(header, data, config)));
+        }
+    }
+
+    /// Read the header and data of the next record.
+    fn read_raw(&mut self) -> io::Result<Option<(perf_event_header, Vec<u8>)>> {
+        const HEADER_LEN: usize = size_of::<perf_event_header>();
+
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+
+        let mut bytes = [0u8; HEADER_LEN];
+        if self.remaining.is_none() {
+            // The end of a pipe-mode file is only indicated by the stream
+            // ending.
+            let len = read_full(&mut self.reader, &mut bytes)?;
+            if len == 0 {
+                return Ok(None);
+            }
+            if len != HEADER_LEN {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        } else {
+            self.reader.read_exact(&mut bytes)?;
+        }
+
+        // SAFETY: perf_event_header is a packed C struct so it is valid to
+        //         copy arbitrary initialized memory into it.
+        let header: perf_event_header = unsafe { std::mem::transmute(bytes) };
+        if (header.size as usize) < HEADER_LEN {
+            return Err(invalid_data("perf.data record had an invalid size"));
+        }
+
+        let mut data = vec![0u8; header.size as usize - HEADER_LEN];
+        self.reader.read_exact(&mut data)?;
+
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(header.size as u64);
+        }
+
+        Ok(Some((header, data)))
+    }
+
+    /// Register the event described by a `PERF_RECORD_HEADER_ATTR` record.
+    fn process_header_attr(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut reader = data;
+        // The size field is right after the type field.
+        let attr_size = match data.get(4..8) {
+            Some(size) => u32::from_ne_bytes(size.try_into().unwrap()) as u64,
+            None => return Err(invalid_data("perf.data attr record was truncated")),
+        };
+        if attr_size as usize > data.len() {
+            return Err(invalid_data("perf.data attr record was truncated"));
+        }
+
+        let attr = read_attr(&mut reader, attr_size)?;
+        let ids = reader
+            .chunks_exact(size_of::<u64>())
+            .map(|id| u64::from_ne_bytes(id.try_into().unwrap()))
+            .collect();
+
+        self.events.push(PerfDataEvent::new(attr, ids));
+        Ok(())
+    }
+
+    /// `PERF_RECORD_HEADER_TRACING_DATA` records are followed by the tracing
+    /// data itself which is not included in the record size.
+    fn skip_tracing_data(&mut self, data: &[u8]) -> io::Result<()> {
+        let size = match data.get(..4) {
+            Some(size) => u32::from_ne_bytes(size.try_into().unwrap()) as u64,
+            None => return Err(invalid_data("perf.data tracing data record was truncated")),
+        };
+        let size = align(size as usize, size_of::<u64>()) as u64;
+
+        let skipped = io::copy(&mut (&mut self.reader).take(size), &mut io::sink())?;
+        if skipped != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(size);
+        }
+
+        Ok(())
+    }
+
+    /// Find the config of the event that generated a record.
+    fn config_for(&self, header: &perf_event_header, data: &[u8]) -> ParseConfig<Native> {
+        let event = match self.events.as_slice() {
+            [] => return ParseConfig::default(),
+            [event] => event,
+            [first, ..] => record_id(first.config(), header, data)
+                .and_then(|id| self.events.iter().find(|event| event.ids.contains(&id)))
+                .unwrap_or(first),
+        };
+
+        event.config().clone()
+    }
+}
+
+impl<R: Read> Iterator for PerfDataReader<R> {
+    type Item = io::Result<OwnedRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// An event described within the attr section of a `perf.data` file.
+#[derive(Clone)]
+pub struct PerfDataEvent {
+    attrs: perf_event_attr,
+    ids: Vec<u64>,
+    config: ParseConfig<Native>,
+}
+
+impl PerfDataEvent {
+    fn new(attrs: perf_event_attr, ids: Vec<u64>) -> Self {
+        Self {
+            attrs,
+            ids,
+            config: ParseConfig::from(attrs),
+        }
+    }
+
+    /// The attributes the event was opened with.
+    pub fn attrs(&self) -> &perf_event_attr {
+        &self.attrs
+    }
+
+    /// The ids of all counters that were opened for this event.
+    pub fn ids(&self) -> &[u64] {
+        &self.ids
+    }
+
+    /// The [`ParseConfig`] for records generated by this event.
+    pub fn config(&self) -> &ParseConfig<Native> {
+        &self.config
+    }
+}
+
+impl std::fmt::Debug for PerfDataEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PerfDataEvent")
+            .field("type", &self.attrs.type_)
+            .field("config", &self.attrs.config)
+            .field("ids", &self.ids)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The size of the header in a pipe-mode file.
+const PIPE_HEADER_SIZE: u64 = 16;
+
+/// The largest `perf_event_attr` that we are willing to read from a file.
+///
+/// The kernel limits `perf_event_attr` to a single page so anything larger
+/// than this is not a valid attr.
+const MAX_ATTR_SIZE: u64 = 4096;
+
+/// Records synthesized by perf when writing pipe-mode files.
+const PERF_RECORD_HEADER_ATTR: u32 = 64;
+const PERF_RECORD_HEADER_TRACING_DATA: u32 = 66;
+
+/// Find the id of the event that generated a record.
+///
+/// Since the id is needed to find the event (and thus the layout of the
+/// record), this requires that every event in the file places the id at the
+/// same position. perf ensures this when writing files.
+fn record_id(config: &ParseConfig<Native>, header: &perf_event_header, data: &[u8]) -> Option<u64> {
+    let sample_type = config.sample_type();
+    let read = |index: usize| {
+        let bytes = data.get(index * 8..index * 8 + 8)?;
+        Some(u64::from_ne_bytes(bytes.try_into().unwrap()))
+    };
+
+    if header.type_ == PERF_RECORD_SAMPLE {
+        if sample_type.contains(SampleFlag::IDENTIFIER) {
+            return read(0);
+        }
+        if !sample_type.contains(SampleFlag::ID) {
+            return None;
+        }
+
+        let index = [
+            SampleFlag::IP,
+            SampleFlag::TID,
+            SampleFlag::TIME,
+            SampleFlag::ADDR,
+        ]
+        .iter()
+        .filter(|&&flag| sample_type.contains(flag))
+        .count();
+
+        return read(index);
+    }
+
+    // For all other records the id is part of the sample_id trailer, which is
+    // parsed from the end of the record.
+    let count = data.len() / 8;
+    let from_end = if sample_type.contains(SampleFlag::IDENTIFIER) {
+        1
+    } else if sample_type.contains(SampleFlag::ID) {
+        1 + [SampleFlag::CPU, SampleFlag::STREAM_ID]
+            .iter()
+            .filter(|&&flag| sample_type.contains(flag))
+            .count()
+    } else {
+        return None;
+    };
+
+    read(count.checked_sub(from_end)?)
+}
+
+/// Read and validate the file magic, returning the size of the header.
+fn read_magic(reader: &mut impl Read) -> io::Result<u64> {
+    let magic = read_u64(reader)?;
+    if magic == MAGIC.swap_bytes() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "perf.data files with a different endianness are not supported",
+        ));
+    }
+    if magic != MAGIC {
+        return Err(invalid_data("not a perf.data file"));
+    }
+
+    read_u64(reader)
+}
+
+/// Read a `perf_event_attr` that is `size` bytes long in the file.
+///
+/// The attr may have been written by a kernel with a different version of
+/// `perf_event_attr`. Any fields not present in the file are left as zero and
+/// any extra fields are ignored.
+fn read_attr(reader: &mut impl Read, size: u64) -> io::Result<perf_event_attr> {
+    if size > MAX_ATTR_SIZE {
+        return Err(invalid_data("perf.data attr size was too large"));
+    }
+
+    let mut bytes = vec![0u8; size as usize];
+    reader.read_exact(&mut bytes)?;
+    bytes.resize(bytes.len().max(size_of::<perf_event_attr>()), 0);
+
+    // SAFETY: perf_event_attr is a plain C struct and all bit patterns are
+    //         valid for it. bytes is at least as long as perf_event_attr.
+    let mut attr: perf_event_attr =
+        unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const perf_event_attr) };
+    attr.size = size_of::<perf_event_attr>() as _;
+    Ok(attr)
+}
+
+fn read_section(reader: &mut impl Read) -> io::Result<Section> {
+    Ok(Section {
+        offset: read_u64(reader)?,
+        size: read_u64(reader)?,
+    })
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_ne_bytes(bytes))
+}
+
+/// Read until `buf` is full or the reader reaches EOF.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_bytes(ty: u32, misc: u16, size: u16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&ty.to_ne_bytes());
+        bytes.extend_from_slice(&misc.to_ne_bytes());
+        bytes.extend_from_slice(&size.to_ne_bytes());
+        bytes
+    }
+
+    fn attr_bytes(attr: &perf_event_attr) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                attr as *const perf_event_attr as *const u8,
+                size_of::<perf_event_attr>(),
+            )
+        }
+    }
+
+    #[test]
+    fn pipe_mode() {
+        let mut attr = perf_event_attr::default();
+        attr.size = size_of::<perf_event_attr>() as _;
+        attr.sample_type = (SampleFlag::IP | SampleFlag::IDENTIFIER).bits();
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&MAGIC.to_ne_bytes());
+        file.extend_from_slice(&PIPE_HEADER_SIZE.to_ne_bytes());
+
+        let attr_len = 8 + size_of::<perf_event_attr>() + 8;
+        file.extend(header_bytes(PERF_RECORD_HEADER_ATTR, 0, attr_len as u16));
+        file.extend_from_slice(attr_bytes(&attr));
+        file.extend_from_slice(&77u64.to_ne_bytes());
+
+        file.extend(header_bytes(PERF_RECORD_SAMPLE, 0, 24));
+        file.extend_from_slice(&77u64.to_ne_bytes());
+        file.extend_from_slice(&0xDEADu64.to_ne_bytes());
+
+        let mut reader = PerfDataReader::from_pipe(file.as_slice()).unwrap();
+        let records = (&mut reader).collect::<io::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(reader.events().len(), 1);
+        assert_eq!(reader.events()[0].ids(), [77]);
+        assert_eq!(records.len(), 1);
+
+        let record = records[0].parse_record().unwrap();
+        match record {
+            crate::data::Record::Sample(sample) => {
+                assert_eq!(sample.id(), Some(77));
+                assert_eq!(sample.ip(), Some(0xDEAD));
+            }
+            record => panic!("expected a sample record, got {:?}", record),
+        }
+    }
+
+    /// Build the header of a regular `perf.data` file.
+    fn file_header(attr_size: u64, attrs: Section, data: Section) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(&MAGIC.to_ne_bytes());
+        for value in [
+            HEADER_SIZE,
+            attr_size,
+            attrs.offset,
+            attrs.size,
+            data.offset,
+            data.size,
+        ] {
+            file.extend_from_slice(&value.to_ne_bytes());
+        }
+        file.resize(HEADER_SIZE as usize, 0);
+        file
+    }
+
+    #[test]
+    fn malformed_header() {
+        let cases = [
+            // attr_size larger than any valid attr.
+            (
+                u64::MAX,
+                Section {
+                    offset: 0,
+                    size: u64::MAX,
+                },
+            ),
+            // attr_size larger than the attr section.
+            (
+                1024,
+                Section {
+                    offset: HEADER_SIZE,
+                    size: 512,
+                },
+            ),
+            // Attr offsets that overflow.
+            (
+                128,
+                Section {
+                    offset: u64::MAX - 64,
+                    size: 256,
+                },
+            ),
+        ];
+
+        for (attr_size, attrs) in cases {
+            let data = Section { offset: 0, size: 0 };
+            let file = file_header(attr_size, attrs, data);
+
+            let error = match PerfDataReader::new(io::Cursor::new(file)) {
+                Ok(_) => panic!("attr size {} was accepted", attr_size),
+                Err(e) => e,
+            };
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", attr_size);
+        }
+    }
+
+    #[test]
+    fn id_position() {
+        let mut attr = perf_event_attr::default();
+        attr.sample_type =
+            (SampleFlag::IP | SampleFlag::TIME | SampleFlag::ID | SampleFlag::CPU).bits();
+        let config = ParseConfig::<Native>::from(attr);
+
+        let data: Vec<u8> = [1u64, 2, 3, 4]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+
+        let sample = perf_event_header {
+            type_: PERF_RECORD_SAMPLE,
+            misc: 0,
+            size: 40,
+        };
+        assert_eq!(record_id(&config, &sample, &data), Some(3));
+
+        let other = perf_event_header {
+            type_: 1,
+            misc: 0,
+            size: 40,
+        };
+        assert_eq!(record_id(&config, &other, &data), Some(3));
+    }
+}
@@ -0,0 +1,445 @@
+use std::convert::TryInto;
+use std::ffi::CStr;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+use super::*;
+use crate::sys::bindings::{
+    perf_event_attr, perf_event_header, PERF_RECORD_MISC_KERNEL, PERF_RECORD_MISC_USER,
+};
+use crate::{check_errno_syscall, Builder, Counter, OwnedRecord, Record};
+
+used_in_docs!(Builder);
+
+/// Writes records to a file in the `perf.data` format.
+///
+/// This allows records collected with a [`Sampler`] to be analyzed using
+/// `perf report`, `perf script`, and other tools that understand the
+/// `perf.data` format.
+///
+/// Every counter that produced records written to the file needs to be
+/// registered with either [`add_counter`] or [`add_event`]. If there is more
+/// than one counter then the records need to be identifiable, usually by
+/// setting [`SampleFlag::IDENTIFIER`] and [`Builder::sample_id_all`].
+///
+/// The file is not valid until [`finish`] has been called.
+///
+/// # Example
+/// ```
+/// use std::io::Cursor;
+///
+/// use perf_event::events::Software;
+/// use perf_event::{Builder, PerfDataWriter, SampleFlag};
+///
+/// let mut sampler = Builder::new(Software::CPU_CLOCK)
+///     .sample_period(100_000)
+///     .sample(SampleFlag::IP | SampleFlag::TID | SampleFlag::TIME)
+///     .enabled(true)
+///     .build()?
+///     .sampled(8192)?;
+///
+/// let mut writer = PerfDataWriter::new(Cursor::new(Vec::new()))?;
+/// writer.add_counter(&sampler);
+/// writer.detect_system_info()?;
+///
+/// // ... do some work ...
+///
+/// while let Some(record) = sampler.next_record() {
+///     writer.write_record(&record)?;
+/// }
+///
+/// let data = writer.finish()?.into_inner();
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Sampler`]: crate::Sampler
+/// [`add_counter`]: Self::add_counter
+/// [`add_event`]: Self::add_event
+/// [`finish`]: Self::finish
+/// [`SampleFlag::IDENTIFIER`]: crate::SampleFlag::IDENTIFIER
+/// [`Builder::sample_id_all`]: crate::Builder::sample_id_all
+pub struct PerfDataWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u64,
+    events: Vec<(perf_event_attr, Vec<u64>)>,
+
+    hostname: Option<String>,
+    osrelease: Option<String>,
+    nrcpus: Option<(u32, u32)>,
+    cmdline: Option<Vec<String>>,
+    build_ids: Vec<BuildId>,
+}
+
+impl<W: Write + Seek> PerfDataWriter<W> {
+    /// Create a new writer.
+    ///
+    /// The file is written starting at the beginning of `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        // Reserve space for the header. It gets filled in by finish once the
+        // location of all the sections is known.
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&[0u8; HEADER_SIZE as usize])?;
+
+        Ok(Self {
+            writer,
+            data_size: 0,
+            events: Vec::new(),
+            hostname: None,
+            osrelease: None,
+            nrcpus: None,
+            cmdline: None,
+            build_ids: Vec::new(),
+        })
+    }
+
+    /// Add an event with the provided attributes and ids.
+    ///
+    /// The attributes will usually come from [`Builder::attrs`] and the ids
+    /// from [`Counter::id`].
+    pub fn add_event(&mut self, attrs: &perf_event_attr, ids: &[u64]) -> &mut Self {
+        let mut attrs = *attrs;
+        attrs.size = size_of::<perf_event_attr>() as _;
+
+        self.events.push((attrs, ids.to_vec()));
+        self
+    }
+
+    /// Add the event for a counter.
+    ///
+    /// If the same event was opened as multiple counters (e.g. one on each
+    /// CPU) then use [`add_event`](Self::add_event) with all the ids instead.
+    pub fn add_counter(&mut self, counter: &Counter) -> &mut Self {
+        self.add_event(counter.attrs(), &[counter.id()])
+    }
+
+    /// Set the hostname of the machine the records were collected on.
+    pub fn hostname(&mut self, hostname: impl Into<String>) -> &mut Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Set the kernel release of the machine the records were collected on.
+    pub fn osrelease(&mut self, osrelease: impl Into<String>) -> &mut Self {
+        self.osrelease = Some(osrelease.into());
+        self
+    }
+
+    /// Set the number of CPUs available and online on the machine the records
+    /// were collected on.
+    pub fn nrcpus(&mut self, available: u32, online: u32) -> &mut Self {
+        self.nrcpus = Some((available, online));
+        self
+    }
+
+    /// Set the command line used to collect the records.
+    pub fn cmdline<I>(&mut self, cmdline: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.cmdline = Some(cmdline.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Add the build id of an object file that is referenced by the records.
+    pub fn build_id(&mut self, build_id: BuildId) -> &mut Self {
+        self.build_ids.push(build_id);
+        self
+    }
+
+    /// Fill in the hostname, osrelease, nrcpus, and cmdline for the current
+    /// machine and process.
+    pub fn detect_system_info(&mut self) -> io::Result<&mut Self> {
+        let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+        check_errno_syscall(|| unsafe { libc::uname(&mut uts) })?;
+
+        let field = |field: &[libc::c_char]| {
+            unsafe { CStr::from_ptr(field.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        let available =
+            check_errno_syscall(|| unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) })?;
+        let online = check_errno_syscall(|| unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) })?;
+
+        self.hostname(field(&uts.nodename))
+            .osrelease(field(&uts.release))
+            .nrcpus(available as u32, online as u32)
+            .cmdline(std::env::args());
+
+        Ok(self)
+    }
+
+    /// Write a record from a [`Sampler`](crate::Sampler) to the data section.
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        self.write_raw(record.ty(), record.misc(), &record.to_contiguous())
+    }
+
+    /// Write an [`OwnedRecord`] to the data section.
+    pub fn write_owned_record(&mut self, record: &OwnedRecord) -> io::Result<()> {
+        self.write_raw(record.ty(), record.misc(), record.data())
+    }
+
+    /// Write a record with the provided header fields and data to the data
+    /// section.
+    ///
+    /// `data` does not include the record header.
+    pub fn write_raw(&mut self, ty: u32, misc: u16, data: &[u8]) -> io::Result<()> {
+        let size = size_of::<perf_event_header>() + data.len();
+        let size: u16 = size.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record is too large to fit in a perf_event_header",
+            )
+        })?;
+
+        self.writer.write_all(&ty.to_ne_bytes())?;
+        self.writer.write_all(&misc.to_ne_bytes())?;
+        self.writer.write_all(&size.to_ne_bytes())?;
+        self.writer.write_all(data)?;
+        self.data_size += size as u64;
+
+        Ok(())
+    }
+
+    /// Write out the feature and attribute sections along with the file
+    /// header, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data = Section {
+            offset: HEADER_SIZE,
+            size: self.data_size,
+        };
+
+        // Feature sections must come directly after the data section.
+        let features = self.encode_features();
+        let table_size = features.len() as u64 * SECTION_SIZE;
+        let mut offset = data.offset + data.size + table_size;
+        let mut bitmap = [0u64; FEATURE_BITS / 64];
+        for (id, bytes) in &features {
+            bitmap[id / 64] |= 1 << (id % 64);
+            Section::new(offset, bytes.len()).write(&mut self.writer)?;
+            offset += bytes.len() as u64;
+        }
+        for (_, bytes) in &features {
+            self.writer.write_all(bytes)?;
+        }
+
+        // Then the ids for each event followed by the attr section which
+        // references them.
+        let mut id_sections = Vec::with_capacity(self.events.len());
+        for (_, ids) in &self.events {
+            id_sections.push(Section::new(offset, ids.len() * size_of::<u64>()));
+            for id in ids {
+                self.writer.write_all(&id.to_ne_bytes())?;
+                offset += size_of::<u64>() as u64;
+            }
+        }
+
+        let attr_size = (size_of::<perf_event_attr>() as u64) + SECTION_SIZE;
+        let attrs = Section {
+            offset,
+            size: attr_size * self.events.len() as u64,
+        };
+        for ((attr, _), ids) in self.events.iter().zip(id_sections) {
+            // SAFETY: perf_event_attr is a plain C struct.
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    attr as *const perf_event_attr as *const u8,
+                    size_of::<perf_event_attr>(),
+                )
+            };
+            self.writer.write_all(bytes)?;
+            ids.write(&mut self.writer)?;
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&MAGIC.to_ne_bytes())?;
+        self.writer.write_all(&HEADER_SIZE.to_ne_bytes())?;
+        self.writer.write_all(&attr_size.to_ne_bytes())?;
+        attrs.write(&mut self.writer)?;
+        data.write(&mut self.writer)?;
+        // event_types, which is no longer used by perf.
+        Section::new(0, 0).write(&mut self.writer)?;
+        for word in bitmap {
+            self.writer.write_all(&word.to_ne_bytes())?;
+        }
+
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Encode each feature section that has been set, ordered by feature id.
+    fn encode_features(&self) -> Vec<(usize, Vec<u8>)> {
+        let mut features = Vec::new();
+
+        if !self.build_ids.is_empty() {
+            let mut bytes = Vec::new();
+            for build_id in &self.build_ids {
+                build_id.encode(&mut bytes);
+            }
+            features.push((feature::BUILD_ID, bytes));
+        }
+        if let Some(hostname) = &self.hostname {
+            let mut bytes = Vec::new();
+            encode_string(&mut bytes, hostname);
+            features.push((feature::HOSTNAME, bytes));
+        }
+        if let Some(osrelease) = &self.osrelease {
+            let mut bytes = Vec::new();
+            encode_string(&mut bytes, osrelease);
+            features.push((feature::OSRELEASE, bytes));
+        }
+        if let Some((available, online)) = self.nrcpus {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&available.to_ne_bytes());
+            bytes.extend_from_slice(&online.to_ne_bytes());
+            features.push((feature::NRCPUS, bytes));
+        }
+        if let Some(cmdline) = &self.cmdline {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&(cmdline.len() as u32).to_ne_bytes());
+            for arg in cmdline {
+                encode_string(&mut bytes, arg);
+            }
+            features.push((feature::CMDLINE, bytes));
+        }
+
+        features
+    }
+}
+
+/// The build id of an object file referenced by records in a `perf.data`
+/// file.
+///
+/// This is used by tools like `perf report` to find the correct debug info
+/// for an object file.
+#[derive(Clone, Debug)]
+pub struct BuildId {
+    pid: i32,
+    kernel: bool,
+    build_id: Vec<u8>,
+    filename: String,
+}
+
+impl BuildId {
+    /// Create a build id for a user-space object file mapped by `pid`.
+    ///
+    /// Build ids longer than 20 bytes will be truncated.
+    pub fn user(pid: i32, build_id: &[u8], filename: impl Into<String>) -> Self {
+        Self {
+            pid,
+            kernel: false,
+            build_id: build_id.iter().copied().take(20).collect(),
+            filename: filename.into(),
+        }
+    }
+
+    /// Create a build id for the kernel or a kernel module.
+    ///
+    /// Build ids longer than 20 bytes will be truncated.
+    pub fn kernel(build_id: &[u8], filename: impl Into<String>) -> Self {
+        Self {
+            // This is what perf uses for the host kernel.
+            pid: -1,
+            kernel: true,
+            build_id: build_id.iter().copied().take(20).collect(),
+            filename: filename.into(),
+        }
+    }
+
+    /// Encode this build id as a `struct build_id_event`.
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let filename_len = align(self.filename.len() + 1, NAME_ALIGN);
+        let size = size_of::<perf_event_header>() + 4 + BUILD_ID_SIZE + filename_len;
+
+        let mut misc = if self.kernel {
+            PERF_RECORD_MISC_KERNEL
+        } else {
+            PERF_RECORD_MISC_USER
+        } as u16;
+        if self.build_id.len() != 20 {
+            misc |= PERF_RECORD_MISC_BUILD_ID_SIZE;
+        }
+
+        let mut build_id = [0u8; BUILD_ID_SIZE];
+        build_id[..self.build_id.len()].copy_from_slice(&self.build_id);
+        build_id[20] = self.build_id.len() as u8;
+
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        bytes.extend_from_slice(&misc.to_ne_bytes());
+        bytes.extend_from_slice(&(size as u16).to_ne_bytes());
+        bytes.extend_from_slice(&self.pid.to_ne_bytes());
+        bytes.extend_from_slice(&build_id);
+        bytes.extend_from_slice(self.filename.as_bytes());
+        bytes.resize(bytes.len() + filename_len - self.filename.len(), 0);
+    }
+}
+
+/// Encode a string in the format used by feature sections: a u32 length
+/// followed by the nul-terminated string padded to a multiple of
+/// [`NAME_ALIGN`].
+fn encode_string(bytes: &mut Vec<u8>, string: &str) {
+    let len = align(string.len() + 1, NAME_ALIGN);
+
+    bytes.extend_from_slice(&(len as u32).to_ne_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+    bytes.resize(bytes.len() + len - string.len(), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::bindings::PERF_RECORD_SAMPLE;
+    use crate::SampleFlag;
+
+    #[test]
+    fn string_encoding() {
+        let mut bytes = Vec::new();
+        encode_string(&mut bytes, "test");
+
+        assert_eq!(bytes.len(), 4 + NAME_ALIGN);
+        assert_eq!(&bytes[..4], &(NAME_ALIGN as u32).to_ne_bytes());
+        assert_eq!(&bytes[4..9], b"test\0");
+    }
+
+    #[test]
+    fn build_id_encoding() {
+        let mut bytes = Vec::new();
+        BuildId::user(5, &[0xAB; 20], "/usr/lib/libc.so.6").encode(&mut bytes);
+
+        let size = u16::from_ne_bytes([bytes[6], bytes[7]]) as usize;
+        assert_eq!(size, bytes.len());
+        assert_eq!(
+            u16::from_ne_bytes([bytes[4], bytes[5]]),
+            PERF_RECORD_MISC_USER as u16
+        );
+        assert_eq!(&bytes[12..32], &[0xAB; 20]);
+        assert_eq!(&bytes[36..54], b"/usr/lib/libc.so.6");
+    }
+
+    #[test]
+    fn magic_round_trip() {
+        let mut attr = perf_event_attr::default();
+        attr.sample_type = SampleFlag::IP.bits();
+
+        let mut writer = PerfDataWriter::new(io::Cursor::new(Vec::new())).unwrap();
+        writer.add_event(&attr, &[1]);
+        writer
+            .write_raw(PERF_RECORD_SAMPLE, 0, &0xDEADu64.to_ne_bytes())
+            .unwrap();
+        let file = writer.finish().unwrap();
+
+        // perf expects the magic in native byte order and uses it to detect
+        // files written on a host with a different endianness.
+        assert_eq!(&file.get_ref()[..8], &MAGIC.to_ne_bytes());
+
+        let mut reader = PerfDataReader::new(file).unwrap();
+        assert_eq!(reader.events()[0].ids(), [1]);
+
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(record.ty(), PERF_RECORD_SAMPLE);
+        assert_eq!(record.data(), 0xDEADu64.to_ne_bytes());
+        assert!(reader.next().is_none());
+    }
+}
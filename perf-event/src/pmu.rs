@@ -0,0 +1,616 @@
+//! Discovery of the performance monitoring units (PMUs) exposed by the kernel.
+//!
+//! Every PMU that can be used with `perf_event_open` has a directory under
+//! `/sys/bus/event_source/devices`. This includes the built-in ones (`cpu`,
+//! `software`, `tracepoint`, ...) as well as dynamic PMUs such as `msr`,
+//! `power`, or the uncore PMUs. The directory describes
+//! - the `type` value to use in `perf_event_attr`,
+//! - the `format` of the config fields that the PMU accepts,
+//! - a set of named `events`, along with their scale and unit,
+//! - which CPUs the PMU counts on (`cpumask`),
+//! - the capabilities of the PMU (`caps`), and,
+//! - the number of address filters it supports (`nr_addr_filters`).
+//!
+//! [`Pmu`] reads all of these. To open a counter for one of the events, use
+//! [`Pmu::builder`] to get a [`DynamicBuilder`].
+//!
+//! # Example
+//! Print all the PMU events on the system, similar to `perf list pmu`:
+//! ```
+//! use perf_event::pmu::Pmu;
+//!
+//! for pmu in Pmu::all()? {
+//!     for event in pmu.events()? {
+//!         println!("{}/{}/", pmu.name(), event.name());
+//!     }
+//! }
+//! # std::io::Result::Ok(())
+//! ```
+//!
+//! The format of these files is documented in the kernel under
+//! `Documentation/ABI/testing/sysfs-bus-event_source-devices-*`.
+
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+use crate::events::error::DynamicBuilderError;
+use crate::events::DynamicBuilder;
+use crate::per_cpu::parse_cpu_list;
+
+type Error = DynamicBuilderError;
+
+/// The directory under which the kernel exposes PMUs.
+const PMU_ROOT: &str = "/sys/bus/event_source/devices";
+
+/// Suffixes of files in the `events` directory that describe an event instead
+/// of being an event themselves.
+const EVENT_PROPERTIES: &[&str] = &["scale", "unit", "per-pkg", "snapshot"];
+
+/// A performance monitoring unit exposed via sysfs.
+#[derive(Clone, Debug)]
+pub struct Pmu {
+    name: String,
+    path: PathBuf,
+}
+
+impl Pmu {
+    /// Get the PMU with the given name.
+    ///
+    /// `pmu` can be either
+    /// - an absolute path to the PMU directory, or,
+    /// - the name of a pmu under `/sys/bus/event_source/devices`.
+    ///
+    /// # Errors
+    /// Returns an error of kind [`NotFound`](io::ErrorKind::NotFound) if the
+    /// PMU directory does not exist.
+    pub fn new(pmu: impl AsRef<Path>) -> io::Result<Self> {
+        Self::_new(pmu.as_ref())
+    }
+
+    fn _new(pmu: &Path) -> io::Result<Self> {
+        let path = Path::new(PMU_ROOT).join(pmu);
+        if !path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("PMU `{}` does not exist", pmu.display()),
+            ));
+        }
+
+        Ok(Self::from_path(path))
+    }
+
+    fn from_path(path: PathBuf) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Self { name, path }
+    }
+
+    /// List all the PMUs on the current system, sorted by name.
+    pub fn all() -> io::Result<Vec<Self>> {
+        let mut pmus = Vec::new();
+        for entry in std::fs::read_dir(PMU_ROOT)? {
+            pmus.push(Self::from_path(entry?.path()));
+        }
+
+        pmus.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(pmus)
+    }
+
+    /// List the core PMUs that count [`Hardware`] and [`Cache`] events.
+    ///
+    /// On hybrid systems (e.g. Intel Alder Lake and later) there is one core
+    /// PMU per type of core, such as `cpu_core` and `cpu_atom`. Otherwise this
+    /// is just the `cpu` PMU, if there is one. See [`is_hybrid`] to check
+    /// which is the case.
+    ///
+    /// [`Hardware`]: crate::events::Hardware
+    /// [`Cache`]: crate::events::Cache
+    pub fn core_pmus() -> io::Result<Vec<Self>> {
+        let hybrid: Vec<_> = Self::all()?
+            .into_iter()
+            .filter(|pmu| pmu.name.starts_with("cpu_") && pmu.path.join("cpus").exists())
+            .collect();
+
+        if !hybrid.is_empty() {
+            return Ok(hybrid);
+        }
+
+        match Self::new("cpu") {
+            Ok(pmu) => Ok(vec![pmu]),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The name of this PMU.
+    ///
+    /// This is the name that `perf` uses for the PMU, e.g. `cpu` or `msr`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The sysfs directory for this PMU.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the type of this PMU.
+    ///
+    /// This is the value that goes in the `type` field of `perf_event_attr`.
+    pub fn ty(&self) -> io::Result<u32> {
+        let path = self.path.join("type");
+        match std::fs::read_to_string(&path)?.trim().parse() {
+            Ok(ty) => Ok(ty),
+            Err(e) => Err(Error::parse(path, e).into()),
+        }
+    }
+
+    /// Read all the format fields of this PMU, sorted by name.
+    ///
+    /// PMUs which do not accept any config fields (e.g. `software`) return an
+    /// empty list.
+    pub fn formats(&self) -> io::Result<Vec<Format>> {
+        let mut formats = Vec::new();
+        for path in read_dir_sorted(&self.path.join("format"))? {
+            formats.push(Format::read(path)?);
+        }
+
+        Ok(formats)
+    }
+
+    /// Read a single format field of this PMU.
+    ///
+    /// Returns `None` if the PMU has no field named `name`.
+    pub fn format(&self, name: &str) -> io::Result<Option<Format>> {
+        optional(Format::read(self.path.join("format").join(name)))
+    }
+
+    /// Read all the events exposed by this PMU, sorted by name.
+    pub fn events(&self) -> io::Result<Vec<PmuEvent>> {
+        let mut events = Vec::new();
+        for path in read_dir_sorted(&self.path.join("events"))? {
+            let is_property = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| EVENT_PROPERTIES.contains(&ext));
+
+            if !is_property {
+                events.push(PmuEvent::read(path)?);
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Read a single event exposed by this PMU.
+    ///
+    /// Returns `None` if the PMU has no event named `name`.
+    pub fn event(&self, name: &str) -> io::Result<Option<PmuEvent>> {
+        optional(PmuEvent::read(self.path.join("events").join(name)))
+    }
+
+    /// Read the CPUs that this PMU counts on.
+    ///
+    /// Counters for PMUs with a cpumask, such as uncore PMUs, should be opened
+    /// on one of the listed CPUs (usually one per socket). Returns `None` if
+    /// the PMU does not restrict which CPUs it can be used on.
+    ///
+    /// On systems with heterogeneous cores, the core PMUs (e.g. `cpu_core`
+    /// and `cpu_atom`) list their CPUs in a `cpus` file instead. This method
+    /// reads that if there is no `cpumask` file.
+    pub fn cpumask(&self) -> io::Result<Option<Vec<usize>>> {
+        for file in ["cpumask", "cpus"] {
+            if let Some(list) = optional(std::fs::read_to_string(self.path.join(file)))? {
+                return parse_cpu_list(&list).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Read the capabilities advertised by this PMU, sorted by name.
+    ///
+    /// These are the files under the `caps` directory, e.g. `max_precise` or
+    /// `branches`. Their values are returned as-is, with trailing whitespace
+    /// removed.
+    pub fn caps(&self) -> io::Result<Vec<(String, String)>> {
+        let mut caps = Vec::new();
+        for path in read_dir_sorted(&self.path.join("caps"))? {
+            let name = file_name(&path)?;
+            let value = read_trimmed(&path)?;
+            caps.push((name, value));
+        }
+
+        Ok(caps)
+    }
+
+    /// Read a single capability of this PMU.
+    ///
+    /// Returns `None` if the PMU does not advertise the capability.
+    pub fn cap(&self, name: &str) -> io::Result<Option<String>> {
+        optional(read_trimmed(&self.path.join("caps").join(name)))
+    }
+
+    /// Read the number of address filters supported by this PMU.
+    ///
+    /// Returns `None` if the PMU does not support address filtering.
+    pub fn nr_addr_filters(&self) -> io::Result<Option<u32>> {
+        let path = self.path.join("nr_addr_filters");
+        match optional(read_trimmed(&path))? {
+            Some(text) => match text.parse() {
+                Ok(count) => Ok(Some(count)),
+                Err(e) => Err(Error::parse(path, e).into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Create a [`DynamicBuilder`] for building events on this PMU.
+    pub fn builder(&self) -> io::Result<DynamicBuilder> {
+        DynamicBuilder::new(&self.path)
+    }
+}
+
+/// Check whether the current system has more than one core PMU.
+///
+/// On hybrid systems, [`Hardware`] and [`Cache`] events only count on one type
+/// of core unless they are opened on each core PMU with [`OnPmu`].
+///
+/// [`Hardware`]: crate::events::Hardware
+/// [`Cache`]: crate::events::Cache
+/// [`OnPmu`]: crate::events::OnPmu
+pub fn is_hybrid() -> io::Result<bool> {
+    Ok(Pmu::core_pmus()?.len() > 1)
+}
+
+/// Which config field of `perf_event_attr` a [`Format`] field is stored in.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FormatTarget {
+    /// `perf_event_attr::config`
+    Config,
+
+    /// `perf_event_attr::config1`
+    Config1,
+
+    /// `perf_event_attr::config2`
+    Config2,
+}
+
+/// A field within the format of a PMU.
+///
+/// Format fields describe how to encode named values (e.g. `event`, `umask`)
+/// into the config fields of `perf_event_attr`. A field may be split across
+/// multiple ranges of bits, in which case the low bits of the value go in the
+/// first range, the next bits in the second range, and so on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Format {
+    name: String,
+    target: FormatTarget,
+    bits: Vec<RangeInclusive<u32>>,
+}
+
+impl Format {
+    fn read(path: PathBuf) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(&path)?;
+        let name = file_name(&path)?;
+
+        Ok(Self::parse(name, &contents).map_err(|e| e.with_path(path))?)
+    }
+
+    /// Parse a format description such as `config:0-7,32-35`.
+    fn parse(name: String, text: &str) -> Result<Self, DynamicBuilderError> {
+        let (target, rest) = text.split_once(':').ok_or_else(Error::missing_colon)?;
+
+        let target = match target {
+            "config" => FormatTarget::Config,
+            "config1" => FormatTarget::Config1,
+            "config2" => FormatTarget::Config2,
+            _ => return Err(Error::unknown_target(target.to_owned())),
+        };
+
+        let mut bits = Vec::new();
+        for range in rest.trim_end().split(',') {
+            let (lo, hi) = range.split_once('-').unwrap_or((range, range));
+            let lo: u32 = lo.parse().map_err(Error::invalid_integer)?;
+            let hi: u32 = hi.parse().map_err(Error::invalid_integer)?;
+
+            if lo > hi || hi > 63 {
+                return Err(Error::invalid_bits(range.to_owned()));
+            }
+
+            bits.push(lo..=hi);
+        }
+
+        Ok(Self { name, target, bits })
+    }
+
+    /// The name of this field.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The config field that this field is stored in.
+    pub fn target(&self) -> FormatTarget {
+        self.target
+    }
+
+    /// The ranges of bits within the config field that make up this field.
+    pub fn bits(&self) -> &[RangeInclusive<u32>] {
+        &self.bits
+    }
+
+    /// The total number of bits in this field.
+    pub fn width(&self) -> u32 {
+        self.bits
+            .iter()
+            .map(|range| range.end() - range.start() + 1)
+            .sum()
+    }
+
+    /// The mask of bits in the config field covered by this field.
+    pub fn mask(&self) -> u64 {
+        self.bits
+            .iter()
+            .map(|range| (u64::MAX >> (63 - range.end())) & (u64::MAX << range.start()))
+            .fold(0, |mask, bits| mask | bits)
+    }
+
+    /// Check whether `value` fits within this field.
+    pub fn validate(&self, value: u64) -> bool {
+        match self.width() {
+            width if width >= 64 => true,
+            width => value >> width == 0,
+        }
+    }
+
+    /// Encode `value` into the bits of the config field covered by this
+    /// field.
+    ///
+    /// Any bits of `value` that do not fit within the field are discarded.
+    pub fn encode(&self, mut value: u64) -> u64 {
+        let mut config = 0;
+        for range in &self.bits {
+            let width = range.end() - range.start() + 1;
+            let mask = u64::MAX >> (64 - width);
+
+            config |= (value & mask) << range.start();
+            value = value.checked_shr(width).unwrap_or(0);
+        }
+
+        config
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match self.target {
+            FormatTarget::Config => "config",
+            FormatTarget::Config1 => "config1",
+            FormatTarget::Config2 => "config2",
+        };
+
+        f.write_str(target)?;
+        for (index, range) in self.bits.iter().enumerate() {
+            let sep = if index == 0 { ':' } else { ',' };
+
+            if range.start() == range.end() {
+                write!(f, "{}{}", sep, range.start())?;
+            } else {
+                write!(f, "{}{}-{}", sep, range.start(), range.end())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A named event exposed by a PMU.
+#[derive(Clone, Debug)]
+pub struct PmuEvent {
+    name: String,
+    path: PathBuf,
+    terms: Vec<(String, Option<u64>)>,
+    scale: Option<f64>,
+    unit: Option<String>,
+    per_pkg: bool,
+    snapshot: bool,
+}
+
+impl PmuEvent {
+    /// Read an event, along with its properties, from its sysfs file.
+    pub(crate) fn read(path: PathBuf) -> io::Result<Self> {
+        // The event file format is described here:
+        // https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-bus-event_source-devices-events
+
+        let text = std::fs::read_to_string(&path)?;
+        let mut terms = Vec::new();
+
+        for term in text.trim_end().split(',') {
+            let (term, value) = match term.split_once('=') {
+                Some((term, "?")) => (term, None),
+                Some((term, value)) => match parse_hex(value) {
+                    Ok(value) => (term, Some(value)),
+                    Err(e) => return Err(Error::parse(path, e))?,
+                },
+                None => (term, Some(1u64)),
+            };
+
+            terms.push((term.to_owned(), value));
+        }
+
+        let property = |suffix: &str| optional(read_trimmed(&path.with_extension(suffix)));
+        let flag = |suffix: &str| -> io::Result<bool> {
+            Ok(property(suffix)?.is_some_and(|value| value == "1"))
+        };
+
+        let scale = match property("scale")? {
+            Some(scale) => match scale.parse() {
+                Ok(scale) => Some(scale),
+                Err(e) => return Err(Error::parse_float(path.with_extension("scale"), e))?,
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            name: file_name(&path)?,
+            terms,
+            scale,
+            unit: property("unit")?,
+            per_pkg: flag("per-pkg")?,
+            snapshot: flag("snapshot")?,
+            path,
+        })
+    }
+
+    /// The name of this event.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The path to the event file within sysfs.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The values that this event assigns to the format fields of its PMU.
+    ///
+    /// Fields with a value of `None` are parameters that must be set by the
+    /// user before the event can be used.
+    pub fn terms(&self) -> impl Iterator<Item = (&str, Option<u64>)> {
+        self.terms.iter().map(|(name, value)| (&**name, *value))
+    }
+
+    /// The fields that must be set by the user before this event can be used.
+    pub fn params(&self) -> impl Iterator<Item = &str> {
+        self.terms()
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| name)
+    }
+
+    /// The scale factor of the event.
+    ///
+    /// This is a value to be multiplied by the event count emitted by the
+    /// kernel in order to convert the count to the unit as returned by
+    /// [`unit`](Self::unit). Not all events have a scale.
+    pub fn scale(&self) -> Option<f64> {
+        self.scale
+    }
+
+    /// The unit of the event, once multiplied by [`scale`](Self::scale). Not
+    /// all events have a unit.
+    pub fn unit(&self) -> Option<&str> {
+        self.unit.as_deref()
+    }
+
+    /// Whether the event counts per package (socket) instead of per CPU.
+    ///
+    /// The counts for per-package events should only be read from one CPU
+    /// per package, otherwise the same count is added multiple times.
+    pub fn per_pkg(&self) -> bool {
+        self.per_pkg
+    }
+
+    /// Whether the event reports a snapshot of a value instead of a count.
+    ///
+    /// The values of snapshot events should not be subtracted from each
+    /// other to get a delta.
+    pub fn snapshot(&self) -> bool {
+        self.snapshot
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u64, std::num::ParseIntError> {
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    u64::from_str_radix(text, 16)
+}
+
+/// Read the files in `dir` sorted by name. A missing directory is treated as
+/// empty.
+fn read_dir_sorted(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut paths = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    let mut content = std::fs::read_to_string(path)?;
+    let trimmed = content.trim_end();
+    content.truncate(trimmed.len());
+    Ok(content)
+}
+
+fn file_name(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_owned())
+        .ok_or_else(|| Error::invalid_field_name(path.to_owned()).into())
+}
+
+/// Convert a `NotFound` error into `None`.
+fn optional<T>(result: io::Result<T>) -> io::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_sanity() {
+        assert_eq!(parse_hex("0xFFFFFFFF"), Ok(0xFFFFFFFF));
+    }
+
+    #[test]
+    fn parse_split_format() {
+        let format = Format::parse("event".into(), "config:0-7,32-35\n").unwrap();
+
+        assert_eq!(format.target(), FormatTarget::Config);
+        assert_eq!(format.bits(), [0..=7, 32..=35]);
+        assert_eq!(format.width(), 12);
+        assert_eq!(format.mask(), 0xF_0000_00FF);
+        assert_eq!(format.encode(0x1A0), 0x1_0000_00A0);
+        assert!(format.validate(0xFFF));
+        assert!(!format.validate(0x1000));
+        assert_eq!(format.to_string(), "config:0-7,32-35");
+    }
+
+    #[test]
+    fn parse_single_bit_format() {
+        let format = Format::parse("edge".into(), "config1:18").unwrap();
+
+        assert_eq!(format.target(), FormatTarget::Config1);
+        assert_eq!(format.mask(), 1 << 18);
+        assert_eq!(format.encode(3), 1 << 18);
+        assert_eq!(format.to_string(), "config1:18");
+
+        let full = Format::parse("raw".into(), "config2:0-63").unwrap();
+        assert_eq!(full.mask(), u64::MAX);
+        assert!(full.validate(u64::MAX));
+        assert_eq!(full.encode(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn parse_invalid_format() {
+        assert!(Format::parse("a".into(), "config0-7").is_err());
+        assert!(Format::parse("a".into(), "config3:0-7").is_err());
+        assert!(Format::parse("a".into(), "config:7-0").is_err());
+        assert!(Format::parse("a".into(), "config:0-64").is_err());
+    }
+}
@@ -3,15 +3,24 @@ use std::convert::{AsMut, AsRef};
 use std::ops::{Deref, DerefMut};
 use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use std::{fmt, io};
 
-use crate::data::parse::{ParseBuf, ParseBufChunk, ParseError, ParseResult, Parser};
+use crate::clock::TimeConversion;
+use crate::data::endian::Native;
+use crate::data::parse::{ParseBuf, ParseBufChunk, ParseConfig, ParseError, ParseResult, Parser};
 use crate::events::Hardware;
 use crate::sys::bindings::{
     __BindgenBitfieldUnit, perf_event_header, perf_event_mmap_page,
-    perf_event_mmap_page__bindgen_ty_1__bindgen_ty_1 as MmapPageFlags,
+    perf_event_mmap_page__bindgen_ty_1__bindgen_ty_1 as MmapPageFlags, PERF_RECORD_MMAP,
+    PERF_RECORD_SAMPLE,
+};
+use crate::sys::ioctls;
+use crate::{
+    arch, check_errno_syscall, data, AdaptivePeriod, AuxBuffer, Clock, Counter, PerfClock,
+    SampleFlag,
 };
-use crate::{check_errno_syscall, data, Counter};
 
 used_in_docs!(Hardware);
 
@@ -33,7 +42,11 @@ used_in_docs!(Hardware);
 /// [0]: https://www.mankier.com/2/perf_event_open
 pub struct Sampler {
     counter: Counter,
-    mmap: memmap2::MmapRaw,
+    mmap: Arc<memmap2::MmapRaw>,
+
+    /// Other counters whose output has been redirected into this sampler's
+    /// ring buffer.
+    attached: Vec<Counter>,
 }
 
 /// A view into a [`Sampler`]'s ring buffer for a single kernel event record.
@@ -51,7 +64,7 @@ pub struct Record<'a> {
 /// A `Buf` that can be either a single byte slice or two disjoint byte
 /// slices.
 #[derive(Copy, Clone)]
-enum ByteBuffer<'a> {
+pub(crate) enum ByteBuffer<'a> {
     Single(&'a [u8]),
     Split([&'a [u8]; 2]),
 }
@@ -60,7 +73,11 @@ impl Sampler {
     pub(crate) fn new(counter: Counter, mmap: memmap2::MmapRaw) -> Self {
         assert!(!mmap.as_ptr().is_null());
 
-        Self { counter, mmap }
+        Self {
+            counter,
+            mmap: Arc::new(mmap),
+            attached: Vec::new(),
+        }
     }
 
     /// Convert this sampler back into a counter.
@@ -80,6 +97,171 @@ impl Sampler {
         &mut self.counter
     }
 
+    /// Redirect the records produced by `counter` into the ring buffer of this
+    /// sampler.
+    ///
+    /// The attached counter is kept alive for as long as this sampler exists.
+    /// To tell which counter produced each record use [`Record::counter`].
+    /// This requires that both this sampler and all attached counters were
+    /// built with [`SampleFlag::IDENTIFIER`] in their sample type and, for
+    /// records other than samples, with [`sample_id_all`] enabled.
+    ///
+    /// The kernel places some restrictions on which counters can share a ring
+    /// buffer. They must either be observing the same CPU or, if this sampler
+    /// is not bound to a CPU, the same process. They must also use the same
+    /// clock and `counter` must not have its own ring buffer.
+    ///
+    /// This method corresponds to the `IOC_SET_OUTPUT` ioctl.
+    ///
+    /// # Example
+    /// ```
+    /// use perf_event::events::Software;
+    /// use perf_event::{Builder, SampleFlag};
+    ///
+    /// let mut builder = Builder::new(Software::CPU_CLOCK);
+    /// builder
+    ///     .sample_period(100_000)
+    ///     .sample(SampleFlag::IDENTIFIER)
+    ///     .sample_id_all(true);
+    ///
+    /// let mut sampler = builder.build()?.sampled(8192)?;
+    /// let other = builder.event(Software::TASK_CLOCK).build()?;
+    /// sampler.attach(other)?;
+    /// # std::io::Result::Ok(())
+    /// ```
+    ///
+    /// [`SampleFlag::IDENTIFIER`]: crate::SampleFlag::IDENTIFIER
+    /// [`sample_id_all`]: crate::Builder::sample_id_all
+    pub fn attach(&mut self, counter: Counter) -> io::Result<()> {
+        let output = self.as_raw_fd();
+        counter.ioctl(|fd| unsafe { ioctls::SET_OUTPUT(fd, output) })?;
+        self.attached.push(counter);
+        Ok(())
+    }
+
+    /// The counters that have been attached to this sampler via
+    /// [`attach`](Self::attach).
+    pub fn attached(&self) -> &[Counter] {
+        &self.attached
+    }
+
+    /// Mutably access the counters that have been attached to this sampler
+    /// via [`attach`](Self::attach).
+    pub fn attached_mut(&mut self) -> &mut [Counter] {
+        &mut self.attached
+    }
+
+    /// Adjust the sampling period of this sampler so that it produces records
+    /// at the rate targeted by `state`.
+    ///
+    /// This reads the current counter value and compares it with the value
+    /// seen on the previous call to estimate how fast the event is occurring.
+    /// It should be called periodically, e.g. after draining the ring buffer.
+    /// Returns the new sampling period, or `None` if the period was left
+    /// unchanged because there was not enough information to pick a new one.
+    /// See [`AdaptivePeriod`] for details.
+    ///
+    /// The sampler must have been built with [`Builder::sample_period`]. An
+    /// error with kind [`io::ErrorKind::InvalidInput`] will be returned if it
+    /// was built with [`Builder::sample_frequency`] instead.
+    ///
+    /// [`Builder::sample_period`]: crate::Builder::sample_period
+    /// [`Builder::sample_frequency`]: crate::Builder::sample_frequency
+    pub fn adapt_period(&mut self, state: &mut AdaptivePeriod) -> io::Result<Option<u64>> {
+        if self.counter.attrs().freq() != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot adapt the period of a sampler in frequency mode",
+            ));
+        }
+
+        let count = self.counter.read()?;
+        let period = match state.update(count, Instant::now()) {
+            Some(period) => period,
+            None => return Ok(None),
+        };
+
+        if period != self.counter.attrs().sample_period {
+            self.counter.set_period(period)?;
+        }
+
+        Ok(Some(period))
+    }
+
+    /// Stop the kernel from writing new records into the ring buffer.
+    ///
+    /// Unlike [`Counter::disable`], the counter keeps counting while output is
+    /// paused. Records that would have been written while output is paused
+    /// are dropped and the kernel will emit a `LOST` record once output is
+    /// resumed. This is useful when draining or taking a snapshot of the ring
+    /// buffer.
+    ///
+    /// This method corresponds to the `IOC_PAUSE_OUTPUT` ioctl.
+    pub fn pause_output(&mut self) -> io::Result<()> {
+        set_output_paused(&self.counter, true)
+    }
+
+    /// Allow the kernel to write records into the ring buffer again after a
+    /// call to [`pause_output`](Self::pause_output).
+    ///
+    /// This method corresponds to the `IOC_PAUSE_OUTPUT` ioctl.
+    pub fn resume_output(&mut self) -> io::Result<()> {
+        set_output_paused(&self.counter, false)
+    }
+
+    /// Pause output to the ring buffer until the returned guard is dropped.
+    ///
+    /// The guard dereferences to this `Sampler` so records can still be read
+    /// through it. See [`pause_output`](Self::pause_output) for details.
+    ///
+    /// # Example
+    /// ```
+    /// use perf_event::events::Software;
+    /// use perf_event::Builder;
+    ///
+    /// let mut sampler = Builder::new(Software::CPU_CLOCK)
+    ///     .sample_period(100_000)
+    ///     .enabled(true)
+    ///     .build()?
+    ///     .sampled(8192)?;
+    ///
+    /// let mut paused = sampler.pause()?;
+    /// while let Some(record) = paused.next_record() {
+    ///     println!("{:?}", record.parse_record());
+    /// }
+    ///
+    /// // Output is resumed once the guard is dropped.
+    /// drop(paused);
+    /// # std::io::Result::Ok(())
+    /// ```
+    pub fn pause(&mut self) -> io::Result<PausedSampler<'_>> {
+        self.pause_output()?;
+        Ok(PausedSampler { sampler: self })
+    }
+
+    /// Map the AUX area of this sampler's ring buffer.
+    ///
+    /// PMUs that produce hardware trace data (e.g. Intel PT) write it into the
+    /// AUX area instead of emitting it as records. See [`AuxBuffer`] for
+    /// details.
+    ///
+    /// `len` will be rounded up to the next power-of-two multiple of the
+    /// system page size. The kernel only allows mapping the AUX area once per
+    /// ring buffer and will return an error if the counter's PMU does not
+    /// support it.
+    pub fn map_aux(&mut self, len: usize) -> io::Result<AuxBuffer> {
+        AuxBuffer::new(self, len, false)
+    }
+
+    /// Map the AUX area of this sampler's ring buffer in overwrite mode.
+    ///
+    /// In overwrite mode the kernel continuously overwrites the oldest data
+    /// in the AUX area. Use [`AuxBuffer::snapshot`] to read the most recent
+    /// data. See [`map_aux`](Self::map_aux) for details on `len`.
+    pub fn map_aux_overwrite(&mut self, len: usize) -> io::Result<AuxBuffer> {
+        AuxBuffer::new(self, len, true)
+    }
+
     /// Read the next record from the ring buffer.
     ///
     /// This method does not block. If you want blocking behaviour, use
@@ -264,42 +446,59 @@ impl Sampler {
     /// | Architecture | Counter Read | Timestamp Read |
     /// |--------------|--------------|----------------|
     /// |  x86/x86_64  | yes          | yes            |
+    /// |  aarch64     | yes          | yes            |
+    /// |  riscv64     | yes          | yes            |
     ///
     /// If you would like to add support for a new architecture here please
     /// submit a PR!
+    ///
+    /// If the counter value could not be read then
+    /// [`UserReadData::try_count`] will report why.
     pub fn read_user(&self) -> UserReadData {
-        #[cfg(target_arch = "x86")]
-        use std::arch::x86::_rdtsc;
-        #[cfg(target_arch = "x86_64")]
-        use std::arch::x86_64::_rdtsc;
-
-        loop {
-            let mut data = unsafe { PmcReadData::new(self.page()) };
-
-            if let Some(index) = data.index() {
-                // SAFETY:
-                // - index was handed to us by the kernel so it is safe to use.
-                // - cap_user_rdpmc will only be set if it is valid to call rdpmc from
-                //   userspace.
-                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-                data.with_pmc(unsafe { rdpmc(index) });
-            }
+        // SAFETY: the first page of the mapping is always a perf_event_mmap_page.
+        unsafe { read_user_page(self.page()) }
+    }
 
-            if data.cap_user_time() {
-                // SAFETY: it is always safe to run rdtsc on x86
-                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-                data.with_tsc(unsafe { _rdtsc() });
-            }
+    /// Create a [`PerfClock`] for converting the timestamps of records from
+    /// this sampler into [`Instant`]s and [`SystemTime`]s.
+    ///
+    /// If the counter was built with [`Builder::clockid`] then this is
+    /// equivalent to [`PerfClock::new`]. Otherwise, the time conversion fields
+    /// in the metadata page are used to relate the kernel's internal perf
+    /// clock to the timestamp counter of the CPU. This requires that both the
+    /// kernel and perf-event2 support reading the timestamp counter from
+    /// userspace on the current architecture, if not then an error with kind
+    /// [`io::ErrorKind::Unsupported`] will be returned.
+    ///
+    /// [`Builder::clockid`]: crate::Builder::clockid
+    /// [`SystemTime`]: std::time::SystemTime
+    pub fn perf_clock(&self) -> io::Result<PerfClock> {
+        let attrs = self.counter.attrs();
+        if attrs.use_clockid() != 0 {
+            return PerfClock::new(Clock::new(attrs.clockid));
+        }
 
-            if let Some(data) = data.finish() {
-                return data;
+        // SAFETY: the first page of the mapping is always a perf_event_mmap_page.
+        let conversion = unsafe { read_time_conversion(self.page()) };
+        match (conversion, arch::read_timestamp()) {
+            (Some(conversion), Some(timestamp)) => {
+                Ok(PerfClock::from_conversion(conversion, timestamp))
             }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "converting perf timestamps requires reading the timestamp counter from userspace",
+            )),
         }
     }
 
     fn page(&self) -> *const perf_event_mmap_page {
         self.mmap.as_ptr() as *const _
     }
+
+    /// The memory mapping containing the metadata page and the data area.
+    pub(crate) fn mmap(&self) -> &Arc<memmap2::MmapRaw> {
+        &self.mmap
+    }
 }
 
 impl Deref for Sampler {
@@ -340,6 +539,56 @@ impl IntoRawFd for Sampler {
     }
 }
 
+/// A guard that keeps the output of a [`Sampler`] paused.
+///
+/// Output to the ring buffer is resumed when this guard is dropped. Any
+/// errors from resuming output are ignored. Use [`resume`](Self::resume) to
+/// resume output and observe the error instead.
+///
+/// This is returned by [`Sampler::pause`].
+pub struct PausedSampler<'a> {
+    sampler: &'a mut Sampler,
+}
+
+impl PausedSampler<'_> {
+    /// Resume output to the ring buffer, returning any error that occurred.
+    pub fn resume(self) -> io::Result<()> {
+        let result = self.sampler.resume_output();
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Deref for PausedSampler<'_> {
+    type Target = Sampler;
+
+    fn deref(&self) -> &Self::Target {
+        self.sampler
+    }
+}
+
+impl DerefMut for PausedSampler<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.sampler
+    }
+}
+
+impl Drop for PausedSampler<'_> {
+    fn drop(&mut self) {
+        let _ = self.sampler.resume_output();
+    }
+}
+
+/// Pause or resume output to the ring buffer of `counter`.
+///
+/// This is shared by [`Sampler`] and [`FlightRecorder`], which both own a
+/// ring buffer but only the former exposes the ioctl directly.
+///
+/// [`FlightRecorder`]: crate::FlightRecorder
+pub(crate) fn set_output_paused(counter: &Counter, paused: bool) -> io::Result<()> {
+    counter.ioctl(|fd| unsafe { ioctls::PAUSE_OUTPUT(fd, paused.into()) })
+}
+
 // This is meant to roughly be the equivalent of the kernel READ_ONCE
 // macro. The closest equivalent in Rust (and, I think, the only one
 // that avoids UB) is to do a relaxed atomic load.
@@ -360,6 +609,60 @@ macro_rules! barrier {
     };
 }
 
+/// Read the value of a counter directly from userspace using its metadata
+/// page.
+///
+/// See [`Sampler::read_user`] for details.
+///
+/// # Safety
+/// `page` must point to a valid [`perf_event_mmap_page`] for the duration of
+/// this call.
+pub(crate) unsafe fn read_user_page(page: *const perf_event_mmap_page) -> UserReadData {
+    loop {
+        let mut data = unsafe { PmcReadData::new(page) };
+
+        if let Some(index) = data.index() {
+            // SAFETY:
+            // - index was handed to us by the kernel so it is safe to use.
+            // - cap_user_rdpmc will only be set if it is valid to read the counter from
+            //   userspace.
+            match unsafe { arch::read_pmc(index) } {
+                Ok(pmc) => data.with_pmc(pmc),
+                Err(error) => data.with_pmc_error(error),
+            }
+        }
+
+        if data.cap_user_time() {
+            if let Some(cyc) = arch::read_timestamp() {
+                data.with_tsc(cyc);
+            }
+        }
+
+        if let Some(data) = data.finish() {
+            return data;
+        }
+    }
+}
+
+/// Read the fields needed to convert timestamp counter values into perf time,
+/// if the kernel supports it.
+///
+/// # Safety
+/// `page` must point to a valid [`perf_event_mmap_page`] for the duration of
+/// this call.
+pub(crate) unsafe fn read_time_conversion(
+    page: *const perf_event_mmap_page,
+) -> Option<TimeConversion> {
+    loop {
+        let data = PmcReadData::new(page);
+        let conversion = data.time_conversion();
+
+        if data.finish().is_some() {
+            return conversion;
+        }
+    }
+}
+
 /// Helper for writing a `read_user` variant.
 struct PmcReadData {
     page: *const perf_event_mmap_page,
@@ -374,6 +677,9 @@ struct PmcReadData {
     /// architectures we don't want to just return the offset value from the
     /// perf mmap page.
     has_pmc_value: bool,
+
+    /// The reason the PMC could not be read, if the counter was active.
+    pmc_error: UserReadError,
 }
 
 #[allow(dead_code)]
@@ -403,6 +709,7 @@ impl PmcReadData {
             index: read_once!((*page).index),
             count: read_once!((*page).offset),
             has_pmc_value: false,
+            pmc_error: UserReadError::UnsupportedArch,
         }
     }
 
@@ -418,6 +725,35 @@ impl PmcReadData {
         self.flags.cap_user_time_short() != 0
     }
 
+    pub fn cap_user_time_zero(&self) -> bool {
+        self.flags.cap_user_time_zero() != 0
+    }
+
+    /// Read the fields for converting timestamp counter values to perf time.
+    pub fn time_conversion(&self) -> Option<TimeConversion> {
+        if !self.cap_user_time_zero() {
+            return None;
+        }
+
+        let page = self.page;
+
+        Some(TimeConversion {
+            time_zero: unsafe { read_once!((*page).time_zero) },
+            time_mult: unsafe { read_once!((*page).time_mult) },
+            time_shift: unsafe { read_once!((*page).time_shift) },
+            time_short: if self.cap_user_time_short() {
+                Some(unsafe {
+                    (
+                        read_once!((*page).time_cycles),
+                        read_once!((*page).time_mask),
+                    )
+                })
+            } else {
+                None
+            },
+        })
+    }
+
     /// Get the index of the PMC counter, should there be one to read.
     pub fn index(&self) -> Option<u32> {
         if self.cap_user_rdpmc() && self.index != 0 {
@@ -484,6 +820,11 @@ impl PmcReadData {
         self.has_pmc_value = true;
     }
 
+    /// Record why the PMC at `index` could not be read.
+    pub fn with_pmc_error(&mut self, error: UserReadError) {
+        self.pmc_error = error;
+    }
+
     pub fn finish(self) -> Option<UserReadData> {
         let page = self.page;
         let seq = self.seq;
@@ -498,9 +839,13 @@ impl PmcReadData {
             time_enabled: self.enabled,
             time_running: self.running,
             value: if self.has_pmc_value {
-                Some(self.count as u64)
+                Ok(self.count as u64)
+            } else if !self.cap_user_rdpmc() {
+                Err(UserReadError::RdpmcNotAllowed)
+            } else if self.index == 0 {
+                Err(UserReadError::CounterInactive)
             } else {
-                None
+                Err(self.pmc_error)
             },
         })
     }
@@ -511,10 +856,23 @@ impl PmcReadData {
 pub struct UserReadData {
     time_enabled: u64,
     time_running: u64,
-    value: Option<u64>,
+    value: Result<u64, UserReadError>,
 }
 
 impl UserReadData {
+    #[cfg(test)]
+    pub(crate) fn new(
+        time_enabled: u64,
+        time_running: u64,
+        value: Result<u64, UserReadError>,
+    ) -> Self {
+        Self {
+            time_enabled,
+            time_running,
+            value,
+        }
+    }
+
     /// The total time for which the counter was enabled at the time of reading.
     ///
     /// If the architecture and counter support it this will be cycle-accurate
@@ -529,19 +887,68 @@ impl UserReadData {
 
     /// The value of the counter, if it was enabled at the time.
     pub fn count(&self) -> Option<u64> {
+        self.value.ok()
+    }
+
+    /// The value of the counter, or the reason it could not be read from
+    /// userspace.
+    pub fn try_count(&self) -> Result<u64, UserReadError> {
         self.value
     }
 
     /// The value of the counter, scaled to reflect `time_enabled`.
+    ///
+    /// This will be `None` if the counter value could not be read or if the
+    /// counter has not run at all.
     pub fn scaled_count(&self) -> Option<u64> {
-        self.count().map(|count| {
-            let quot = count / self.time_running;
-            let rem = count % self.time_running;
-            quot * self.time_enabled + (rem * self.time_enabled) / self.time_running
+        crate::scale(self.count()?, self.time_enabled, self.time_running)
+    }
+}
+
+/// The reason a counter value could not be read by [`Sampler::read_user`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UserReadError {
+    /// The kernel does not allow this counter to be read from userspace
+    /// (`cap_user_rdpmc` is not set).
+    ///
+    /// This is usually because the event is not a hardware event or because
+    /// userspace counter access has been disabled (e.g. via
+    /// `/proc/sys/kernel/perf_user_access` on aarch64).
+    RdpmcNotAllowed,
+
+    /// The counter was not scheduled on a hardware counter at the time of
+    /// reading.
+    CounterInactive,
+
+    /// perf-event2 does not support reading counters from userspace on the
+    /// current architecture.
+    UnsupportedArch,
+
+    /// The counter was scheduled on a hardware counter that perf-event2 does
+    /// not know how to read on the current architecture.
+    UnsupportedCounter,
+}
+
+impl fmt::Display for UserReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::RdpmcNotAllowed => {
+                "the kernel does not allow reading this counter from userspace"
+            }
+            Self::CounterInactive => "the counter is not currently active on a hardware counter",
+            Self::UnsupportedArch => {
+                "reading counters from userspace is not supported on this architecture"
+            }
+            Self::UnsupportedCounter => {
+                "the hardware counter cannot be read from userspace on this architecture"
+            }
         })
     }
 }
 
+impl std::error::Error for UserReadError {}
+
 impl<'s> Record<'s> {
     /// Access the `type` field of the kernel record header.
     ///
@@ -572,10 +979,7 @@ impl<'s> Record<'s> {
     /// wrap-around then one slice will be returned here, otherwise, two will
     /// be returned.
     pub fn data(&self) -> &[&[u8]] {
-        match &self.data {
-            ByteBuffer::Single(buf) => std::slice::from_ref(buf),
-            ByteBuffer::Split(bufs) => &bufs[..],
-        }
+        self.data.as_slices()
     }
 
     /// Copy the bytes of this record to an owned [`Vec`].
@@ -588,48 +992,125 @@ impl<'s> Record<'s> {
     /// For most records this is effectively free but if the record wraps
     /// around the end of the ringbuffer then it will be copied to a vector.
     pub fn to_contiguous(&self) -> Cow<'_, [u8]> {
-        match self.data {
-            ByteBuffer::Single(data) => Cow::Borrowed(data),
-            ByteBuffer::Split([a, b]) => {
-                let mut vec = Vec::with_capacity(a.len() + b.len());
-                vec.extend_from_slice(a);
-                vec.extend_from_slice(b);
-                Cow::Owned(vec)
-            }
+        self.data.to_contiguous()
+    }
+
+    /// The counter that produced this record.
+    ///
+    /// If no other counters have been [attached] to the sampler then this is
+    /// always the sampler's own counter. Otherwise, the counter is identified
+    /// using the [`SampleFlag::IDENTIFIER`] value within the record. This
+    /// will return `None` if the record does not have an identifier or if it
+    /// does not match any of the counters writing to this ring buffer.
+    ///
+    /// [attached]: Sampler::attach
+    pub fn counter(&self) -> Option<&'s Counter> {
+        let sampler = self.sampler;
+        if sampler.attached.is_empty() {
+            return Some(&sampler.counter);
+        }
+
+        let id = self.identifier()?;
+        std::iter::once(&sampler.counter)
+            .chain(&sampler.attached)
+            .find(|counter| counter.id() == id)
+    }
+
+    /// Read the [`SampleFlag::IDENTIFIER`] value out of this record, should it
+    /// have one.
+    ///
+    /// The identifier is always at a fixed location within the record so we
+    /// can read it without knowing which counter emitted it.
+    fn identifier(&self) -> Option<u64> {
+        const ID_LEN: usize = std::mem::size_of::<u64>();
+
+        let attrs = self.sampler.counter.attrs();
+        if !SampleFlag::from_bits_retain(attrs.sample_type).contains(SampleFlag::IDENTIFIER) {
+            return None;
+        }
+
+        let offset = if self.ty() == PERF_RECORD_SAMPLE {
+            0
+        } else if attrs.sample_id_all() != 0 {
+            self.data.len().checked_sub(ID_LEN)?
+        } else {
+            return None;
+        };
+
+        if self.data.len() < offset + ID_LEN {
+            return None;
         }
+
+        let mut data = self.data;
+        let mut bytes = [0u8; ID_LEN];
+        data.advance(offset);
+        data.copy_to_slice(&mut bytes);
+        Some(u64::from_ne_bytes(bytes))
+    }
+
+    /// The parse config for the counter that produced this record.
+    fn config(&self) -> &ParseConfig<Native> {
+        self.counter().unwrap_or(&self.sampler.counter).config()
     }
 
     /// Parse the data in this record to a [`data::Record`] enum.
+    ///
+    /// If other counters have been [attached] to the sampler then the record
+    /// will be parsed using the configuration of the counter that produced it.
+    /// See [`counter`](Self::counter) for details.
+    ///
+    /// [attached]: Sampler::attach
     pub fn parse_record(&self) -> ParseResult<data::Record<'_>> {
-        let mut parser = Parser::new(self.data, self.sampler.config().clone());
+        let mut parser = Parser::new(self.data, self.config().clone());
         data::Record::parse_with_header(&mut parser, self.header)
     }
 
     /// Parse the sample id for the record.
     ///
     /// This will only be non-empty if the [`sample_id_all`] was set when
-    /// building the counter. In addition, `MMAP` records never have a sample id
-    /// set. If you want sample ids and `MMAP` records you will need to request
-    /// `MMAP2` records instead.
+    /// building the counter.
     ///
     /// [`sample_id_all`]: crate::Builder::sample_id_all
     pub fn parse_sample_id(&self) -> ParseResult<data::SampleId> {
-        use perf_event_open_sys::bindings;
-
-        let config = self.sampler.config();
-        let mut parser = Parser::new(self.data, config.clone());
+        parse_sample_id(self.header, self.data, self.config())
+    }
+}
 
-        let (mut parser, metadata) = parser.parse_metadata_with_header(self.header)?;
+/// Parse the sample id of a record with the given header and data.
+///
+/// This is shared between [`Record`] and [`OwnedRecord`] so that they always
+/// agree on the sample id of a record.
+///
+/// [`OwnedRecord`]: crate::OwnedRecord
+pub(crate) fn parse_sample_id<'p, B: ParseBuf<'p>>(
+    header: perf_event_header,
+    data: B,
+    config: &ParseConfig<Native>,
+) -> ParseResult<data::SampleId> {
+    let mut parser = Parser::new(data, config.clone());
+
+    // The kernel writes a trailing sample id for MMAP records as well but the
+    // parser in perf-event-data skips it, so we parse it here.
+    if header.type_ == PERF_RECORD_MMAP {
+        let len = (header.size as usize).saturating_sub(std::mem::size_of::<perf_event_header>());
+        let offset = len
+            .checked_sub(data::SampleId::estimate_len(config))
+            .ok_or_else(ParseError::eof)?;
+
+        parser.parse_bytes(offset)?;
+        return parser.parse();
+    }
 
-        // All other records either already parsed the sample id or don't have it.
-        // With SAMPLE records, we can construct the sample id struct directly.
-        if self.ty() != bindings::PERF_RECORD_SAMPLE {
-            return Ok(*metadata.sample_id());
-        }
+    let (mut parser, metadata) = parser.parse_metadata_with_header(header)?;
 
-        let record = parser.parse::<data::Sample>()?;
-        Ok(data::SampleId::from_sample(&record))
+    // All other records either already parsed the sample id or don't have it.
+    // With SAMPLE records, we can construct the sample id struct directly.
+    if header.type_ != PERF_RECORD_SAMPLE {
+        return Ok(*metadata.sample_id());
     }
+
+    let record = parser.parse::<data::Sample>()?;
+    Ok(data::SampleId::from_sample(&record))
 }
 
 impl<'s> Drop for Record<'s> {
@@ -676,13 +1157,35 @@ impl<'a> ByteBuffer<'a> {
         unsafe { std::mem::transmute(bytes) }
     }
 
-    fn len(&self) -> usize {
+    pub(crate) fn len(&self) -> usize {
         match self {
             Self::Single(buf) => buf.len(),
             Self::Split([a, b]) => a.len() + b.len(),
         }
     }
 
+    /// Access the underlying byte slices of this buffer.
+    pub(crate) fn as_slices(&self) -> &[&'a [u8]] {
+        match self {
+            Self::Single(buf) => std::slice::from_ref(buf),
+            Self::Split(bufs) => &bufs[..],
+        }
+    }
+
+    /// Get the bytes of this buffer as a single contiguous slice, copying
+    /// them if necessary.
+    pub(crate) fn to_contiguous(self) -> Cow<'a, [u8]> {
+        match self {
+            Self::Single(data) => Cow::Borrowed(data),
+            Self::Split([a, b]) => {
+                let mut vec = Vec::with_capacity(a.len() + b.len());
+                vec.extend_from_slice(a);
+                vec.extend_from_slice(b);
+                Cow::Owned(vec)
+            }
+        }
+    }
+
     /// Shorten this byte buffer to only include the first `new_len` bytes.
     ///
     /// # Panics
@@ -766,7 +1269,7 @@ macro_rules! assert_same_size {
     }};
 }
 
-trait Atomic: Sized + Copy {
+pub(crate) trait Atomic: Sized + Copy {
     type Atomic;
 
     unsafe fn store(ptr: *const Self, val: Self, order: Ordering);
@@ -805,7 +1308,7 @@ impl_atomic!(i64, std::sync::atomic::AtomicI64);
 /// # Safety
 /// - `ptr` must be valid for writes.
 /// - `ptr` must be properly aligned.
-unsafe fn atomic_store<T: Atomic>(ptr: *const T, val: T, order: Ordering) {
+pub(crate) unsafe fn atomic_store<T: Atomic>(ptr: *const T, val: T, order: Ordering) {
     T::store(ptr, val, order)
 }
 
@@ -814,54 +1317,10 @@ unsafe fn atomic_store<T: Atomic>(ptr: *const T, val: T, order: Ordering) {
 /// # Safety
 /// - `ptr` must be valid for reads.
 /// - `ptr` must be properly aligned.
-unsafe fn atomic_load<T: Atomic>(ptr: *const T, order: Ordering) -> T {
+pub(crate) unsafe fn atomic_load<T: Atomic>(ptr: *const T, order: Ordering) -> T {
     T::load(ptr, order)
 }
 
-/// Read a performance monitoring counter via the `rdpmc` instruction.
-///
-/// # Safety
-/// - `index` must be a valid PMC index
-/// - The current CPU must be allowed to execute the `rdpmc` instruction at the
-///   current priviledge level.
-///
-/// Note that the safety constraints come from the x86 ISA so any violation of
-/// them will likely lead to a SIGINT or other such signal.
-#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
-unsafe fn rdpmc(index: u32) -> u64 {
-    // This saves a few instructions for 64-bit since LLVM doesn't realize
-    // that the top 32 bits of RAX:RDX are cleared otherwise.
-    #[cfg(target_arch = "x86_64")]
-    {
-        let lo: u64;
-        let hi: u64;
-
-        std::arch::asm!(
-            "rdpmc",
-            in("ecx") index,
-            out("rax") lo,
-            out("rdx") hi
-        );
-
-        lo | (hi << u32::BITS)
-    }
-
-    #[cfg(target_arch = "x86")]
-    {
-        let lo: u32;
-        let hi: u32;
-
-        std::arch::asm!(
-            "rdpmc",
-            in("ecx") index,
-            out("eax") lo,
-            out("edx") hi
-        );
-
-        (lo as u64) | ((hi as u64) << u32::BITS)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -918,4 +1377,54 @@ mod tests {
         buf.copy_to_slice(&mut out);
         assert_eq!(&out, b"12345");
     }
+
+    fn read_page(page: &perf_event_mmap_page) -> UserReadData {
+        let data = unsafe { PmcReadData::new(page) };
+        data.finish().expect("page lock changed during read")
+    }
+
+    #[test]
+    fn user_read_reports_missing_rdpmc() {
+        let page: perf_event_mmap_page = unsafe { std::mem::zeroed() };
+
+        let data = read_page(&page);
+        assert_eq!(data.try_count(), Err(UserReadError::RdpmcNotAllowed));
+        assert_eq!(data.count(), None);
+    }
+
+    #[test]
+    fn user_read_reports_inactive_counter() {
+        let mut page: perf_event_mmap_page = unsafe { std::mem::zeroed() };
+        unsafe { page.__bindgen_anon_1.__bindgen_anon_1.set_cap_user_rdpmc(1) };
+
+        let data = read_page(&page);
+        assert_eq!(data.try_count(), Err(UserReadError::CounterInactive));
+    }
+
+    #[test]
+    fn user_read_reports_unsupported_counter() {
+        let mut page: perf_event_mmap_page = unsafe { std::mem::zeroed() };
+        unsafe { page.__bindgen_anon_1.__bindgen_anon_1.set_cap_user_rdpmc(1) };
+        page.index = 100;
+
+        let mut data = unsafe { PmcReadData::new(&page) };
+        data.with_pmc_error(UserReadError::UnsupportedCounter);
+        let data = data.finish().unwrap();
+        assert_eq!(data.try_count(), Err(UserReadError::UnsupportedCounter));
+    }
+
+    #[test]
+    fn user_read_sign_extends_pmc() {
+        let mut page: perf_event_mmap_page = unsafe { std::mem::zeroed() };
+        unsafe { page.__bindgen_anon_1.__bindgen_anon_1.set_cap_user_rdpmc(1) };
+        page.index = 1;
+        page.offset = 10;
+        page.pmc_width = 8;
+
+        let mut data = unsafe { PmcReadData::new(&page) };
+        assert_eq!(data.index(), Some(0));
+        data.with_pmc(0xFF);
+        let data = data.finish().unwrap();
+        assert_eq!(data.try_count(), Ok(9));
+    }
 }
@@ -0,0 +1,376 @@
+use std::io;
+use std::path::Path;
+
+use crate::events::Software;
+use crate::per_cpu::{online_cpus, topology_id, CPU_ROOT};
+use crate::pmu::{Pmu, PmuEvent};
+use crate::{Builder, Counter, CounterData};
+
+/// A set of counters for an event on an uncore PMU.
+///
+/// Uncore PMUs (e.g. the memory controller, `power`, or `cstate_pkg`) count
+/// events for a whole socket or die instead of for a single CPU. The kernel
+/// lists the CPUs that their counters should be opened on in the PMU's
+/// `cpumask`, typically one per socket. `UncoreCounters` opens one counter
+/// for the event on each of those CPUs and can aggregate their values.
+///
+/// The event's sysfs properties are taken into account:
+/// - `.scale` and `.unit` are available via [`scale`] and [`unit`], and are
+///   applied by [`UncoreCounterData::value`].
+/// - For `.per-pkg` events, only one counter is opened per package (or per die
+///   on packages with multiple dies) so that the total does not count the same
+///   package multiple times.
+/// - `.snapshot` events report the current value of the counter instead of a
+///   count. [`is_snapshot`] tells you whether that is the case. Their values
+///   should not be subtracted from each other and [`reset`] has no effect.
+///
+/// # Example
+/// Measure the energy used by the package over one second:
+/// ```no_run
+/// use perf_event::UncoreCounters;
+///
+/// let mut counters = UncoreCounters::new("power", "energy-pkg")?;
+/// counters.enable()?;
+/// std::thread::sleep(std::time::Duration::from_secs(1));
+/// counters.disable()?;
+///
+/// let data = counters.read_full()?;
+/// println!("{:.3} {}", data.value(), counters.unit().unwrap_or(""));
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`scale`]: Self::scale
+/// [`unit`]: Self::unit
+/// [`is_snapshot`]: Self::is_snapshot
+/// [`reset`]: Self::reset
+#[derive(Debug)]
+pub struct UncoreCounters {
+    event: PmuEvent,
+    counters: Vec<(usize, Location, Counter)>,
+}
+
+/// The package and die that a CPU belongs to, if known.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Location {
+    package: Option<usize>,
+    die: Option<usize>,
+}
+
+impl UncoreCounters {
+    /// Open `event` on the PMU `pmu`.
+    ///
+    /// `pmu` can be either the name of a PMU or an absolute path to its sysfs
+    /// directory, see [`Pmu::new`].
+    pub fn new(pmu: impl AsRef<Path>, event: &str) -> io::Result<Self> {
+        Self::with_builder(&Builder::new(Software::DUMMY), &Pmu::new(pmu)?, event)
+    }
+
+    /// Open `event` on `pmu` using `builder` for everything but the event and
+    /// the process and CPU being observed.
+    ///
+    /// Uncore events cannot be attributed to a single process or privilege
+    /// level, so the counters always observe all processes and do not exclude
+    /// user, kernel, or hypervisor mode.
+    ///
+    /// # Errors
+    /// - An error of kind [`NotFound`](io::ErrorKind::NotFound) if `pmu` has no
+    ///   event named `event`.
+    /// - Any errors from reading the PMU or building the counters.
+    pub fn with_builder(builder: &Builder, pmu: &Pmu, event: &str) -> io::Result<Self> {
+        let info = pmu.event(event)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("PMU `{}` has no event `{}`", pmu.name(), event),
+            )
+        })?;
+
+        let mut dynamic = pmu.builder()?;
+        dynamic.event(event)?;
+
+        let mut builder = builder.clone();
+        builder
+            .event(dynamic.build()?)
+            .any_pid()
+            .exclude_user(false)
+            .exclude_kernel(false)
+            .exclude_hv(false);
+
+        let cpus = match pmu.cpumask()? {
+            Some(cpus) => cpus,
+            None => online_cpus()?,
+        };
+
+        let counters = select_cpus(Path::new(CPU_ROOT), cpus, info.per_pkg())?
+            .into_iter()
+            .map(|(cpu, location)| Ok((cpu, location, builder.one_cpu(cpu).build()?)))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            event: info,
+            counters,
+        })
+    }
+
+    /// The sysfs description of the event being counted.
+    pub fn event(&self) -> &PmuEvent {
+        &self.event
+    }
+
+    /// The scale factor to multiply the counts by to get a value in
+    /// [`unit`](Self::unit)s.
+    pub fn scale(&self) -> Option<f64> {
+        self.event.scale()
+    }
+
+    /// The unit of the event, once multiplied by [`scale`](Self::scale).
+    pub fn unit(&self) -> Option<&str> {
+        self.event.unit()
+    }
+
+    /// Whether the event reports a snapshot of a value instead of a count.
+    pub fn is_snapshot(&self) -> bool {
+        self.event.snapshot()
+    }
+
+    /// The number of counters in this set.
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Whether this set contains no counters at all.
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// Get the counter on `cpu`, if there is one.
+    pub fn get(&self, cpu: usize) -> Option<&Counter> {
+        self.counters
+            .iter()
+            .find(|(c, _, _)| *c == cpu)
+            .map(|(_, _, counter)| counter)
+    }
+
+    /// The package (socket) that the counter on `cpu` is counting for, if
+    /// known.
+    pub fn package(&self, cpu: usize) -> Option<usize> {
+        self.counters
+            .iter()
+            .find(|(c, _, _)| *c == cpu)
+            .and_then(|(_, location, _)| location.package)
+    }
+
+    /// The die within its package that the counter on `cpu` is counting for,
+    /// if known.
+    pub fn die(&self, cpu: usize) -> Option<usize> {
+        self.counters
+            .iter()
+            .find(|(c, _, _)| *c == cpu)
+            .and_then(|(_, location, _)| location.die)
+    }
+
+    /// Iterate over the counters in this set along with the CPU that each one
+    /// was opened on.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Counter)> {
+        self.counters
+            .iter()
+            .map(|(cpu, _, counter)| (*cpu, counter))
+    }
+
+    /// Enable every counter in this set.
+    ///
+    /// See [`Counter::enable`] for details.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.for_each(Counter::enable)
+    }
+
+    /// Disable every counter in this set.
+    ///
+    /// See [`Counter::disable`] for details.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.for_each(Counter::disable)
+    }
+
+    /// Reset every counter in this set to zero.
+    ///
+    /// This does nothing for [snapshot](Self::is_snapshot) events.
+    ///
+    /// See [`Counter::reset`] for details.
+    pub fn reset(&mut self) -> io::Result<()> {
+        if self.is_snapshot() {
+            return Ok(());
+        }
+
+        self.for_each(Counter::reset)
+    }
+
+    /// Read the sum of the values of all counters in this set, multiplied by
+    /// the [`scale`](Self::scale) of the event.
+    pub fn read(&mut self) -> io::Result<f64> {
+        Ok(self.read_full()?.value())
+    }
+
+    /// Read all the data for every counter in this set.
+    pub fn read_full(&mut self) -> io::Result<UncoreCounterData> {
+        let per_cpu = self
+            .counters
+            .iter_mut()
+            .map(|(cpu, _, counter)| Ok((*cpu, counter.read_full()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(UncoreCounterData {
+            total: CounterData::sum(per_cpu.iter().map(|(_, data)| data)),
+            per_cpu,
+            scale: self.scale().unwrap_or(1.0),
+        })
+    }
+
+    fn for_each<F>(&mut self, mut func: F) -> io::Result<()>
+    where
+        F: FnMut(&mut Counter) -> io::Result<()>,
+    {
+        self.counters
+            .iter_mut()
+            .try_for_each(|(_, _, counter)| func(counter))
+    }
+}
+
+/// Look up the package and die of each CPU in `cpus` from the CPU devices in
+/// `root`.
+///
+/// If `per_pkg` is set then only the first CPU of each package and die is
+/// kept. CPUs whose package is unknown are always kept.
+fn select_cpus(root: &Path, cpus: Vec<usize>, per_pkg: bool) -> io::Result<Vec<(usize, Location)>> {
+    let mut selected: Vec<(usize, Location)> = Vec::with_capacity(cpus.len());
+    for cpu in cpus {
+        let location = Location {
+            package: topology_id(root, cpu, "physical_package_id")?,
+            die: topology_id(root, cpu, "die_id")?,
+        };
+
+        let seen = selected
+            .iter()
+            .any(|&(_, l)| l.package.is_some() && l == location);
+        if per_pkg && seen {
+            continue;
+        }
+
+        selected.push((cpu, location));
+    }
+
+    Ok(selected)
+}
+
+/// The data read from an [`UncoreCounters`].
+///
+/// This contains the [`CounterData`] read from the counter on each CPU, along
+/// with an aggregate of all of them. Since each counter covers a different
+/// socket or die, the aggregate is the total across the whole system.
+#[derive(Clone, Debug)]
+pub struct UncoreCounterData {
+    total: CounterData,
+    per_cpu: Vec<(usize, CounterData)>,
+    scale: f64,
+}
+
+impl UncoreCounterData {
+    /// The aggregate of the data across all counters.
+    pub fn total(&self) -> &CounterData {
+        &self.total
+    }
+
+    /// The total count multiplied by the scale of the event.
+    pub fn value(&self) -> f64 {
+        self.total.count() as f64 * self.scale
+    }
+
+    /// Get the data for the counter on `cpu`, if there is one.
+    pub fn get(&self, cpu: usize) -> Option<&CounterData> {
+        self.per_cpu
+            .iter()
+            .find(|(c, _)| *c == cpu)
+            .map(|(_, data)| data)
+    }
+
+    /// Get the count for the counter on `cpu` multiplied by the scale of the
+    /// event, if there is one.
+    pub fn value_on(&self, cpu: usize) -> Option<f64> {
+        self.get(cpu).map(|data| data.count() as f64 * self.scale)
+    }
+
+    /// Iterate over the data for each counter along with the CPU it was
+    /// opened on.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &CounterData)> {
+        self.per_cpu.iter().map(|(cpu, data)| (*cpu, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::per_cpu::parse_cpu_list;
+    use crate::test_util::TempDir;
+
+    /// Create a fake sysfs CPU directory where each CPU has the given
+    /// `(physical_package_id, die_id)`.
+    fn fake_cpus(name: &str, topology: &[(usize, Option<usize>)]) -> TempDir {
+        let dir = TempDir::new(name);
+
+        for (cpu, &(package, die)) in topology.iter().enumerate() {
+            let topology = dir.path().join(format!("cpu{cpu}/topology"));
+            fs::create_dir_all(&topology).unwrap();
+            fs::write(topology.join("physical_package_id"), format!("{package}\n")).unwrap();
+            if let Some(die) = die {
+                fs::write(topology.join("die_id"), format!("{die}\n")).unwrap();
+            }
+        }
+
+        dir
+    }
+
+    #[test]
+    fn per_pkg_multi_die() {
+        // Two packages with two dies each, and two CPUs per die.
+        let root = fake_cpus(
+            "multi-die",
+            &[
+                (0, Some(0)),
+                (0, Some(0)),
+                (0, Some(1)),
+                (0, Some(1)),
+                (1, Some(0)),
+                (1, Some(0)),
+                (1, Some(1)),
+                (1, Some(1)),
+            ],
+        );
+        let cpus = parse_cpu_list("0-7\n").unwrap();
+
+        let selected = select_cpus(root.path(), cpus.clone(), true).unwrap();
+        let selected: Vec<_> = selected
+            .iter()
+            .map(|&(cpu, l)| (cpu, l.package.unwrap(), l.die.unwrap()))
+            .collect();
+        assert_eq!(selected, [(0, 0, 0), (2, 0, 1), (4, 1, 0), (6, 1, 1)]);
+
+        assert_eq!(select_cpus(root.path(), cpus, false).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn per_pkg_without_die_id() {
+        let root = fake_cpus("no-die", &[(0, None), (0, None), (1, None), (1, None)]);
+        let cpus = parse_cpu_list("0,1,2,3\n").unwrap();
+
+        let selected = select_cpus(root.path(), cpus, true).unwrap();
+        let selected: Vec<_> = selected
+            .iter()
+            .map(|&(cpu, l)| (cpu, l.package, l.die))
+            .collect();
+        assert_eq!(selected, [(0, Some(0), None), (2, Some(1), None)]);
+
+        // CPUs with no topology information at all are always kept.
+        let selected = select_cpus(root.path(), vec![8, 9], true).unwrap();
+        assert_eq!(selected.len(), 2);
+    }
+}
@@ -0,0 +1,370 @@
+use std::io;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+
+use crate::sampler::read_user_page;
+use crate::sys::bindings::perf_event_mmap_page;
+use crate::{
+    check_errno_syscall, Counter, CounterData, Group, GroupData, ReadFormat, UserReadData,
+};
+
+/// A counter that can be read directly from userspace.
+///
+/// [`Sampler::read_user`] requires mapping a full ring buffer even when no
+/// records will ever be read from it. A `UserCounter` only maps the metadata
+/// page of the counter, which is all that is needed to read the counter from
+/// userspace.
+///
+/// [`read`](Self::read) will read the counter from userspace when the kernel
+/// allows it and will fall back to a regular `read(2)` call otherwise. Use
+/// [`read_user`](Self::read_user) if you want to avoid the syscall entirely.
+///
+/// See [`Sampler::read_user`] for the restrictions on which counters can be
+/// read from userspace.
+///
+/// # Example
+/// ```
+/// use perf_event::events::Hardware;
+/// use perf_event::Builder;
+///
+/// let mut counter = Builder::new(Hardware::INSTRUCTIONS)
+///     .enabled(true)
+///     .build()?
+///     .user_counter()?;
+///
+/// let before = counter.read()?.count();
+/// // ... do some work ...
+/// let after = counter.read()?.count();
+///
+/// println!("{} instructions", after - before);
+/// # std::io::Result::Ok(())
+/// ```
+///
+/// [`Sampler::read_user`]: crate::Sampler::read_user
+pub struct UserCounter {
+    counter: Counter,
+    mmap: memmap2::MmapRaw,
+}
+
+impl UserCounter {
+    pub(crate) fn new(counter: Counter) -> io::Result<Self> {
+        let mmap = map_metadata_page(&counter)?;
+        Ok(Self { counter, mmap })
+    }
+
+    /// Convert this `UserCounter` back into a [`Counter`].
+    pub fn into_counter(self) -> Counter {
+        self.counter
+    }
+
+    /// Access the underlying counter.
+    pub fn as_counter(&self) -> &Counter {
+        &self.counter
+    }
+
+    /// Mutably access the underlying counter.
+    pub fn as_counter_mut(&mut self) -> &mut Counter {
+        &mut self.counter
+    }
+
+    /// Read the counter directly from userspace.
+    ///
+    /// This never makes a syscall. If the counter value could not be read
+    /// then [`UserReadData::try_count`] will indicate why.
+    pub fn read_user(&self) -> UserReadData {
+        // SAFETY: the mapping is a single perf_event_mmap_page.
+        unsafe { read_user_page(self.mmap.as_ptr() as *const perf_event_mmap_page) }
+    }
+
+    /// Read the counter, from userspace if possible.
+    ///
+    /// If the counter cannot be read from userspace then this falls back to
+    /// [`Counter::read_full`]. The number of lost samples is not available
+    /// from userspace so counters with [`ReadFormat::LOST`] in their
+    /// `read_format` are always read using [`Counter::read_full`].
+    pub fn read(&mut self) -> io::Result<CounterData> {
+        let read_format = self.counter.config().read_format();
+        if read_format.contains(ReadFormat::LOST) {
+            return self.counter.read_full();
+        }
+
+        let data = self.read_user();
+        let count = match data.count() {
+            Some(count) => count,
+            None => return self.counter.read_full(),
+        };
+
+        Ok(CounterData::from_parts(
+            count,
+            read_format
+                .contains(ReadFormat::TOTAL_TIME_ENABLED)
+                .then(|| data.time_enabled().as_nanos() as u64),
+            read_format
+                .contains(ReadFormat::TOTAL_TIME_RUNNING)
+                .then(|| data.time_running().as_nanos() as u64),
+            read_format
+                .contains(ReadFormat::ID)
+                .then(|| self.counter.id()),
+            None,
+        ))
+    }
+}
+
+impl AsRawFd for UserCounter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.counter.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for UserCounter {
+    fn into_raw_fd(self) -> RawFd {
+        self.counter.into_raw_fd()
+    }
+}
+
+/// A [`Group`] whose members can be read directly from userspace.
+///
+/// This is the group equivalent of [`UserCounter`]. The metadata page of each
+/// member of the group is mapped so that they can be read without making a
+/// syscall. Since the group is scheduled as a unit, all members will either be
+/// readable from userspace or not.
+///
+/// # Example
+/// ```
+/// use perf_event::events::Hardware;
+/// use perf_event::{Builder, Group};
+///
+/// let mut group = Group::new()?;
+/// let cycles = group.add(&Builder::new(Hardware::CPU_CYCLES))?;
+/// let insns = group.add(&Builder::new(Hardware::INSTRUCTIONS))?;
+/// let mut group = group.user_group(vec![cycles, insns])?;
+///
+/// group.enable()?;
+/// // ... do some work ...
+/// let counts = group.read()?;
+///
+/// let cycles = &group.members()[0];
+/// let insns = &group.members()[1];
+/// println!(
+///     "cycles / instructions: {} / {}",
+///     counts[cycles.as_counter()],
+///     counts[insns.as_counter()]
+/// );
+/// # std::io::Result::Ok(())
+/// ```
+pub struct UserGroup {
+    group: Group,
+    members: Vec<UserCounter>,
+}
+
+impl UserGroup {
+    pub(crate) fn new(mut group: Group, members: Vec<Counter>) -> io::Result<Self> {
+        let data = group.read()?;
+        if let Some(member) = members.iter().find(|member| data.get(member).is_none()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "counter with id {} is not a member of the group",
+                    member.id()
+                ),
+            ));
+        }
+
+        let members = members
+            .into_iter()
+            .map(UserCounter::new)
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { group, members })
+    }
+
+    /// Access the underlying group.
+    pub fn as_group(&self) -> &Group {
+        &self.group
+    }
+
+    /// Mutably access the underlying group.
+    pub fn as_group_mut(&mut self) -> &mut Group {
+        &mut self.group
+    }
+
+    /// Convert this `UserGroup` back into its group and member counters.
+    pub fn into_parts(self) -> (Group, Vec<Counter>) {
+        let members = self
+            .members
+            .into_iter()
+            .map(UserCounter::into_counter)
+            .collect();
+
+        (self.group, members)
+    }
+
+    /// The members of this group, in the order they were provided.
+    pub fn members(&self) -> &[UserCounter] {
+        &self.members
+    }
+
+    /// Enable all counters in this group.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.group.enable()
+    }
+
+    /// Disable all counters in this group.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.group.disable()
+    }
+
+    /// Reset the value of all counters in this group to zero.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.group.reset()
+    }
+
+    /// Read each member of the group directly from userspace.
+    ///
+    /// This never makes a syscall. The values are returned in the same order
+    /// as [`members`](Self::members).
+    pub fn read_user(&self) -> Vec<UserReadData> {
+        self.members.iter().map(UserCounter::read_user).collect()
+    }
+
+    /// Read all members of the group, from userspace if possible.
+    ///
+    /// If any member cannot be read from userspace then this falls back to
+    /// [`Group::read`]. As with [`UserCounter::read`], groups with
+    /// [`ReadFormat::LOST`] in their `read_format` are always read using
+    /// [`Group::read`].
+    ///
+    /// Either way, the returned [`GroupData`] only contains entries for the
+    /// [`members`](Self::members) of this `UserGroup`, in the same order. The
+    /// group leader and any other counters in the group are not included.
+    ///
+    /// Note that, unlike [`Group::read`], the members are not read atomically
+    /// when read from userspace. The returned times are then the longest times
+    /// of any member, which is the closest match for the times of the group.
+    pub fn read(&mut self) -> io::Result<GroupData> {
+        let read_format = self.group.as_counter().config().read_format();
+        if read_format.contains(ReadFormat::LOST) {
+            return self.read_syscall();
+        }
+
+        let ids = self.members.iter().map(|member| member.as_counter().id());
+        match user_group_data(read_format, ids.zip(self.read_user())) {
+            Some(data) => Ok(data),
+            None => self.read_syscall(),
+        }
+    }
+
+    /// Read the group using [`Group::read`], keeping only the entries for
+    /// the members of this `UserGroup`.
+    fn read_syscall(&mut self) -> io::Result<GroupData> {
+        let data = self.group.read()?;
+        let entries = self
+            .members
+            .iter()
+            .filter_map(|member| data.get(member.as_counter()))
+            .map(|entry| (entry.value(), Some(entry.id()), entry.lost()))
+            .collect::<Vec<_>>();
+
+        Ok(GroupData::from_parts(
+            data.time_enabled().map(|time| time.as_nanos() as u64),
+            data.time_running().map(|time| time.as_nanos() as u64),
+            entries,
+        ))
+    }
+}
+
+/// Build the [`GroupData`] for a [`UserGroup`] from the userspace reads of
+/// each of its members, along with their ids.
+///
+/// Returns `None` if any of the members could not be read from userspace.
+fn user_group_data<I>(read_format: ReadFormat, reads: I) -> Option<GroupData>
+where
+    I: IntoIterator<Item = (u64, UserReadData)>,
+{
+    let mut entries = Vec::new();
+    let mut enabled = 0;
+    let mut running = 0;
+
+    for (id, data) in reads {
+        entries.push((data.count()?, Some(id), None));
+        enabled = enabled.max(data.time_enabled().as_nanos() as u64);
+        running = running.max(data.time_running().as_nanos() as u64);
+    }
+
+    Some(GroupData::from_parts(
+        read_format
+            .contains(ReadFormat::TOTAL_TIME_ENABLED)
+            .then_some(enabled),
+        read_format
+            .contains(ReadFormat::TOTAL_TIME_RUNNING)
+            .then_some(running),
+        entries,
+    ))
+}
+
+/// Map only the metadata page for a counter.
+fn map_metadata_page(counter: &Counter) -> io::Result<memmap2::MmapRaw> {
+    let pagesize = check_errno_syscall(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) })? as usize;
+
+    memmap2::MmapOptions::new()
+        .len(pagesize)
+        .map_raw_read_only(&counter.file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Software;
+    use crate::{Builder, UserReadError};
+
+    #[test]
+    fn both_reads_return_members() {
+        let mut group = Group::new().unwrap();
+        let first = group.add(&Builder::new(Software::DUMMY)).unwrap();
+        let second = group.add(&Builder::new(Software::DUMMY)).unwrap();
+        let other = group.add(&Builder::new(Software::DUMMY)).unwrap();
+        let ids = [second.id(), first.id()];
+
+        let mut group = group.user_group(vec![second, first]).unwrap();
+        let read_format = group.as_group().as_counter().config().read_format();
+
+        // Software counters can't be read from userspace so this falls back to
+        // the syscall.
+        let syscall = group.read().unwrap();
+
+        let user = user_group_data(
+            read_format,
+            [
+                (ids[0], UserReadData::new(300, 200, Ok(5))),
+                (ids[1], UserReadData::new(400, 100, Ok(7))),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            user.time_enabled(),
+            Some(std::time::Duration::from_nanos(400))
+        );
+        assert_eq!(
+            user.time_running(),
+            Some(std::time::Duration::from_nanos(200))
+        );
+        assert_eq!(user[group.members()[1].as_counter()], 7);
+
+        for data in [&syscall, &user] {
+            assert_eq!(data.iter().map(|entry| entry.id()).collect::<Vec<_>>(), ids);
+            assert!(data.get(group.as_group().as_counter()).is_none());
+            assert!(data.get(&other).is_none());
+        }
+
+        let inactive = user_group_data(
+            read_format,
+            [
+                (ids[0], UserReadData::new(300, 200, Ok(5))),
+                (
+                    ids[1],
+                    UserReadData::new(300, 200, Err(UserReadError::CounterInactive)),
+                ),
+            ],
+        );
+        assert!(inactive.is_none());
+    }
+}
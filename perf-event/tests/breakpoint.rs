@@ -1,15 +1,9 @@
 use perf_event::events::Breakpoint;
 use perf_event::Builder;
 
-// Need a function that will not be optimized away or inlined by the compiler.
-#[inline(never)]
-fn use_data(data: &[u8]) {
-    for byte in data {
-        // Use a volatile read here to ensure that the resulting program
-        // actually does the read from data and it doesn't get optimized away.
-        unsafe { std::ptr::read_volatile(byte) };
-    }
-}
+use crate::common::use_data;
+
+mod common;
 
 #[test]
 fn data() {
@@ -47,3 +41,43 @@ fn execute() {
     counter.disable().unwrap();
     assert_eq!(counter.read().unwrap(), 1000);
 }
+
+#[test]
+fn modify() {
+    let data = b"TEST DATA".to_vec();
+    let other = b"OTHER DATA".to_vec();
+
+    let mut counter = Builder::new(Breakpoint::read_write(data.as_ptr() as usize as _, 1))
+        .observe_self()
+        .build()
+        .expect("Unable to build performance counter");
+    counter.enable().unwrap();
+
+    for _ in 0..1000 {
+        use_data(&data);
+    }
+
+    counter
+        .modify_breakpoint(Breakpoint::read_write(other.as_ptr() as usize as _, 1))
+        .expect("Unable to modify breakpoint");
+
+    for _ in 0..1000 {
+        use_data(&data);
+        use_data(&other);
+    }
+
+    counter.disable().unwrap();
+    assert_eq!(counter.read().unwrap(), 2000);
+}
+
+#[test]
+fn modify_non_breakpoint() {
+    let mut counter = Builder::new(perf_event::events::Software::DUMMY)
+        .build()
+        .expect("Unable to build performance counter");
+
+    let error = counter
+        .modify_breakpoint(Breakpoint::execute(use_data as fn(_) as usize as u64))
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
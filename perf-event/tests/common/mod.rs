@@ -0,0 +1,63 @@
+// Each test binary only uses some of these helpers.
+#![allow(dead_code)]
+
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Run a loop that will not be optimized away so that counters have
+/// something to count.
+pub fn busy_loop(iterations: u64) {
+    let mut sum = 0u64;
+    for i in 0..iterations {
+        sum = black_box(sum.wrapping_add(i));
+    }
+    black_box(sum);
+}
+
+/// Read every byte of `data` so that breakpoints on it trigger.
+///
+/// This is never inlined so that its address can also be used for execute
+/// breakpoints.
+#[inline(never)]
+pub fn use_data(data: &[u8]) {
+    for byte in data {
+        // Use a volatile read here to ensure that the resulting program
+        // actually does the read from data and it doesn't get optimized away.
+        unsafe { std::ptr::read_volatile(byte) };
+    }
+}
+
+/// A temporary directory that is removed when it is dropped, even if the test
+/// using it panics.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a new, empty, directory whose name includes `name`.
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "perf-event-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        Self(dir)
+    }
+
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
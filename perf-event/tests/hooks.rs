@@ -0,0 +1,433 @@
+#![cfg(feature = "hooks")]
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use perf_event::events::{Breakpoint, Hardware, Raw, Software, TracepointFilter};
+use perf_event::hooks::sys::bindings;
+use perf_event::hooks::{clear_thread_hooks, set_thread_hooks, Hooks, MockKernel};
+use perf_event::pmu::Pmu;
+use perf_event::{
+    Builder, CpuSampler, Group, HybridCounters, ReadFormat, SampleFlag, UnsupportedOptionsError,
+};
+
+/// Run `func` with a fresh `MockKernel` installed for the current thread.
+fn with_mock<F>(func: F)
+where
+    F: FnOnce(&MockKernel),
+{
+    let kernel = MockKernel::new();
+    unsafe { set_thread_hooks(Box::new(kernel.clone())) };
+    func(&kernel);
+    unsafe { clear_thread_hooks() };
+}
+
+#[test]
+fn scripted_counts() {
+    with_mock(|kernel| {
+        let mut counter = Builder::new(Hardware::INSTRUCTIONS).build().unwrap();
+        assert_eq!(kernel.is_enabled(counter.id()), Some(false));
+
+        kernel.script_counts(counter.id(), [10, 20]);
+        counter.enable().unwrap();
+        assert_eq!(kernel.is_enabled(counter.id()), Some(true));
+
+        assert_eq!(counter.read().unwrap(), 10);
+        assert_eq!(counter.read().unwrap(), 20);
+        assert_eq!(counter.read().unwrap(), 20);
+
+        counter.reset().unwrap();
+        assert_eq!(counter.read().unwrap(), 0);
+
+        kernel.set_count(counter.id(), 5);
+        assert_eq!(counter.read().unwrap(), 5);
+    });
+}
+
+#[test]
+fn multiplexing() {
+    with_mock(|kernel| {
+        let mut counter = Builder::new(Hardware::CPU_CYCLES)
+            .read_format(
+                ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::TOTAL_TIME_RUNNING | ReadFormat::LOST,
+            )
+            .build()
+            .unwrap();
+        kernel.set_count(counter.id(), 1000);
+        kernel.set_running_ratio(counter.id(), 0.25);
+        kernel.set_lost(counter.id(), 3);
+
+        // Time doesn't advance while the counter is disabled.
+        kernel.advance(Duration::from_millis(4));
+        counter.enable().unwrap();
+        kernel.advance(Duration::from_millis(8));
+
+        let data = counter.read_full().unwrap();
+        assert_eq!(data.count(), 1000);
+        assert_eq!(data.time_enabled(), Some(Duration::from_millis(8)));
+        assert_eq!(data.time_running(), Some(Duration::from_millis(2)));
+        assert_eq!(data.scaled_count(), Some(4000));
+        assert_eq!(data.lost(), Some(3));
+    });
+}
+
+#[test]
+fn groups() {
+    with_mock(|kernel| {
+        let mut group = Group::new().unwrap();
+        let a = group.add(&Builder::new(Hardware::INSTRUCTIONS)).unwrap();
+        let b = group.add(&Builder::new(Hardware::CPU_CYCLES)).unwrap();
+        assert_eq!(kernel.leader(b.id()), Some(group.id()));
+
+        kernel.set_count(a.id(), 1);
+        kernel.set_count(b.id(), 2);
+        kernel.set_running_ratio(group.id(), 0.5);
+
+        group.enable().unwrap();
+        assert_eq!(kernel.is_enabled(b.id()), Some(true));
+        kernel.advance(Duration::from_millis(2));
+
+        let data = group.read().unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[&a], 1);
+        assert_eq!(data[&b], 2);
+        assert_eq!(data.time_running(), Some(Duration::from_millis(1)));
+
+        group.disable().unwrap();
+        assert_eq!(kernel.is_enabled(a.id()), Some(false));
+    });
+}
+
+#[test]
+fn group_read_grows_buffer() {
+    with_mock(|kernel| {
+        let mut group = Group::new().unwrap();
+
+        // Add members behind the group's back so that it has to retry the read
+        // with a larger buffer.
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let mut attrs = *Builder::new(Software::DUMMY).attrs();
+            let fd = unsafe {
+                perf_event::hooks::sys::perf_event_open(
+                    &mut attrs,
+                    0,
+                    -1,
+                    group.as_counter().as_raw_fd(),
+                    0,
+                )
+            };
+            assert!(fd >= 0);
+            ids.push(fd);
+        }
+
+        assert_eq!(kernel.ids().len(), 5);
+        assert_eq!(group.read().unwrap().len(), 4);
+
+        for fd in ids {
+            unsafe { libc::close(fd) };
+        }
+    });
+}
+
+#[test]
+fn open_errors() {
+    with_mock(|kernel| {
+        kernel.set_attr_size(64);
+        let error = Builder::new(Software::DUMMY).build().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        let inner = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<UnsupportedOptionsError>())
+            .unwrap();
+        assert_eq!(inner.expected_size(), 64);
+        kernel.set_attr_size(
+            std::mem::size_of::<perf_event::hooks::sys::bindings::perf_event_attr>() as u32,
+        );
+
+        kernel.set_paranoid(2);
+        let error = Builder::new(Software::DUMMY)
+            .exclude_kernel(false)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::EACCES));
+        let error = Builder::new(Software::DUMMY)
+            .any_pid()
+            .one_cpu(0)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::EACCES));
+        Builder::new(Software::DUMMY).build().unwrap();
+
+        kernel.fail_next_open(libc::ENOENT);
+        let error = Builder::new(Software::DUMMY).build().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        Builder::new(Software::DUMMY).build().unwrap();
+    });
+}
+
+#[test]
+fn ioctls() {
+    with_mock(|kernel| {
+        let mut counter = Builder::new(Software::DUMMY).build().unwrap();
+        let id = counter.id();
+
+        counter
+            .set_filter(&TracepointFilter::new_unchecked("common_pid == 1"))
+            .unwrap();
+        assert_eq!(kernel.filter(id).as_deref(), Some("common_pid == 1"));
+
+        counter.set_period(1234).unwrap();
+        assert_eq!(
+            unsafe { kernel.attrs(id).unwrap().__bindgen_anon_1.sample_period },
+            1234
+        );
+
+        counter.set_bpf(7).unwrap();
+        counter.set_bpf(9).unwrap();
+        assert_eq!(counter.attached_bpf_programs().unwrap(), [7, 9]);
+
+        let mut sampler = Builder::new(Software::DUMMY)
+            .build()
+            .unwrap()
+            .sampled(4096)
+            .unwrap();
+        sampler.pause_output().unwrap();
+        assert_eq!(kernel.is_paused(sampler.as_counter().id()), Some(true));
+
+        let other = Builder::new(Software::DUMMY).build().unwrap();
+        let other_id = other.id();
+        sampler.attach(other).unwrap();
+        assert_eq!(kernel.output(other_id), Some(sampler.as_counter().id()));
+    });
+}
+
+#[test]
+fn modify_breakpoint() {
+    with_mock(|kernel| {
+        let mut breakpoint = Builder::new(Breakpoint::execute(0x1000)).build().unwrap();
+        let id = breakpoint.id();
+        assert_eq!(kernel.is_enabled(id), Some(false));
+
+        breakpoint
+            .modify_breakpoint(Breakpoint::read(0x2000, 8))
+            .unwrap();
+        assert_eq!(kernel.is_enabled(id), Some(true));
+
+        let attrs = kernel.attrs(id).unwrap();
+        assert_eq!(attrs.bp_type, bindings::HW_BREAKPOINT_R);
+        assert_eq!(attrs.disabled(), 0);
+        assert_eq!(unsafe { attrs.__bindgen_anon_3.bp_addr }, 0x2000);
+        assert_eq!(unsafe { attrs.__bindgen_anon_4.bp_len }, 8);
+
+        // Only counters created with a breakpoint event can be modified.
+        let mut counter = Builder::new(Software::DUMMY).build().unwrap();
+        let error = counter
+            .modify_breakpoint(Breakpoint::read(0x2000, 8))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(kernel.is_enabled(counter.id()), Some(false));
+    });
+}
+
+#[test]
+fn ring_buffer() {
+    with_mock(|kernel| {
+        let mut sampler = Builder::new(Software::DUMMY)
+            .build()
+            .unwrap()
+            .sampled(4096)
+            .unwrap();
+        assert!(sampler.next_record().is_none());
+
+        // Write enough records to wrap around the end of the buffer.
+        for round in 0..3u8 {
+            for i in 0..100u8 {
+                let data = [round, i, 0xAB];
+                kernel.push_record(&sampler, 1000, 2, &data).unwrap();
+            }
+
+            for i in 0..100u8 {
+                let record = sampler.next_record().unwrap();
+                assert_eq!(record.ty(), 1000);
+                assert_eq!(record.misc(), 2);
+                assert_eq!(record.to_vec(), [round, i, 0xAB, 0, 0, 0, 0, 0]);
+            }
+
+            assert!(sampler.next_record().is_none());
+        }
+
+        let data = vec![0; 8192];
+        assert!(kernel.push_record(&sampler, 1000, 0, &data).is_err());
+    });
+}
+
+#[test]
+fn cpu_sampler_orders_mmap_records() {
+    use perf_event::hooks::sys::bindings::{PERF_RECORD_MMAP, PERF_RECORD_SAMPLE};
+
+    with_mock(|kernel| {
+        let mut builder = Builder::new(Software::DUMMY);
+        builder.any_pid().sample(SampleFlag::TID);
+        let mut sampler = CpuSampler::with_cpus(&builder, [0, 1], 4096).unwrap();
+
+        // The trailing sample id contains the pid/tid and the time.
+        let mut mmap = Vec::new();
+        mmap.extend_from_slice(&[1u32, 1].map(u32::to_ne_bytes).concat());
+        mmap.extend_from_slice(&[0x1000u64, 0x2000, 0].map(u64::to_ne_bytes).concat());
+        mmap.extend_from_slice(b"/usr/bin/ls\0\0\0\0\0");
+        mmap.extend_from_slice(&[1u32, 1].map(u32::to_ne_bytes).concat());
+        mmap.extend_from_slice(&200u64.to_ne_bytes());
+        kernel
+            .push_record(sampler.get(0).unwrap(), PERF_RECORD_MMAP, 0, &mmap)
+            .unwrap();
+
+        let mut sample = Vec::new();
+        sample.extend_from_slice(&[1u32, 1].map(u32::to_ne_bytes).concat());
+        sample.extend_from_slice(&100u64.to_ne_bytes());
+        kernel
+            .push_record(sampler.get(1).unwrap(), PERF_RECORD_SAMPLE, 0, &sample)
+            .unwrap();
+
+        let first = sampler.next_record().unwrap();
+        assert_eq!(first.ty(), PERF_RECORD_SAMPLE);
+        drop(first);
+
+        // The other ring buffer is now empty so the record is only returned
+        // once the reordering window has been bypassed.
+        assert!(sampler.next_record().is_none());
+        let second = sampler.flush_next().unwrap();
+        assert_eq!(second.ty(), PERF_RECORD_MMAP);
+        assert_eq!(second.parse_sample_id().unwrap().time(), Some(200));
+        drop(second);
+
+        assert!(sampler.flush_next().is_none());
+        assert!(format!("{:?}", sampler).contains("cpus: [0, 1]"));
+    });
+}
+
+#[test]
+fn flight_recorder_resumes_output() {
+    with_mock(|kernel| {
+        let mut recorder = Builder::new(Software::DUMMY)
+            .write_backward(true)
+            .build()
+            .unwrap()
+            .flight_recorder(4096)
+            .unwrap();
+        let id = recorder.as_counter().id();
+
+        assert!(recorder.snapshot(16).unwrap().is_empty());
+        assert_eq!(kernel.is_paused(id), Some(false));
+    });
+}
+
+/// Hooks that attach another BPF program each time the attached programs are
+/// queried, as if they were being attached concurrently.
+struct GrowingBpf {
+    programs: Vec<u32>,
+    pending: u32,
+}
+
+impl Hooks for GrowingBpf {
+    unsafe fn perf_event_open(
+        &mut self,
+        _attrs: *mut bindings::perf_event_attr,
+        _pid: libc::pid_t,
+        _cpu: libc::c_int,
+        _group_fd: libc::c_int,
+        _flags: libc::c_ulong,
+    ) -> libc::c_int {
+        libc::open(
+            b"/dev/null\0".as_ptr() as *const libc::c_char,
+            libc::O_RDONLY | libc::O_CLOEXEC,
+        )
+    }
+
+    unsafe fn ID(&mut self, _fd: libc::c_int, id: *mut u64) -> libc::c_int {
+        *id = 1;
+        0
+    }
+
+    unsafe fn QUERY_BPF(
+        &mut self,
+        _fd: libc::c_int,
+        query: *mut bindings::perf_event_query_bpf,
+    ) -> libc::c_int {
+        if self.pending > 0 {
+            self.programs.push(self.programs.len() as u32 + 10);
+            self.pending -= 1;
+        }
+
+        let capacity = (*query).ids_len as usize;
+        (*query).prog_cnt = self.programs.len() as u32;
+        if capacity < self.programs.len() {
+            *libc::__errno_location() = libc::ENOSPC;
+            return -1;
+        }
+
+        let ids = (*query).ids.as_mut_ptr();
+        for (i, &id) in self.programs.iter().enumerate() {
+            *ids.add(i) = id;
+        }
+
+        0
+    }
+}
+
+#[test]
+fn attached_bpf_programs_grows_buffer() {
+    unsafe {
+        set_thread_hooks(Box::new(GrowingBpf {
+            programs: vec![10, 11, 12],
+            pending: 2,
+        }))
+    };
+
+    let counter = Builder::new(Software::DUMMY).build().unwrap();
+    let programs = counter.attached_bpf_programs();
+    drop(counter);
+    unsafe { clear_thread_hooks() };
+
+    // The first query only finds out how many programs there are, the second
+    // is too small because another program was attached in between, and the
+    // third succeeds.
+    assert_eq!(programs.unwrap(), [10, 11, 12, 13, 14]);
+}
+
+#[test]
+fn hybrid_counters_per_pmu() {
+    let data = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+    // The PMU types are 4 and 66666.
+    let pmus = [
+        Pmu::new(format!("{}/cpu-pmu", data)).unwrap(),
+        Pmu::new(format!("{}/dyn-pmu", data)).unwrap(),
+    ];
+
+    with_mock(|kernel| {
+        let builder = Builder::new(Hardware::INSTRUCTIONS);
+        let mut counters =
+            HybridCounters::with_pmus(&builder, Hardware::INSTRUCTIONS, &pmus).unwrap();
+
+        let ids: Vec<_> = counters.iter().map(|(_, counter)| counter.id()).collect();
+        let configs: Vec<_> = ids
+            .iter()
+            .map(|&id| kernel.attrs(id).unwrap().config)
+            .collect();
+        assert_eq!(configs, [4 << 32 | 1, 66666 << 32 | 1]);
+
+        // Each counter only counts on its own type of core so the total is
+        // the sum of the two.
+        kernel.set_count(ids[0], 100);
+        kernel.set_count(ids[1], 50);
+        assert_eq!(counters.read().unwrap(), 150);
+
+        let counters = HybridCounters::with_pmus(&builder, Raw::new(0xC0), &pmus).unwrap();
+        let types: Vec<_> = counters
+            .iter()
+            .map(|(_, counter)| kernel.attrs(counter.id()).unwrap().type_)
+            .collect();
+        assert_eq!(types, [4, 66666]);
+    });
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use perf_event::events::Software;
+use perf_event::pmu::Pmu;
+use perf_event::{Builder, HybridCounters};
+
+use crate::common::busy_loop;
+
+mod common;
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+
+#[test]
+fn single_core_pmu() {
+    if perf_event::pmu::is_hybrid().unwrap() {
+        return;
+    }
+
+    let builder = Builder::new(Software::TASK_CLOCK);
+    let mut counters = HybridCounters::new(&builder, Software::TASK_CLOCK).unwrap();
+    assert_eq!(counters.len(), 1);
+
+    counters.enable().unwrap();
+    busy_loop(1_000_000);
+    counters.disable().unwrap();
+
+    assert!(counters.read().unwrap() > 0);
+}
+
+#[test]
+fn rejects_software_events() {
+    // Software events count on every core already so opening them on each core
+    // PMU would count everything twice.
+    let pmus = [
+        Pmu::new(Path::new(DATA).join("cpu-pmu")).unwrap(),
+        Pmu::new(Path::new(DATA).join("dyn-pmu")).unwrap(),
+    ];
+
+    let builder = Builder::new(Software::TASK_CLOCK);
+    let error = HybridCounters::with_pmus(&builder, Software::TASK_CLOCK, &pmus).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
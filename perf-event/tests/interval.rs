@@ -0,0 +1,79 @@
+use perf_event::events::{Breakpoint, Software};
+use perf_event::{Builder, Group, IntervalReader, ReadFormat};
+
+use crate::common::use_data;
+
+mod common;
+
+#[test]
+fn counter_deltas() {
+    let data = b"TEST DATA".to_vec();
+
+    let counter = Builder::new(Breakpoint::read_write(data.as_ptr() as usize as _, 1))
+        .observe_self()
+        .enable_scaling()
+        .enabled(true)
+        .build()
+        .expect("Unable to build performance counter");
+    let mut reader = IntervalReader::new(counter);
+
+    for _ in 0..1000 {
+        use_data(&data);
+    }
+    let first = reader.read().unwrap();
+    assert_eq!(first.count(), 1000);
+
+    for _ in 0..500 {
+        use_data(&data);
+    }
+    let second = reader.read().unwrap();
+    assert_eq!(second.count(), 500);
+    assert_eq!(second.scaled_count(), Some(500));
+    assert!(second.time_enabled().unwrap() < first.time_enabled().unwrap() * 1000);
+
+    // Resetting the counter should not produce a bogus delta.
+    reader.as_inner_mut().reset().unwrap();
+    for _ in 0..10 {
+        use_data(&data);
+    }
+    assert_eq!(reader.read().unwrap().count(), 10);
+}
+
+#[test]
+fn group_membership_changes() {
+    let mut group = Group::new().unwrap();
+    let first = group.add(&Builder::new(Software::TASK_CLOCK)).unwrap();
+    let mut reader = IntervalReader::new(group);
+    reader.as_inner_mut().enable().unwrap();
+
+    let data = reader.read().unwrap();
+    assert_eq!(data.len(), 1);
+
+    let second = reader
+        .as_inner_mut()
+        .add(&Builder::new(Software::TASK_CLOCK))
+        .unwrap();
+
+    let data = reader.read().unwrap();
+    assert_eq!(data.len(), 2);
+    assert!(data.get(&second).is_some());
+    assert!(data[&first] <= data.time_enabled().unwrap().as_nanos() as u64);
+
+    drop(first);
+    let data = reader.read().unwrap();
+    assert_eq!(data.len(), 1);
+    assert!(data.get(&second).is_some());
+}
+
+#[test]
+fn counter_delta_keeps_id() {
+    let counter = Builder::new(Software::DUMMY)
+        .read_format(ReadFormat::ID)
+        .build()
+        .unwrap();
+    let id = counter.id();
+    let mut reader = IntervalReader::new(counter);
+
+    assert_eq!(reader.read().unwrap().id(), Some(id));
+    assert_eq!(reader.read().unwrap().id(), Some(id));
+}
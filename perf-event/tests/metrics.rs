@@ -0,0 +1,65 @@
+use std::io;
+
+use perf_event::events::Software;
+use perf_event::metrics::{Metric, MetricGroup};
+
+use crate::common::busy_loop;
+
+mod common;
+
+#[test]
+fn software_metric() {
+    let mut ratio = Metric::new("clock ratio", "task_clock / cpu_clock", "ratio").unwrap();
+    ratio
+        .event("task_clock", Software::TASK_CLOCK)
+        .event("cpu_clock", Software::CPU_CLOCK);
+
+    let mut percent = Metric::new("clock percent", "100 * task_clock / cpu_clock", "%").unwrap();
+    percent
+        .event("task_clock", Software::TASK_CLOCK)
+        .event("cpu_clock", Software::CPU_CLOCK);
+
+    let mut group = MetricGroup::new(vec![ratio, percent]).unwrap();
+    assert!(group.counter("task_clock").is_some());
+    assert!(group.counter("cpu_clock").is_some());
+    assert!(group.counter("missing").is_none());
+
+    group.enable().unwrap();
+    busy_loop(10_000_000);
+    group.disable().unwrap();
+
+    let values = group.read().unwrap();
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[0].name(), "clock ratio");
+    assert_eq!(values[1].unit(), "%");
+
+    let ratio = values[0].value().unwrap();
+    assert!(0.5 < ratio && ratio < 1.5, "ratio was {}", ratio);
+
+    let percent = values[1].value().unwrap();
+    assert!((percent - ratio * 100.0).abs() < 1e-6);
+    assert!(format!("{:.1}", values[1]).ends_with(" %"));
+}
+
+#[test]
+fn undefined_event() {
+    let mut metric = Metric::new("bad", "task_clock / cpu_clock", "ratio").unwrap();
+    metric.event("task_clock", Software::TASK_CLOCK);
+
+    let error = MetricGroup::new(vec![metric]).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn not_counted() {
+    let mut metric = Metric::new("faults", "task_clock / faults", "ns per fault").unwrap();
+    metric
+        .event("task_clock", Software::TASK_CLOCK)
+        .event("faults", Software::PAGE_FAULTS);
+
+    // The group is never enabled so there is nothing to divide by.
+    let mut group = MetricGroup::new(vec![metric]).unwrap();
+    let values = group.read().unwrap();
+    assert_eq!(values[0].value(), None);
+    assert_eq!(values[0].to_string(), "<not counted>");
+}
@@ -0,0 +1,112 @@
+use std::io;
+use std::path::Path;
+
+use perf_event::pmu::{FormatTarget, Pmu};
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+
+fn dyn_pmu() -> Pmu {
+    Pmu::new(Path::new(DATA).join("dyn-pmu")).unwrap()
+}
+
+#[test]
+fn pmu_metadata() {
+    let pmu = dyn_pmu();
+
+    assert_eq!(pmu.name(), "dyn-pmu");
+    assert_eq!(pmu.ty().unwrap(), 66666);
+    assert_eq!(pmu.cpumask().unwrap(), Some(vec![0, 1, 4]));
+    assert_eq!(pmu.nr_addr_filters().unwrap(), Some(2));
+    assert_eq!(
+        pmu.caps().unwrap(),
+        [
+            ("branches".to_owned(), "32".to_owned()),
+            ("max_precise".to_owned(), "3".to_owned())
+        ]
+    );
+    assert_eq!(pmu.cap("max_precise").unwrap().as_deref(), Some("3"));
+    assert_eq!(pmu.cap("missing").unwrap(), None);
+
+    let cpu = Pmu::new(Path::new(DATA).join("cpu-pmu")).unwrap();
+    assert_eq!(cpu.cpumask().unwrap(), None);
+    assert_eq!(cpu.nr_addr_filters().unwrap(), None);
+}
+
+#[test]
+fn pmu_formats() {
+    let pmu = dyn_pmu();
+
+    let formats = pmu.formats().unwrap();
+    let names: Vec<_> = formats.iter().map(|format| format.name()).collect();
+    assert_eq!(names, ["event", "flag", "param"]);
+
+    let param = pmu.format("param").unwrap().unwrap();
+    assert_eq!(param.target(), FormatTarget::Config);
+    assert_eq!(param.bits(), [32..=47]);
+    assert_eq!(param.mask(), 0xFFFF_0000_0000);
+    assert!(param.validate(0xFFFF));
+    assert!(!param.validate(0x10000));
+
+    assert_eq!(pmu.format("missing").unwrap(), None);
+}
+
+#[test]
+fn pmu_events() {
+    let pmu = dyn_pmu();
+
+    let events = pmu.events().unwrap();
+    let names: Vec<_> = events.iter().map(|event| event.name()).collect();
+    assert_eq!(names, ["evt1", "evt2"]);
+
+    let evt1 = &events[0];
+    assert_eq!(
+        evt1.terms().collect::<Vec<_>>(),
+        [
+            ("event", Some(0xFFFFFFFF)),
+            ("param", None),
+            ("flag", Some(1))
+        ]
+    );
+    assert_eq!(evt1.params().collect::<Vec<_>>(), ["param"]);
+    assert_eq!(evt1.scale(), Some(0.5));
+    assert_eq!(evt1.unit(), Some("Frogs"));
+    assert!(evt1.per_pkg());
+    assert!(!evt1.snapshot());
+
+    let evt2 = pmu.event("evt2").unwrap().unwrap();
+    assert_eq!(evt2.scale(), None);
+    assert_eq!(evt2.unit(), None);
+    assert!(!evt2.per_pkg());
+    assert!(evt2.snapshot());
+
+    assert!(pmu.event("missing").unwrap().is_none());
+}
+
+#[test]
+fn pmu_builder() {
+    let mut builder = dyn_pmu().builder().unwrap();
+    builder.event("evt2").unwrap();
+    assert_eq!(builder.scale().unwrap(), None);
+    builder.build().unwrap();
+}
+
+#[test]
+fn missing_pmu() {
+    let error = Pmu::new(Path::new(DATA).join("missing-pmu")).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn system_pmus() {
+    let pmus = Pmu::all().unwrap();
+
+    for pmu in &pmus {
+        pmu.ty().unwrap();
+        pmu.formats().unwrap();
+        pmu.events().unwrap();
+    }
+
+    // The software PMU is always present.
+    assert!(pmus.iter().any(|pmu| pmu.name() == "software"));
+    assert_eq!(Pmu::new("software").unwrap().ty().unwrap(), 1);
+}
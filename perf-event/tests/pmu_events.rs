@@ -0,0 +1,228 @@
+use std::io;
+use std::path::Path;
+
+use perf_event::events::Event;
+use perf_event::metrics::{PmuEvents, ResolvedEvent};
+use perf_event_open_sys::bindings::perf_event_attr;
+
+use crate::common::TempDir;
+
+mod common;
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+
+fn load() -> PmuEvents {
+    let mut events = PmuEvents::load(Path::new(DATA).join("pmu-events")).unwrap();
+    events.pmu(Path::new(DATA).join("cpu-pmu"));
+    events
+}
+
+fn attrs(event: ResolvedEvent) -> perf_event_attr {
+    let mut attrs = perf_event_attr::default();
+    event.update_attrs(&mut attrs);
+    attrs
+}
+
+#[test]
+fn load_definitions() {
+    let events = load();
+
+    assert_eq!(events.events().count(), 8);
+    assert_eq!(events.metrics().count(), 7);
+
+    let event = events.find_event("uops_issued.stall_cycles").unwrap();
+    assert_eq!(event.name(), "UOPS_ISSUED.STALL_CYCLES");
+    assert_eq!(event.get("CounterMask"), Some("1"));
+    assert!(event.description().unwrap().starts_with("Cycles when"));
+
+    let metric = events.find_metric("cpi").unwrap();
+    assert_eq!(metric.expr(), "1 / IPC");
+    assert_eq!(
+        metric.groups().collect::<Vec<_>>(),
+        ["Pipeline", "Mem", "Summary"]
+    );
+}
+
+#[test]
+fn resolve_events() {
+    let events = load();
+
+    let attrs_for = |name| attrs(events.resolve_event(name).unwrap());
+
+    let inst_retired = attrs_for("INST_RETIRED.ANY");
+    assert_eq!(inst_retired.type_, 4);
+    assert_eq!(inst_retired.config, 0xC0);
+
+    // AnyThread is 0 so it is fine that the PMU does not support it.
+    let stalls = attrs_for("UOPS_ISSUED.STALL_CYCLES");
+    assert_eq!(stalls.config, 0x0E | 0x1 << 8 | 1 << 23 | 1 << 24);
+
+    let offcore = attrs_for("OCR.DEMAND_DATA_RD.ANY_RESPONSE");
+    assert_eq!(offcore.config, 0x1B7);
+    assert_eq!(offcore.config1, 0x10001);
+
+    let l3_miss = attrs_for("cpu@MEM_LOAD_RETIRED.L3_MISS,cmask=1@");
+    assert_eq!(l3_miss.config, 0xD1 | 0x20 << 8 | 1 << 24);
+
+    // Events from sysfs work too.
+    assert_eq!(attrs_for("slots").config, 0x400);
+
+    let error = |name| events.resolve_event(name).unwrap_err().kind();
+    assert_eq!(error("UNC_M_CAS_COUNT.RD"), io::ErrorKind::Unsupported);
+    assert_eq!(error("INST_RETIRED.ANY:k"), io::ErrorKind::Unsupported);
+    assert_eq!(error("NOT_AN_EVENT"), io::ErrorKind::NotFound);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn resolve_without_pmu() {
+    let mut events = load();
+    events.pmu(Path::new(DATA).join("missing-pmu"));
+
+    match events.resolve_event("UOPS_ISSUED.STALL_CYCLES").unwrap() {
+        ResolvedEvent::Raw(raw) => assert_eq!(raw.config, 0x0E | 0x1 << 8 | 1 << 23 | 1 << 24),
+        event => panic!("expected a raw event, got {:?}", event),
+    }
+}
+
+#[test]
+fn build_metrics() {
+    let mut events = load();
+    events.literal("#smt_on", 1.0);
+
+    let values = |name: &str| match name {
+        "INST_RETIRED.ANY" => Some(300.0),
+        "CPU_CLK_UNHALTED.THREAD" => Some(100.0),
+        "BR_INST_RETIRED.ALL_BRANCHES" => Some(50.0),
+        "BR_MISP_RETIRED.ALL_BRANCHES" => Some(5.0),
+        "UOPS_ISSUED.STALL_CYCLES" => Some(40.0),
+        "#slots" => Some(4.0),
+        "#smt_on" => Some(1.0),
+        _ => None,
+    };
+
+    // References to other metrics are inlined.
+    let cpi = events.metric("CPI").unwrap();
+    assert_eq!(
+        cpi.expr().to_string(),
+        "1 / (INST_RETIRED.ANY / CPU_CLK_UNHALTED.THREAD)"
+    );
+    assert_eq!(
+        cpi.events().collect::<Vec<_>>(),
+        ["CPU_CLK_UNHALTED.THREAD", "INST_RETIRED.ANY"]
+    );
+    assert_eq!(cpi.unit(), "");
+
+    let branch = events.metric("branch_misprediction_ratio").unwrap();
+    assert_eq!(branch.unit(), "%");
+    assert_eq!(branch.expr().eval(values), Some(10.0));
+
+    let stalls = events.metric("tma_issue_stalls").unwrap();
+    let mut constants = stalls.constants().collect::<Vec<_>>();
+    constants.sort_by(|a, b| a.0.cmp(b.0));
+    assert_eq!(constants, [("#slots", 4.0), ("#smt_on", 1.0)]);
+    assert_eq!(stalls.expr().eval(values), Some(10.0));
+
+    let l3 = events.metric("L3_Miss_Cycles").unwrap();
+    assert_eq!(l3.unit(), "per_instr");
+
+    let error = |name| events.metric(name).unwrap_err().kind();
+    assert_eq!(error("DRAM_Read_BW"), io::ErrorKind::Unsupported);
+    assert_eq!(error("Insts_Per_TSC"), io::ErrorKind::Unsupported);
+    assert_eq!(error("Not_A_Metric"), io::ErrorKind::NotFound);
+
+    events.literal("#system_tsc_freq", 1e9);
+    assert!(events.metric("Insts_Per_TSC").is_ok());
+}
+
+#[test]
+fn metric_groups() {
+    let events = load();
+
+    let summary = events.metric_group("summary").unwrap();
+    let names: Vec<_> = summary.iter().map(|metric| metric.name()).collect();
+    assert_eq!(names, ["IPC", "CPI"]);
+
+    let error = events.metric_group("NotAGroup").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn metric_group_skips_unsupported() {
+    let mut events = load();
+    events
+        .add_json(
+            r#"[
+                {
+                    "MetricExpr": "INST_RETIRED.ANY / duration_time",
+                    "MetricGroup": "Mixed",
+                    "MetricName": "Insts_Per_Second"
+                },
+                {
+                    "MetricExpr": "INST_RETIRED.ANY:k / INST_RETIRED.ANY",
+                    "MetricGroup": "Mixed",
+                    "MetricName": "Kernel_Fraction"
+                },
+                {
+                    "MetricExpr": "INST_RETIRED.ANY / #num_packages",
+                    "MetricGroup": "Mixed",
+                    "MetricName": "Insts_Per_Package"
+                },
+                {
+                    "MetricExpr": "CPU_CLK_UNHALTED.THREAD / INST_RETIRED.ANY",
+                    "MetricGroup": "Mixed",
+                    "MetricName": "Cycles_Per_Inst"
+                }
+            ]"#,
+        )
+        .unwrap();
+
+    let mixed = events.metric_group("Mixed").unwrap();
+    let names: Vec<_> = mixed.iter().map(|metric| metric.name()).collect();
+    assert_eq!(names, ["Cycles_Per_Inst"]);
+
+    let (_, skipped) = events.metric_group_with_skipped("Mixed").unwrap();
+    let skipped: Vec<_> = skipped
+        .iter()
+        .map(|(name, error)| (name.as_str(), error.kind()))
+        .collect();
+    assert_eq!(
+        skipped,
+        [
+            ("Insts_Per_Second", io::ErrorKind::NotFound),
+            ("Kernel_Fraction", io::ErrorKind::Unsupported),
+            ("Insts_Per_Package", io::ErrorKind::Unsupported),
+        ]
+    );
+
+    // A group is only an error if none of its metrics can be built.
+    events
+        .add_json(
+            r#"[
+                {
+                    "MetricExpr": "UNC_M_CAS_COUNT.RD",
+                    "MetricGroup": "Dram",
+                    "MetricName": "DRAM_Reads"
+                }
+            ]"#,
+        )
+        .unwrap();
+    let error = events.metric_group("Dram").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn symlink_cycle() {
+    let temp = TempDir::new("cycle");
+    let dir = temp.path();
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(
+        dir.join("events.json"),
+        r#"[{"EventName": "A", "EventCode": "0x1"}]"#,
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(dir, dir.join("sub/loop")).unwrap();
+
+    let events = PmuEvents::load(dir).unwrap();
+    assert_eq!(events.events().count(), 1);
+}
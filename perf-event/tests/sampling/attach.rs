@@ -0,0 +1,57 @@
+use perf_event::events::Software;
+use perf_event::{Builder, SampleFlag};
+use perf_event_open_sys::bindings;
+
+use super::spin;
+
+#[test]
+fn attached_counter_records_are_identified() {
+    let mut builder = Builder::new(Software::CPU_CLOCK);
+    builder
+        .sample_period(100_000)
+        .sample(SampleFlag::IDENTIFIER | SampleFlag::TIME)
+        .sample_id_all(true);
+
+    let mut sampler = builder
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to build sampler");
+    let other = builder
+        .event(Software::TASK_CLOCK)
+        .build()
+        .expect("Failed to build attached counter");
+    let other_id = other.id();
+
+    sampler.attach(other).expect("Failed to attach counter");
+    assert_eq!(sampler.attached().len(), 1);
+
+    sampler.enable().expect("Failed to enable sampler");
+    sampler.attached_mut()[0]
+        .enable()
+        .expect("Failed to enable attached counter");
+    std::hint::black_box(spin(20_000_000));
+    sampler.disable().expect("Failed to disable sampler");
+
+    let own_id = sampler.id();
+    let mut own = 0;
+    let mut attached = 0;
+    while let Some(record) = sampler.next_record() {
+        if record.ty() != bindings::PERF_RECORD_SAMPLE {
+            continue;
+        }
+
+        let counter = record.counter().expect("Record had no matching counter");
+        let sample_id = record.parse_sample_id().expect("Failed to parse sample id");
+        assert_eq!(sample_id.id(), Some(counter.id()));
+
+        match counter.id() {
+            id if id == own_id => own += 1,
+            id if id == other_id => attached += 1,
+            id => panic!("unexpected counter id {}", id),
+        }
+    }
+
+    assert_ne!(own, 0);
+    assert_ne!(attached, 0);
+}
@@ -0,0 +1,19 @@
+use perf_event::events::Software;
+use perf_event::Builder;
+
+#[test]
+fn aux_unsupported_by_software_events() {
+    let mut sampler = Builder::new(Software::DUMMY)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+
+    // Software events don't have an AUX area so the kernel should reject the
+    // mapping instead of us crashing.
+    assert!(sampler.map_aux(4096).is_err());
+    assert!(sampler.map_aux_overwrite(4096).is_err());
+
+    // The ring buffer should still be usable afterwards.
+    assert!(sampler.next_record().is_none());
+}
@@ -0,0 +1,72 @@
+use std::io;
+use std::time::{Duration, SystemTime};
+
+use perf_event::data::Record;
+use perf_event::events::Software;
+use perf_event::{Builder, Clock, SampleFlag, Sampler};
+
+use super::spin;
+
+fn sample_times(sampler: &mut Sampler) -> Vec<u64> {
+    let mut times = Vec::new();
+    while let Some(record) = sampler.next_record() {
+        if let Ok(Record::Sample(sample)) = record.parse_record() {
+            times.extend(sample.time());
+        }
+    }
+    times
+}
+
+fn assert_recent(clock: &perf_event::PerfClock, times: &[u64]) {
+    assert!(!times.is_empty());
+
+    let now = SystemTime::now();
+    for &time in times {
+        let time = clock.to_system_time(time).unwrap();
+        let age = now.duration_since(time).unwrap_or_default();
+        assert!(age < Duration::from_secs(10), "sample was {:?} old", age);
+    }
+}
+
+#[test]
+fn clockid_timestamps() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleFlag::TIME)
+        .clockid(Clock::MONOTONIC)
+        .enabled(true)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().unwrap();
+
+    let clock = sampler.perf_clock().unwrap();
+    assert_eq!(clock.clock(), Some(Clock::MONOTONIC));
+    assert_eq!(clock.tsc_to_perf_time(0), None);
+    assert_recent(&clock, &sample_times(&mut sampler));
+}
+
+#[test]
+fn perf_clock_timestamps() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleFlag::TIME)
+        .enabled(true)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().unwrap();
+
+    let clock = match sampler.perf_clock() {
+        Ok(clock) => clock,
+        // Not all kernels and architectures export the required time fields.
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => return,
+        Err(e) => panic!("failed to create perf clock: {}", e),
+    };
+    assert_eq!(clock.clock(), None);
+    assert_recent(&clock, &sample_times(&mut sampler));
+}
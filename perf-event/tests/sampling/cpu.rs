@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use perf_event::events::Software;
+use perf_event::{Builder, CpuSampler};
+
+use super::spin;
+
+#[test]
+fn records_are_time_ordered() {
+    let mut builder = Builder::new(Software::CPU_CLOCK);
+    builder.sample_period(100_000);
+
+    let mut sampler = CpuSampler::new(&builder, 8192).expect("Failed to build sampler");
+    sampler.reorder_window(Duration::from_millis(1));
+
+    sampler.enable().expect("Failed to enable sampler");
+    std::hint::black_box(spin(20_000_000));
+    sampler.disable().expect("Failed to disable sampler");
+
+    let mut count = 0;
+    let mut last = 0;
+    while let Some(record) = sampler.flush_next() {
+        let time = record
+            .parse_sample_id()
+            .expect("Failed to parse sample id")
+            .time()
+            .expect("Record had no timestamp");
+
+        assert!(
+            time >= last,
+            "records were out of order: {} < {}",
+            time,
+            last
+        );
+        last = time;
+        count += 1;
+    }
+
+    assert_ne!(count, 0);
+}
@@ -0,0 +1,60 @@
+use perf_event::data::Record;
+use perf_event::events::Software;
+use perf_event::{Builder, SampleFlag};
+
+use super::spin;
+
+#[test]
+fn snapshot_returns_most_recent_records() {
+    let mut recorder = Builder::new(Software::CPU_CLOCK)
+        .sample_period(10_000)
+        .sample(SampleFlag::TIME)
+        .write_backward(true)
+        .build()
+        .expect("Failed to build counter")
+        .flight_recorder(4096)
+        .expect("Failed to create flight recorder");
+
+    recorder.as_counter_mut().enable().unwrap();
+    std::hint::black_box(spin(5_000_000));
+    recorder.as_counter_mut().disable().unwrap();
+
+    let all = recorder.snapshot(usize::MAX).unwrap();
+    let recent = recorder.snapshot(5).unwrap();
+
+    // A page can hold far fewer samples than were generated, so old records
+    // should have been overwritten.
+    assert!(all.len() <= 4096 / 16);
+    assert!(all.len() > 5);
+    assert_eq!(recent.len(), 5);
+
+    let times: Vec<u64> = all
+        .iter()
+        .map(|record| match record.parse_record().unwrap() {
+            Record::Sample(sample) => sample.time().unwrap(),
+            other => panic!("unexpected record: {:?}", other),
+        })
+        .collect();
+
+    assert!(
+        times.windows(2).all(|w| w[0] <= w[1]),
+        "records were out of order: {:?}",
+        times
+    );
+
+    let recent_times: Vec<u64> = recent
+        .iter()
+        .map(|record| record.parse_sample_id().unwrap().time().unwrap())
+        .collect();
+    assert_eq!(recent_times, times[times.len() - 5..]);
+}
+
+#[test]
+fn requires_write_backward() {
+    let counter = Builder::new(Software::DUMMY)
+        .build()
+        .expect("Failed to build counter");
+
+    let error = counter.flight_recorder(4096).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
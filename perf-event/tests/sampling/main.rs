@@ -1,6 +1,24 @@
 use std::fmt;
 
+mod attach;
+mod aux;
+mod clock;
+mod cpu;
+mod flight_recorder;
 mod mmap;
+mod pause;
+mod perf_data;
+mod period;
+
+/// Burn CPU time so that samplers on the CPU clock have something to sample.
+#[inline(never)]
+fn spin(iterations: u64) -> u64 {
+    let mut acc = 0u64;
+    for i in 0..iterations {
+        acc = acc.wrapping_add(std::hint::black_box(i) * i);
+    }
+    acc
+}
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 struct Hex<T>(T);
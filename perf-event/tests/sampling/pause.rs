@@ -0,0 +1,54 @@
+use perf_event::events::Software;
+use perf_event::Builder;
+
+use super::spin;
+
+#[test]
+fn paused_output_writes_no_samples() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(10_000)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+
+    sampler.pause_output().expect("Failed to pause output");
+    sampler.enable().expect("Failed to enable sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().expect("Failed to disable sampler");
+
+    // The counter keeps counting while output is paused.
+    assert_ne!(sampler.read().unwrap(), 0);
+    assert!(sampler.next_record().is_none());
+
+    sampler.resume_output().expect("Failed to resume output");
+    sampler.enable().expect("Failed to enable sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().expect("Failed to disable sampler");
+
+    assert!(sampler.next_record().is_some());
+}
+
+#[test]
+fn guard_resumes_output() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(10_000)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+
+    {
+        let mut paused = sampler.pause().expect("Failed to pause output");
+        paused.enable().expect("Failed to enable sampler");
+        std::hint::black_box(spin(5_000_000));
+        paused.disable().expect("Failed to disable sampler");
+        assert!(paused.next_record().is_none());
+    }
+
+    sampler.enable().expect("Failed to enable sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().expect("Failed to disable sampler");
+
+    assert!(sampler.next_record().is_some());
+}
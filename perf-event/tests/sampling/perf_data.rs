@@ -0,0 +1,115 @@
+use std::convert::TryInto;
+use std::io::Cursor;
+
+use perf_event::data::Record;
+use perf_event::events::Software;
+use perf_event::{Builder, PerfDataReader, PerfDataWriter, SampleFlag};
+
+use super::spin;
+
+fn read_u64(data: &[u8], offset: u64) -> u64 {
+    let offset = offset as usize;
+    u64::from_ne_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[test]
+fn write_sampler_records() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleFlag::IP | SampleFlag::TID | SampleFlag::TIME)
+        .enabled(true)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().unwrap();
+
+    let mut writer = PerfDataWriter::new(Cursor::new(Vec::new())).unwrap();
+    writer.add_counter(&sampler);
+    writer.detect_system_info().unwrap();
+
+    let mut records = 0;
+    let mut bytes = 0;
+    while let Some(record) = sampler.next_record() {
+        writer.write_record(&record).unwrap();
+        records += 1;
+        bytes += record.len() as u64 + 8;
+    }
+    assert!(records > 0);
+
+    let data = writer.finish().unwrap().into_inner();
+
+    assert_eq!(read_u64(&data, 0), u64::from_le_bytes(*b"PERFILE2"));
+    assert_eq!(read_u64(&data, 8), 104);
+
+    // Attr section
+    let attr_size = read_u64(&data, 16);
+    let attrs_offset = read_u64(&data, 24);
+    assert_eq!(read_u64(&data, 32), attr_size);
+
+    let ids_offset = read_u64(&data, attrs_offset + attr_size - 16);
+    let ids_size = read_u64(&data, attrs_offset + attr_size - 8);
+    assert_eq!(ids_size, 8);
+    assert_eq!(read_u64(&data, ids_offset), sampler.id());
+
+    // Data section
+    assert_eq!(read_u64(&data, 40), 104);
+    assert_eq!(read_u64(&data, 48), bytes);
+
+    // Features: hostname, osrelease, nrcpus, and cmdline.
+    let features = read_u64(&data, 72);
+    assert_eq!(features, (1 << 3) | (1 << 4) | (1 << 7) | (1 << 11));
+
+    let table = 104 + bytes;
+    let hostname_offset = read_u64(&data, table);
+    let hostname_len =
+        u32::from_ne_bytes(data[hostname_offset as usize..][..4].try_into().unwrap());
+    assert_eq!(hostname_len % 64, 0);
+}
+
+#[test]
+fn round_trip() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleFlag::IP | SampleFlag::TID | SampleFlag::TIME)
+        .enabled(true)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+    std::hint::black_box(spin(5_000_000));
+    sampler.disable().unwrap();
+
+    let mut writer = PerfDataWriter::new(Cursor::new(Vec::new())).unwrap();
+    writer.add_counter(&sampler);
+    writer.detect_system_info().unwrap();
+
+    let mut expected = Vec::new();
+    while let Some(record) = sampler.next_record() {
+        writer.write_record(&record).unwrap();
+        expected.push((record.ty(), record.to_vec()));
+    }
+
+    let file = writer.finish().unwrap();
+    let mut reader = PerfDataReader::new(file).unwrap();
+
+    assert_eq!(reader.events().len(), 1);
+    assert_eq!(reader.events()[0].ids(), [sampler.id()]);
+    assert_eq!(
+        reader.events()[0].config().sample_type(),
+        sampler.config().sample_type()
+    );
+
+    let mut actual = Vec::new();
+    for record in &mut reader {
+        let record = record.unwrap();
+        if let Ok(Record::Sample(sample)) = record.parse_record() {
+            assert!(sample.ip().is_some());
+            assert!(sample.time().is_some());
+        }
+        actual.push((record.ty(), record.data().to_vec()));
+    }
+
+    assert_eq!(actual, expected);
+}
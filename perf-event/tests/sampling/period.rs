@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use perf_event::events::Software;
+use perf_event::{AdaptivePeriod, Builder};
+
+use super::spin;
+
+#[test]
+fn refresh_disables_after_overflows() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(10_000)
+        .enabled(false)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+
+    sampler.refresh(2).expect("Failed to refresh counter");
+    for _ in 0..10 {
+        std::hint::black_box(spin(5_000_000));
+    }
+
+    let mut samples = 0;
+    while let Some(_record) = sampler.next_record() {
+        samples += 1;
+    }
+
+    // The counter gets disabled after the second overflow so we should only see
+    // two sample records.
+    assert_eq!(samples, 2);
+}
+
+#[test]
+fn adapt_period_changes_period() {
+    let mut sampler = Builder::new(Software::CPU_CLOCK)
+        .sample_period(1_000)
+        .build()
+        .expect("Failed to build counter")
+        .sampled(8192)
+        .expect("Failed to create sampler");
+    let mut period = AdaptivePeriod::new(10.0);
+
+    sampler.enable().expect("Failed to enable sampler");
+    assert_eq!(sampler.adapt_period(&mut period).unwrap(), None);
+
+    std::hint::black_box(spin(5_000_000));
+    std::thread::sleep(Duration::from_millis(10));
+
+    let new = sampler
+        .adapt_period(&mut period)
+        .unwrap()
+        .expect("No new period was computed");
+
+    // CPU_CLOCK counts nanoseconds so at 10 records/sec the period should be
+    // well above the initial 1us.
+    assert!(new > 1_000, "period was {}", new);
+
+    sampler.set_period(50_000).expect("Failed to set period");
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::time::Duration;
+
+use perf_event::events::{Tracepoint, TracepointFilter};
+use perf_event::{Builder, Counter};
+
+const SCHED_SWITCH: &str = "/sys/kernel/debug/tracing/events/sched/sched_switch";
+
+/// Open a counter for `sched:sched_switch` on the current thread.
+///
+/// Returns `None` if tracefs is not available.
+fn sched_switch() -> Option<Counter> {
+    if !Path::new(SCHED_SWITCH).exists() {
+        return None;
+    }
+
+    let tracepoint = Tracepoint::with_name("sched/sched_switch").unwrap();
+    Some(Builder::new(tracepoint).observe_self().build().unwrap())
+}
+
+/// Count the context switches of the current thread while sleeping a few
+/// times.
+fn count_switches(counter: &mut Counter) -> u64 {
+    counter.reset().unwrap();
+    counter.enable().unwrap();
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    counter.disable().unwrap();
+    counter.read().unwrap()
+}
+
+#[test]
+fn filter_sched_switch() {
+    let mut counter = match sched_switch() {
+        Some(counter) => counter,
+        None => return,
+    };
+
+    let tid = unsafe { libc::gettid() };
+    let matching =
+        TracepointFilter::new("sched/sched_switch", &format!("prev_pid == {tid}")).unwrap();
+    counter.set_filter(&matching).unwrap();
+    assert!(count_switches(&mut counter) >= 10);
+
+    // The thread being switched out is always this one, never the idle task.
+    let never = TracepointFilter::new("sched/sched_switch", "prev_pid == 0").unwrap();
+    counter.set_filter(&never).unwrap();
+    assert_eq!(count_switches(&mut counter), 0);
+}
+
+#[test]
+fn invalid_filter() {
+    let mut counter = match sched_switch() {
+        Some(counter) => counter,
+        None => return,
+    };
+
+    let filter = TracepointFilter::new_unchecked("no_such_field == 1");
+    let error = counter.set_filter(&filter).unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(libc::EINVAL));
+}
@@ -0,0 +1,112 @@
+#![cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+
+use std::fs;
+use std::path::Path;
+
+use perf_event::pmu::Pmu;
+use perf_event::UncoreCounters;
+
+use crate::common::TempDir;
+
+mod common;
+
+const MSR: &str = "/sys/bus/event_source/devices/msr";
+
+/// Create a copy of the `msr` PMU with a custom cpumask and event properties.
+///
+/// Returns `None` if the `msr` PMU is not available.
+fn fake_pmu(name: &str, cpumask: &str, properties: &[&str]) -> Option<TempDir> {
+    if !Path::new(MSR).exists() {
+        return None;
+    }
+
+    let temp = TempDir::new(name);
+    let dir = temp.path();
+    fs::create_dir_all(dir.join("format")).unwrap();
+    fs::create_dir_all(dir.join("events")).unwrap();
+
+    fs::copy(Path::new(MSR).join("type"), dir.join("type")).unwrap();
+    fs::write(dir.join("cpumask"), cpumask).unwrap();
+    fs::write(dir.join("format/event"), "config:0-63\n").unwrap();
+    fs::write(dir.join("events/tsc"), "event=0x00\n").unwrap();
+    for property in properties {
+        fs::write(dir.join("events").join(format!("tsc.{}", property)), "1\n").unwrap();
+    }
+
+    Some(temp)
+}
+
+/// Read the sysfs list of online CPUs.
+fn online_cpus() -> String {
+    fs::read_to_string("/sys/devices/system/cpu/online").unwrap()
+}
+
+/// The CPUs in the cpumask of `pmu`.
+fn cpumask(pmu: &Path) -> Vec<usize> {
+    Pmu::new(pmu).unwrap().cpumask().unwrap().unwrap()
+}
+
+#[test]
+fn count_msr() {
+    if !Path::new(MSR).exists() {
+        return;
+    }
+
+    let mut counters = UncoreCounters::new("msr", "tsc").unwrap();
+    assert!(!counters.is_empty());
+    assert!(!counters.is_snapshot());
+
+    counters.enable().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    counters.disable().unwrap();
+
+    let data = counters.read_full().unwrap();
+    assert!(data.value() > 0.0);
+    assert_eq!(data.iter().count(), counters.len());
+}
+
+#[test]
+fn uses_cpumask() {
+    let pmu = match fake_pmu("cpumask", &online_cpus(), &[]) {
+        Some(pmu) => pmu,
+        None => return,
+    };
+    let cpus = cpumask(pmu.path());
+
+    let counters = UncoreCounters::new(pmu.path(), "tsc").unwrap();
+    assert_eq!(counters.len(), cpus.len());
+    for (cpu, (opened, _)) in cpus.iter().zip(counters.iter()) {
+        assert_eq!(*cpu, opened);
+    }
+}
+
+#[test]
+fn per_pkg_and_snapshot() {
+    let pmu = match fake_pmu("per-pkg", &online_cpus(), &["per-pkg", "snapshot"]) {
+        Some(pmu) => pmu,
+        None => return,
+    };
+    let cpus = cpumask(pmu.path());
+
+    let mut counters = UncoreCounters::with_builder(
+        &perf_event::Group::builder(),
+        &Pmu::new(pmu.path()).unwrap(),
+        "tsc",
+    )
+    .unwrap();
+
+    // Only one counter is opened for each package and die.
+    let mut locations: Vec<_> = counters
+        .iter()
+        .map(|(cpu, _)| (counters.package(cpu), counters.die(cpu)))
+        .collect();
+    assert!(!locations.is_empty() && locations.len() <= cpus.len());
+    locations.sort();
+    locations.dedup();
+    assert_eq!(locations.len(), counters.len());
+    assert!(counters.is_snapshot());
+    counters.reset().unwrap();
+
+    let missing = UncoreCounters::new(pmu.path(), "missing").unwrap_err();
+    assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+}
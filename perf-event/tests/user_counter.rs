@@ -0,0 +1,67 @@
+use perf_event::events::{Breakpoint, Software};
+use perf_event::{Builder, Group, UserReadError};
+
+use crate::common::use_data;
+
+mod common;
+
+#[test]
+fn read_falls_back_to_syscall() {
+    let data = b"TEST DATA".to_vec();
+
+    let mut counter = Builder::new(Breakpoint::read_write(data.as_ptr() as usize as _, 1))
+        .observe_self()
+        .build()
+        .expect("Unable to build performance counter")
+        .user_counter()
+        .expect("Unable to map the counter metadata page");
+    counter.as_counter_mut().enable().unwrap();
+
+    for _ in 0..1000 {
+        use_data(&data);
+    }
+
+    counter.as_counter_mut().disable().unwrap();
+
+    // Breakpoints are not hardware counters so they can't be read from
+    // userspace.
+    assert_eq!(
+        counter.read_user().try_count(),
+        Err(UserReadError::RdpmcNotAllowed)
+    );
+    assert_eq!(counter.read().unwrap().count(), 1000);
+}
+
+#[test]
+fn group_read() {
+    let mut group = Group::new().unwrap();
+    let counter = group.add(&Builder::new(Software::TASK_CLOCK)).unwrap();
+    let id = counter.id();
+
+    let mut group = group.user_group(vec![counter]).unwrap();
+    group.enable().unwrap();
+
+    let mut sum = 0u64;
+    for i in 0..1_000_000u64 {
+        sum = sum.wrapping_add(std::hint::black_box(i));
+    }
+    std::hint::black_box(sum);
+
+    group.disable().unwrap();
+
+    let counts = group.read().unwrap();
+    assert_ne!(counts[group.members()[0].as_counter()], 0);
+    assert_eq!(
+        counts.iter().map(|entry| entry.id()).collect::<Vec<_>>(),
+        [id]
+    );
+}
+
+#[test]
+fn group_rejects_non_members() {
+    let group = Group::new().unwrap();
+    let counter = Builder::new(Software::DUMMY).build().unwrap();
+
+    let error = group.user_group(vec![counter]).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}